@@ -12,10 +12,12 @@ use defaults::Defaults;
 
 pub(crate) use commandline::Operation;
 pub(crate) use file::{
-    ConfigFile as Configuration, Media as MediaConfiguration, OpenTelemetry, Repo, Sled, Tracing,
+    ConfigFile as Configuration, Media as MediaConfiguration, OpenTelemetry, Postgres, Repo, Sled,
+    Tracing,
 };
 pub(crate) use primitives::{
-    AudioCodec, Filesystem, ImageFormat, LogFormat, ObjectStorage, Store, VideoCodec,
+    AnimationFormat, AudioCodec, ChromaSubsampling, Filesystem, ImageFormat, LogFormat,
+    ObjectStorage, Store, TargetFormat, VideoCodec,
 };
 
 /// Source for pict-rs configuration when embedding as a library
@@ -79,6 +81,8 @@ pub(crate) fn configure_without_clap<P: AsRef<Path>, T: serde::Serialize, Q: AsR
 
     let config: Configuration = built.try_deserialize()?;
 
+    validate_presets(&config)?;
+
     if let Some(save_to) = save_to {
         let output = toml::to_string_pretty(&config)?;
         std::fs::write(save_to, output)?;
@@ -114,6 +118,8 @@ pub(crate) fn configure() -> color_eyre::Result<(Configuration, Operation)> {
 
     let config: Configuration = built.try_deserialize()?;
 
+    validate_presets(&config)?;
+
     if let Some(save_to) = save_to {
         let output = toml::to_string_pretty(&config)?;
         std::fs::write(save_to, output)?;
@@ -121,3 +127,13 @@ pub(crate) fn configure() -> color_eyre::Result<(Configuration, Operation)> {
 
     Ok((config, operation))
 }
+
+fn validate_presets(config: &Configuration) -> color_eyre::Result<()> {
+    for (name, preset) in &config.media.presets {
+        if let Err(e) = preset.validate(&config.media.encoding) {
+            return Err(color_eyre::eyre::eyre!("Invalid preset {name}: {e}"));
+        }
+    }
+
+    Ok(())
+}
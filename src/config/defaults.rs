@@ -1,5 +1,5 @@
 use crate::{
-    config::primitives::{LogFormat, Targets, VideoCodec},
+    config::primitives::{ImageFormat, LogFormat, Targets, VideoCodec},
     serde_str::Serde,
 };
 use std::{net::SocketAddr, path::PathBuf};
@@ -67,12 +67,18 @@ struct MediaDefaults {
     max_file_size: usize,
     max_frame_count: usize,
     gif: GifDefaults,
+    animation: AnimationDefaults,
+    storyboard: StoryboardDefaults,
+    encoding: EncodingDefaults,
     enable_silent_video: bool,
     enable_full_video: bool,
     video_codec: VideoCodec,
+    representative_frame: bool,
     filters: Vec<String>,
+    auto_formats: Vec<ImageFormat>,
     skip_validate_imports: bool,
     cache_duration: i64,
+    presets_only: bool,
 }
 
 #[derive(Clone, Debug, serde::Serialize)]
@@ -84,6 +90,29 @@ struct GifDefaults {
     max_frame_count: usize,
 }
 
+#[derive(Clone, Debug, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+struct AnimationDefaults {
+    max_frame_rate: u32,
+    max_duration: u32,
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+struct StoryboardDefaults {
+    max_frames: u32,
+    max_width: u32,
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+struct EncodingDefaults {
+    min_quality: u8,
+    max_quality: u8,
+    lossless: bool,
+    progressive: bool,
+}
+
 #[derive(Clone, Debug, serde::Serialize)]
 #[serde(rename_all = "snake_case")]
 #[serde(tag = "type")]
@@ -165,19 +194,35 @@ impl Default for MediaDefaults {
             max_file_size: 40,
             max_frame_count: 900,
             gif: Default::default(),
+            animation: Default::default(),
+            storyboard: Default::default(),
+            encoding: Default::default(),
             enable_silent_video: true,
             enable_full_video: false,
             video_codec: VideoCodec::Vp9,
+            representative_frame: false,
             filters: vec![
+                "autolevel".into(),
                 "blur".into(),
+                "brightness".into(),
+                "contrast".into(),
                 "crop".into(),
+                "flip".into(),
+                "flop".into(),
+                "frame".into(),
+                "grayscale".into(),
                 "identity".into(),
                 "resize".into(),
+                "rotate".into(),
+                "saturation".into(),
+                "sharpen".into(),
                 "thumbnail".into(),
             ],
+            auto_formats: vec![ImageFormat::Webp, ImageFormat::Jpeg],
             skip_validate_imports: false,
             // one week (in hours)
             cache_duration: 24 * 7,
+            presets_only: false,
         }
     }
 }
@@ -193,6 +238,35 @@ impl Default for GifDefaults {
     }
 }
 
+impl Default for AnimationDefaults {
+    fn default() -> Self {
+        AnimationDefaults {
+            max_frame_rate: 15,
+            max_duration: 10,
+        }
+    }
+}
+
+impl Default for StoryboardDefaults {
+    fn default() -> Self {
+        StoryboardDefaults {
+            max_frames: 100,
+            max_width: 320,
+        }
+    }
+}
+
+impl Default for EncodingDefaults {
+    fn default() -> Self {
+        EncodingDefaults {
+            min_quality: 1,
+            max_quality: 100,
+            lossless: false,
+            progressive: false,
+        }
+    }
+}
+
 impl Default for RepoDefaults {
     fn default() -> Self {
         Self::Sled(SledDefaults::default())
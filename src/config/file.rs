@@ -1,9 +1,18 @@
 use crate::{
-    config::primitives::{AudioCodec, ImageFormat, LogFormat, Store, Targets, VideoCodec},
+    config::primitives::{
+        AudioCodec, ChromaSubsampling, ImageFormat, LogFormat, Store, TargetFormat, Targets,
+        VideoCodec,
+    },
+    error::{Error, UploadError},
+    processor::{Lossless, Processor, Progressive, Quality, Subsampling},
     serde_str::Serde,
 };
 use once_cell::sync::OnceCell;
-use std::{collections::BTreeSet, net::SocketAddr, path::PathBuf};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    net::SocketAddr,
+    path::PathBuf,
+};
 use url::Url;
 
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
@@ -27,6 +36,7 @@ pub(crate) struct ConfigFile {
 #[serde(tag = "type")]
 pub(crate) enum Repo {
     Sled(Sled),
+    Postgres(Postgres),
 }
 
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
@@ -38,6 +48,9 @@ pub(crate) struct Server {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) api_key: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) signing_key: Option<String>,
 }
 
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
@@ -48,6 +61,9 @@ pub(crate) struct Tracing {
     pub(crate) console: Console,
 
     pub(crate) opentelemetry: OpenTelemetry,
+
+    #[serde(default)]
+    pub(crate) prometheus: Prometheus,
 }
 
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
@@ -78,6 +94,13 @@ pub(crate) struct Console {
     pub(crate) buffer_capacity: usize,
 }
 
+#[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) struct Prometheus {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) address: Option<SocketAddr>,
+}
+
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
 #[serde(rename_all = "snake_case")]
 pub(crate) struct OldDb {
@@ -102,6 +125,12 @@ pub(crate) struct Media {
 
     pub(crate) gif: Gif,
 
+    pub(crate) animation: Animation,
+
+    pub(crate) storyboard: Storyboard,
+
+    pub(crate) encoding: Encoding,
+
     pub(crate) enable_silent_video: bool,
 
     pub(crate) enable_full_video: bool,
@@ -111,14 +140,59 @@ pub(crate) struct Media {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) audio_codec: Option<AudioCodec>,
 
+    pub(crate) representative_frame: bool,
+
     pub(crate) filters: BTreeSet<String>,
 
+    pub(crate) auto_formats: Vec<ImageFormat>,
+
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) format: Option<ImageFormat>,
 
     pub(crate) skip_validate_imports: bool,
 
     pub(crate) cache_duration: i64,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) variant_max_age: Option<i64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) variant_storage_budget: Option<u64>,
+
+    #[serde(default)]
+    pub(crate) presets_only: bool,
+
+    #[serde(default)]
+    pub(crate) presets: BTreeMap<String, Preset>,
+}
+
+/// A named processing chain, served from `/image/preset/{name}/{alias}`
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub(crate) struct Preset {
+    pub(crate) format: ImageFormat,
+
+    /// Processing steps in the same format as the process endpoint's query
+    pub(crate) steps: String,
+
+    /// Whether to generate this preset for all media as soon as it's ingested
+    #[serde(default)]
+    pub(crate) eager: bool,
+}
+
+impl Preset {
+    pub(crate) fn operations(&self) -> Option<Vec<(String, String)>> {
+        serde_urlencoded::from_str(&self.steps).ok()
+    }
+
+    /// Build the preset's processing chain once, so broken steps are caught before any request
+    pub(crate) fn validate(&self, encoding: &Encoding) -> Result<(), Error> {
+        let operations = self.operations().ok_or(UploadError::ParsePath)?;
+        let ext = TargetFormat::Image(self.format).to_path_segment();
+
+        crate::processor::build_chain(&encoding.apply(operations), &ext, None)?;
+
+        Ok(())
+    }
 }
 
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
@@ -132,6 +206,96 @@ pub(crate) struct Gif {
     pub(crate) max_frame_count: usize,
 }
 
+/// Bounds on animations produced by processing GIFs and videos
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub(crate) struct Animation {
+    pub(crate) max_frame_rate: u32,
+
+    /// In seconds. Anything past this is cut from the animation
+    pub(crate) max_duration: u32,
+}
+
+/// Bounds on storyboard sprites made from GIFs and videos
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub(crate) struct Storyboard {
+    pub(crate) max_frames: u32,
+
+    /// In pixels, for each tile of the sprite
+    pub(crate) max_width: u32,
+}
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub(crate) struct Encoding {
+    pub(crate) min_quality: u8,
+
+    pub(crate) max_quality: u8,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) quality: Option<u8>,
+
+    pub(crate) lossless: bool,
+
+    pub(crate) progressive: bool,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) chroma_subsampling: Option<ChromaSubsampling>,
+}
+
+impl Encoding {
+    /// Clamp requested encoder settings to the allowed range and fill in the defaults.
+    ///
+    /// Encoder settings apply to the output no matter where they appear in the chain, so they're
+    /// moved to the end in a fixed order. This keeps equivalent requests from being stored as
+    /// separate variants.
+    pub(crate) fn apply(&self, operations: Vec<(String, String)>) -> Vec<(String, String)> {
+        let mut quality = self.quality.map(|quality| quality.to_string());
+        let mut lossless = self.lossless.then(|| String::from("true"));
+        let mut progressive = self.progressive.then(|| String::from("true"));
+        let mut subsampling = self
+            .chroma_subsampling
+            .map(|subsampling| subsampling.to_short_str().to_string());
+
+        let mut operations = operations
+            .into_iter()
+            .filter(|(name, value)| {
+                let setting = match name.as_str() {
+                    Quality::NAME => &mut quality,
+                    Lossless::NAME => &mut lossless,
+                    Progressive::NAME => &mut progressive,
+                    Subsampling::NAME => &mut subsampling,
+                    _ => return true,
+                };
+
+                *setting = Some(value.clone());
+                false
+            })
+            .collect::<Vec<_>>();
+
+        if let Some(quality) = quality {
+            // Values that don't parse are left for the processor to reject
+            let quality = quality
+                .parse::<u8>()
+                .map(|q| q.max(self.min_quality).min(self.max_quality).to_string())
+                .unwrap_or(quality);
+
+            operations.push((Quality::NAME.to_string(), quality));
+        }
+
+        // Both are off unless requested, so `false` is the same as leaving them out
+        for (name, value) in [(Lossless::NAME, lossless), (Progressive::NAME, progressive)] {
+            if let Some(value) = value.filter(|value| value != "false") {
+                operations.push((name.to_string(), value));
+            }
+        }
+
+        if let Some(subsampling) = subsampling {
+            operations.push((Subsampling::NAME.to_string(), subsampling));
+        }
+
+        operations
+    }
+}
+
 impl Media {
     pub(crate) fn preprocess_steps(&self) -> Option<&[(String, String)]> {
         static PREPROCESS_STEPS: OnceCell<Vec<(String, String)>> = OnceCell::new();
@@ -151,10 +315,114 @@ impl Media {
     }
 }
 
-#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+/// Configuration for the sled-backed data repository
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize, clap::Parser)]
 #[serde(rename_all = "snake_case")]
 pub(crate) struct Sled {
+    /// The path to the sled database
+    #[arg(short, long)]
     pub(crate) path: PathBuf,
 
+    /// The cache capacity, in bytes, allowed to sled for in-memory operations
+    #[arg(short, long, default_value_t = 1024 * 1024 * 64)]
     pub(crate) cache_capacity: u64,
 }
+
+/// Configuration for the postgres-backed data repository
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize, clap::Parser)]
+#[serde(rename_all = "snake_case")]
+pub(crate) struct Postgres {
+    /// The URL of the postgres database
+    #[arg(short, long)]
+    pub(crate) url: Url,
+}
+
+impl From<Sled> for Repo {
+    fn from(s: Sled) -> Self {
+        Self::Sled(s)
+    }
+}
+
+impl From<Postgres> for Repo {
+    fn from(p: Postgres) -> Self {
+        Self::Postgres(p)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Encoding, Preset};
+    use crate::config::{ChromaSubsampling, ImageFormat};
+
+    fn operations(query: &str) -> Vec<(String, String)> {
+        serde_urlencoded::from_str(query).unwrap()
+    }
+
+    #[test]
+    fn encoding_settings_are_normalized() {
+        let encoding = Encoding {
+            min_quality: 30,
+            max_quality: 90,
+            quality: None,
+            lossless: false,
+            progressive: false,
+            chroma_subsampling: None,
+        };
+
+        assert_eq!(
+            encoding.apply(operations("progressive=true&quality=95&resize=200")),
+            operations("resize=200&quality=90&progressive=true")
+        );
+        assert_eq!(
+            encoding.apply(operations("lossless=false&quality=10")),
+            operations("quality=30")
+        );
+        assert_eq!(
+            encoding.apply(operations("thumbnail=100")),
+            operations("thumbnail=100")
+        );
+    }
+
+    #[test]
+    fn encoding_defaults_can_be_overridden() {
+        let encoding = Encoding {
+            min_quality: 1,
+            max_quality: 100,
+            quality: Some(80),
+            lossless: true,
+            progressive: false,
+            chroma_subsampling: Some(ChromaSubsampling::Yuv420),
+        };
+
+        assert_eq!(
+            encoding.apply(operations("resize=200")),
+            operations("resize=200&quality=80&lossless=true&subsampling=420")
+        );
+        assert_eq!(
+            encoding.apply(operations("lossless=false&subsampling=4:4:4")),
+            operations("quality=80&subsampling=4:4:4")
+        );
+    }
+
+    #[test]
+    fn presets_are_validated() {
+        let encoding = Encoding {
+            min_quality: 1,
+            max_quality: 100,
+            quality: None,
+            lossless: false,
+            progressive: false,
+            chroma_subsampling: None,
+        };
+
+        let preset = |steps: &str| Preset {
+            format: ImageFormat::Webp,
+            steps: steps.to_string(),
+            eager: false,
+        };
+
+        assert!(preset("thumbnail=200&blur=1.5").validate(&encoding).is_ok());
+        assert!(preset("thumbnail=huge").validate(&encoding).is_err());
+        assert!(preset("sparkle=true").validate(&encoding).is_err());
+    }
+}
@@ -43,6 +43,39 @@ pub(crate) enum ImageFormat {
     Jpeg,
     Webp,
     Png,
+    Avif,
+    Jxl,
+}
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    serde::Deserialize,
+    serde::Serialize,
+    ValueEnum,
+)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum AnimationFormat {
+    Gif,
+    Webp,
+    Mp4,
+    Webm,
+}
+
+/// What a process request produces: a still image, or an animation of a GIF or video
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(untagged)]
+pub(crate) enum TargetFormat {
+    // Still images serialize as a bare format, so jobs queued before animations existed still
+    // deserialize
+    Image(ImageFormat),
+    Animation { animation: AnimationFormat },
 }
 
 #[derive(
@@ -87,6 +120,31 @@ pub(crate) enum AudioCodec {
     Vorbis,
 }
 
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    serde::Deserialize,
+    serde::Serialize,
+    ValueEnum,
+)]
+pub(crate) enum ChromaSubsampling {
+    #[serde(rename = "4:2:0")]
+    #[value(name = "4:2:0")]
+    Yuv420,
+    #[serde(rename = "4:2:2")]
+    #[value(name = "4:2:2")]
+    Yuv422,
+    #[serde(rename = "4:4:4")]
+    #[value(name = "4:4:4")]
+    Yuv444,
+}
+
 #[derive(Clone, Debug)]
 pub(crate) struct Targets {
     pub(crate) targets: tracing_subscriber::filter::Targets,
@@ -161,6 +219,8 @@ impl ImageFormat {
             Self::Jpeg => "JPEG",
             Self::Png => "PNG",
             Self::Webp => "WEBP",
+            Self::Avif => "AVIF",
+            Self::Jxl => "JXL",
         }
     }
 
@@ -169,6 +229,37 @@ impl ImageFormat {
             Self::Jpeg => ".jpeg",
             Self::Png => ".png",
             Self::Webp => ".webp",
+            Self::Avif => ".avif",
+            Self::Jxl => ".jxl",
+        }
+    }
+}
+
+impl AnimationFormat {
+    pub(crate) fn as_hint(self) -> ValidInputType {
+        match self {
+            Self::Gif => ValidInputType::Gif,
+            Self::Webp => ValidInputType::Webp,
+            Self::Mp4 => ValidInputType::Mp4,
+            Self::Webm => ValidInputType::Webm,
+        }
+    }
+}
+
+impl TargetFormat {
+    pub(crate) fn as_hint(self) -> ValidInputType {
+        match self {
+            Self::Image(format) => format.as_hint(),
+            Self::Animation { animation } => animation.as_hint(),
+        }
+    }
+
+    /// The last segment of the variant path, which keeps animations apart from stills of the
+    /// same format
+    pub(crate) fn to_path_segment(self) -> String {
+        match self {
+            Self::Image(format) => format.to_string(),
+            Self::Animation { animation } => format!("animated.{animation}"),
         }
     }
 }
@@ -246,6 +337,46 @@ impl FromStr for ImageFormat {
             "jpeg" | "jpg" => Ok(Self::Jpeg),
             "png" => Ok(Self::Png),
             "webp" => Ok(Self::Webp),
+            "avif" => Ok(Self::Avif),
+            "jxl" => Ok(Self::Jxl),
+            other => Err(format!("Invalid variant: {other}")),
+        }
+    }
+}
+
+impl FromStr for AnimationFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "gif" => Ok(Self::Gif),
+            "webp" => Ok(Self::Webp),
+            "mp4" => Ok(Self::Mp4),
+            "webm" => Ok(Self::Webm),
+            other => Err(format!("Invalid variant: {other}")),
+        }
+    }
+}
+
+impl ChromaSubsampling {
+    /// The sampling factor without separators, for use in variant paths
+    pub(crate) const fn to_short_str(self) -> &'static str {
+        match self {
+            Self::Yuv420 => "420",
+            Self::Yuv422 => "422",
+            Self::Yuv444 => "444",
+        }
+    }
+}
+
+impl FromStr for ChromaSubsampling {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "4:2:0" | "420" => Ok(Self::Yuv420),
+            "4:2:2" | "422" => Ok(Self::Yuv422),
+            "4:4:4" | "444" => Ok(Self::Yuv444),
             other => Err(format!("Invalid variant: {other}")),
         }
     }
@@ -273,6 +404,24 @@ impl Display for ImageFormat {
     }
 }
 
+impl Display for AnimationFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.to_possible_value()
+            .expect("no values are skipped")
+            .get_name()
+            .fmt(f)
+    }
+}
+
+impl Display for ChromaSubsampling {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.to_possible_value()
+            .expect("no values are skipped")
+            .get_name()
+            .fmt(f)
+    }
+}
+
 impl Display for LogFormat {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         self.to_possible_value()
@@ -284,7 +433,7 @@ impl Display for LogFormat {
 
 #[cfg(test)]
 mod tests {
-    use super::Targets;
+    use super::{AnimationFormat, ImageFormat, TargetFormat, Targets};
     use crate::serde_str::Serde;
 
     #[test]
@@ -309,4 +458,20 @@ mod tests {
 
         assert_eq!(t.to_string(), "warn,pict_rs=info");
     }
+
+    #[test]
+    fn target_formats_deserialize_from_image_formats() {
+        let still: TargetFormat = serde_json::from_str("\"webp\"").unwrap();
+        assert_eq!(still, TargetFormat::Image(ImageFormat::Webp));
+
+        let animated = TargetFormat::Animation {
+            animation: AnimationFormat::Webp,
+        };
+        let json = serde_json::to_string(&animated).unwrap();
+        assert_eq!(
+            serde_json::from_str::<TargetFormat>(&json).unwrap(),
+            animated
+        );
+        assert_ne!(still.to_path_segment(), animated.to_path_segment());
+    }
 }
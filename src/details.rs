@@ -2,6 +2,7 @@ use crate::{
     error::Error,
     ffmpeg::VideoFormat,
     magick::{video_mp4, video_webm, ValidInputType},
+    placeholder::Placeholder,
     serde_str::Serde,
     store::Store,
 };
@@ -21,6 +22,31 @@ pub(crate) struct Details {
     frames: Option<usize>,
     content_type: Serde<mime::Mime>,
     created_at: MaybeHumanDate,
+    // Details stored before placeholders existed don't have them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    blurhash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    dominant_color: Option<String>,
+    // Neither do details stored before media was probed in depth
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    duration: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    frame_rate: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    video_codec: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    audio_codec: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bitrate: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    has_audio: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    color_space: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    orientation: Option<String>,
+    /// In bytes, so storage use can be summed without asking the store
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    size: Option<u64>,
 }
 
 impl Details {
@@ -39,15 +65,19 @@ impl Details {
         let details = if let Some(details) = details {
             details
         } else {
-            crate::magick::details_bytes(input, Some(hint)).await?
+            crate::magick::details_bytes(input.clone(), Some(hint)).await?
         };
 
-        Ok(Details::now(
-            details.width,
-            details.height,
-            details.mime_type,
-            details.frames,
-        ))
+        let (width, height) = (details.width, details.height);
+        let new_details = Details::now(details).with_size(Some(input.len() as u64));
+
+        // Only videos stored on their own can be thumbnailed for a placeholder
+        let placeholder = match new_details.to_input_format() {
+            Some(VideoFormat::Mp4 | VideoFormat::Webm) => None,
+            _ => ok_or_warn(crate::placeholder::placeholder_bytes(input, width, height).await),
+        };
+
+        Ok(new_details.with_placeholder(placeholder))
     }
 
     pub(crate) async fn from_store<S: Store + 'static>(
@@ -64,32 +94,80 @@ impl Details {
         let details = if let Some(details) = details {
             details
         } else {
-            crate::magick::details_store(store, identifier, expected_format).await?
+            crate::magick::details_store(store.clone(), identifier.clone(), expected_format).await?
         };
 
-        Ok(Details::now(
-            details.width,
-            details.height,
-            details.mime_type,
-            details.frames,
-        ))
+        let (width, height) = (details.width, details.height);
+        let size = store.len(&identifier).await.ok();
+        let new_details = Details::now(details).with_size(size);
+
+        let placeholder = ok_or_warn(
+            crate::placeholder::placeholder_store(
+                &store,
+                &identifier,
+                new_details.to_input_format(),
+                width,
+                height,
+            )
+            .await,
+        );
+
+        Ok(new_details.with_placeholder(placeholder))
     }
 
-    pub(crate) fn now(
-        width: usize,
-        height: usize,
-        content_type: mime::Mime,
-        frames: Option<usize>,
-    ) -> Self {
+    fn now(details: crate::magick::Details) -> Self {
         Details {
-            width,
-            height,
-            frames,
-            content_type: Serde::new(content_type),
+            width: details.width,
+            height: details.height,
+            frames: details.frames,
+            content_type: Serde::new(details.mime_type),
             created_at: MaybeHumanDate::HumanDate(time::OffsetDateTime::now_utc()),
+            blurhash: None,
+            dominant_color: None,
+            duration: details.duration,
+            frame_rate: details.frame_rate,
+            video_codec: details.video_codec,
+            audio_codec: details.audio_codec,
+            bitrate: details.bitrate,
+            has_audio: details.has_audio,
+            color_space: details.color_space,
+            orientation: details.orientation,
+            size: None,
         }
     }
 
+    pub(crate) fn with_size(mut self, size: Option<u64>) -> Self {
+        self.size = size;
+        self
+    }
+
+    pub(crate) fn size(&self) -> Option<u64> {
+        self.size
+    }
+
+    /// Keep the original creation time when media is probed again
+    pub(crate) fn with_created_at(mut self, previous: &Details) -> Self {
+        self.created_at = previous.created_at;
+        self
+    }
+
+    fn with_placeholder(mut self, placeholder: Option<Placeholder>) -> Self {
+        if let Some(Placeholder {
+            blurhash,
+            dominant_color,
+        }) = placeholder
+        {
+            self.blurhash = Some(blurhash);
+            self.dominant_color = Some(dominant_color);
+        }
+
+        self
+    }
+
+    pub(crate) fn dimensions(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
     pub(crate) fn content_type(&self) -> mime::Mime {
         (*self.content_type).clone()
     }
@@ -98,6 +176,13 @@ impl Details {
         self.created_at.into()
     }
 
+    pub(crate) fn created_at(&self) -> time::OffsetDateTime {
+        match self.created_at {
+            MaybeHumanDate::OldDate(old) => old,
+            MaybeHumanDate::HumanDate(human) => human,
+        }
+    }
+
     pub(crate) fn to_input_format(&self) -> Option<VideoFormat> {
         if *self.content_type == mime::IMAGE_GIF {
             return Some(VideoFormat::Gif);
@@ -115,6 +200,17 @@ impl Details {
     }
 }
 
+// Placeholders are a nicety, so failing to make one doesn't fail the details
+fn ok_or_warn(res: Result<Placeholder, Error>) -> Option<Placeholder> {
+    match res {
+        Ok(placeholder) => Some(placeholder),
+        Err(e) => {
+            tracing::warn!("Failed to compute placeholder: {e}");
+            None
+        }
+    }
+}
+
 impl From<MaybeHumanDate> for std::time::SystemTime {
     fn from(this: MaybeHumanDate) -> Self {
         match this {
@@ -9,6 +9,20 @@ impl Error {
     fn kind(&self) -> Option<&UploadError> {
         self.inner.downcast_ref()
     }
+
+    pub(crate) fn name(&self) -> &'static str {
+        self.kind().map(UploadError::name).unwrap_or("unknown")
+    }
+
+    pub(crate) fn is_missing(&self) -> bool {
+        matches!(
+            self.kind(),
+            Some(
+                UploadError::Sled(crate::repo::sled::SledError::Missing)
+                    | UploadError::Postgres(crate::repo::postgres::PostgresError::Missing)
+            )
+        )
+    }
 }
 
 impl std::fmt::Debug for Error {
@@ -48,6 +62,9 @@ pub(crate) enum UploadError {
     #[error("Error in DB")]
     Sled(#[from] crate::repo::sled::SledError),
 
+    #[error("Error in postgres DB")]
+    Postgres(#[from] crate::repo::postgres::PostgresError),
+
     #[error("Error in old sled DB")]
     OldSled(#[from] ::sled::Error),
 
@@ -117,6 +134,30 @@ pub(crate) enum UploadError {
     #[error("Tried to save an image with an already-taken name")]
     DuplicateAlias,
 
+    #[error("Provided hash is not valid hex")]
+    InvalidHash,
+
+    #[error("No perceptual hash was provided or stored for this file")]
+    MissingPerceptualHash,
+
+    #[error("Focal point must be between 0 and 1 on both axes")]
+    InvalidFocalPoint,
+
+    #[error("This file has been blocked")]
+    Blocked,
+
+    #[error("Requested a preset that doesn't exist")]
+    MissingPreset,
+
+    #[error("Media can only be processed with a preset")]
+    PresetsOnly,
+
+    #[error("URL signing is not enabled")]
+    SigningDisabled,
+
+    #[error("Only gifs and videos can be processed into animations or storyboards")]
+    NotAnimated,
+
     #[error("Error in json")]
     Json(#[from] serde_json::Error),
 
@@ -133,6 +174,53 @@ pub(crate) enum UploadError {
     Timeout(#[from] crate::stream::TimeoutError),
 }
 
+impl UploadError {
+    /// A stable name for this kind of error, used to label metrics
+    pub(crate) const fn name(&self) -> &'static str {
+        match self {
+            Self::Upload(_) => "upload",
+            Self::Sled(_) => "sled",
+            Self::Postgres(_) => "postgres",
+            Self::OldSled(_) => "old_sled",
+            Self::ParseString(_) => "parse_string",
+            Self::Io(_) => "io",
+            Self::PathGenerator(_) => "path_generator",
+            Self::StripPrefix(_) => "strip_prefix",
+            Self::FileStore(_) => "file_store",
+            Self::ObjectStore(_) => "object_store",
+            Self::ParsePath => "parse_path",
+            Self::Semaphore => "semaphore",
+            Self::Canceled => "canceled",
+            Self::NoFiles => "no_files",
+            Self::AlreadyClaimed => "already_claimed",
+            Self::MissingAlias => "missing_alias",
+            Self::InvalidToken => "invalid_token",
+            Self::UnsupportedFormat => "unsupported_format",
+            Self::SilentVideoDisabled => "silent_video_disabled",
+            Self::Dimensions => "dimensions",
+            Self::Frames => "frames",
+            Self::Download(_) => "download",
+            Self::Payload(_) => "payload",
+            Self::SendRequest(_) => "send_request",
+            Self::Path => "path",
+            Self::DuplicateAlias => "duplicate_alias",
+            Self::InvalidHash => "invalid_hash",
+            Self::MissingPerceptualHash => "missing_perceptual_hash",
+            Self::InvalidFocalPoint => "invalid_focal_point",
+            Self::Blocked => "blocked",
+            Self::MissingPreset => "missing_preset",
+            Self::PresetsOnly => "presets_only",
+            Self::SigningDisabled => "signing_disabled",
+            Self::NotAnimated => "not_animated",
+            Self::Json(_) => "json",
+            Self::Cbor(_) => "cbor",
+            Self::Range => "range",
+            Self::Limit(_) => "limit",
+            Self::Timeout(_) => "timeout",
+        }
+    }
+}
+
 impl From<awc::error::SendRequestError> for UploadError {
     fn from(e: awc::error::SendRequestError) -> Self {
         UploadError::SendRequest(e.to_string())
@@ -156,18 +244,27 @@ impl ResponseError for Error {
         match self.kind() {
             Some(
                 UploadError::DuplicateAlias
+                | UploadError::InvalidHash
+                | UploadError::InvalidFocalPoint
                 | UploadError::Limit(_)
                 | UploadError::NoFiles
                 | UploadError::Upload(_)
                 | UploadError::UnsupportedFormat
                 | UploadError::AlreadyClaimed
+                | UploadError::SigningDisabled
+                | UploadError::NotAnimated
                 | UploadError::SilentVideoDisabled,
             ) => StatusCode::BAD_REQUEST,
             Some(
                 UploadError::Sled(crate::repo::sled::SledError::Missing)
-                | UploadError::MissingAlias,
+                | UploadError::Postgres(crate::repo::postgres::PostgresError::Missing)
+                | UploadError::MissingAlias
+                | UploadError::MissingPerceptualHash
+                | UploadError::MissingPreset,
             ) => StatusCode::NOT_FOUND,
-            Some(UploadError::InvalidToken) => StatusCode::FORBIDDEN,
+            Some(UploadError::InvalidToken | UploadError::Blocked | UploadError::PresetsOnly) => {
+                StatusCode::FORBIDDEN
+            }
             Some(UploadError::Range) => StatusCode::RANGE_NOT_SATISFIABLE,
             _ => StatusCode::INTERNAL_SERVER_ERROR,
         }
@@ -1,14 +1,21 @@
 use crate::{
-    config::{AudioCodec, ImageFormat, MediaConfiguration, VideoCodec},
+    config::{AnimationFormat, AudioCodec, ImageFormat, MediaConfiguration, VideoCodec},
     error::{Error, UploadError},
     magick::{Details, ValidInputType},
     process::Process,
+    processor::FramePosition,
     store::Store,
 };
 use actix_web::web::Bytes;
 use once_cell::sync::OnceCell;
-use std::collections::HashSet;
-use tokio::io::{AsyncRead, AsyncReadExt};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt},
+    process::Command,
+};
 
 #[derive(Debug)]
 pub(crate) struct TranscodeOptions {
@@ -197,6 +204,15 @@ pub(crate) enum ThumbnailFormat {
     // Webp,
 }
 
+/// Which frame of a gif or video a thumbnail is taken from
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum ThumbnailFrame {
+    First,
+    At(FramePosition),
+    /// The most detailed frame that isn't black, out of a sample from across the video
+    Representative,
+}
+
 #[derive(Clone, Copy, Debug)]
 pub(crate) enum FileFormat {
     Image(ImageFormat),
@@ -212,6 +228,8 @@ impl ValidInputType {
             Self::Jpeg => FileFormat::Image(ImageFormat::Jpeg),
             Self::Png => FileFormat::Image(ImageFormat::Png),
             Self::Webp => FileFormat::Image(ImageFormat::Webp),
+            Self::Avif => FileFormat::Image(ImageFormat::Avif),
+            Self::Jxl => FileFormat::Image(ImageFormat::Jxl),
         }
     }
 }
@@ -306,6 +324,53 @@ impl VideoCodec {
     }
 }
 
+impl AnimationFormat {
+    const fn to_file_extension(self) -> &'static str {
+        match self {
+            Self::Gif => ".gif",
+            Self::Webp => ".webp",
+            Self::Mp4 => ".mp4",
+            Self::Webm => ".webm",
+        }
+    }
+
+    fn encode_args(self) -> &'static [&'static str] {
+        match self {
+            Self::Gif => &[
+                "-filter_complex",
+                "[0:v] split [a][b]; [a] palettegen=reserve_transparent=on:transparency_color=ffffff [p]; [b][p] paletteuse",
+                "-loop",
+                "0",
+                "-f",
+                "gif",
+            ],
+            Self::Webp => &["-c:v", "libwebp", "-loop", "0", "-f", "webp"],
+            Self::Mp4 => &[
+                "-pix_fmt",
+                "yuv420p",
+                "-vf",
+                "scale=trunc(iw/2)*2:trunc(ih/2)*2",
+                "-c:v",
+                "h264",
+                "-movflags",
+                "+faststart",
+                "-f",
+                "mp4",
+            ],
+            Self::Webm => &[
+                "-pix_fmt",
+                "yuv420p",
+                "-vf",
+                "scale=trunc(iw/2)*2:trunc(ih/2)*2",
+                "-c:v",
+                "vp9",
+                "-f",
+                "webm",
+            ],
+        }
+    }
+}
+
 impl AudioCodec {
     const fn to_ffmpeg_codec(self) -> &'static str {
         match self {
@@ -316,6 +381,16 @@ impl AudioCodec {
     }
 }
 
+/// How many frames are compared when picking a representative frame
+const REPRESENTATIVE_SAMPLES: u32 = 10;
+
+/// Frames with a mean brightness below this fraction are treated as black
+const BLACK_THRESHOLD: f64 = 0.1;
+
+/// Seeking to the very end of a video leaves no frame to extract, so seeks stop this many seconds
+/// short
+const END_MARGIN: f64 = 0.1;
+
 const FORMAT_MAPPINGS: &[(&str, VideoFormat)] = &[
     ("gif", VideoFormat::Gif),
     ("mp4", VideoFormat::Mp4),
@@ -411,53 +486,98 @@ where
         &[
             "-v",
             "quiet",
-            "-select_streams",
-            "v:0",
             "-count_frames",
-            "-show_entries",
-            "stream=width,height,nb_read_frames:format=format_name",
+            "-show_streams",
+            "-show_format",
             "-of",
-            "default=noprint_wrappers=1:nokey=1",
+            "json",
             input_file_str,
         ],
     )?;
 
     let mut output = Vec::new();
     process.read().read_to_end(&mut output).await?;
-    let output = String::from_utf8_lossy(&output);
     tokio::fs::remove_file(input_file_str).await?;
 
-    parse_details(output)
+    parse_details(&output)
 }
 
-fn parse_details(output: std::borrow::Cow<'_, str>) -> Result<Option<Details>, Error> {
-    tracing::debug!("OUTPUT: {}", output);
+#[derive(Debug, serde::Deserialize)]
+struct Probe {
+    #[serde(default)]
+    streams: Vec<ProbeStream>,
+    format: Option<ProbeFormat>,
+}
 
-    let mut lines = output.lines();
+// ffprobe reports most numbers as strings
+#[derive(Debug, serde::Deserialize)]
+struct ProbeStream {
+    codec_type: Option<String>,
+    codec_name: Option<String>,
+    width: Option<usize>,
+    height: Option<usize>,
+    nb_read_frames: Option<String>,
+    avg_frame_rate: Option<String>,
+    color_space: Option<String>,
+    #[serde(default)]
+    side_data_list: Vec<ProbeSideData>,
+    #[serde(default)]
+    tags: ProbeTags,
+}
 
-    let width = match lines.next() {
-        Some(line) => line,
-        None => return Ok(None),
-    };
+#[derive(Debug, serde::Deserialize)]
+struct ProbeSideData {
+    rotation: Option<f64>,
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+struct ProbeTags {
+    rotate: Option<String>,
+    major_brand: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct ProbeFormat {
+    format_name: String,
+    duration: Option<String>,
+    bit_rate: Option<String>,
+    #[serde(default)]
+    tags: ProbeTags,
+}
+
+fn parse_details(output: &[u8]) -> Result<Option<Details>, Error> {
+    tracing::debug!("OUTPUT: {}", String::from_utf8_lossy(output));
 
-    let height = match lines.next() {
-        Some(line) => line,
-        None => return Ok(None),
+    // Files ffprobe can't read produce no output at all
+    let Ok(probe) = serde_json::from_slice::<Probe>(output) else {
+        return Ok(None);
     };
 
-    let frames = match lines.next() {
-        Some(line) => line,
-        None => return Ok(None),
+    let Some(format) = probe.format else {
+        return Ok(None);
     };
 
-    let formats = match lines.next() {
-        Some(line) => line,
-        None => return Ok(None),
+    // AVIF shares a container with MP4, and is left for imagemagick to handle as an image
+    if let Some("avif" | "avis") = format.tags.major_brand.as_deref().map(str::trim) {
+        return Ok(None);
+    }
+
+    let Some(video) = probe
+        .streams
+        .iter()
+        .find(|stream| stream.codec_type.as_deref() == Some("video"))
+    else {
+        return Ok(None);
     };
 
+    let audio = probe
+        .streams
+        .iter()
+        .find(|stream| stream.codec_type.as_deref() == Some("audio"));
+
     for (k, v) in FORMAT_MAPPINGS {
-        if formats.contains(k) {
-            return Ok(Some(parse_details_inner(width, height, frames, *v)?));
+        if format.format_name.contains(k) {
+            return Ok(Some(parse_details_inner(video, audio, &format, *v)?));
         }
     }
 
@@ -465,23 +585,63 @@ fn parse_details(output: std::borrow::Cow<'_, str>) -> Result<Option<Details>, E
 }
 
 fn parse_details_inner(
-    width: &str,
-    height: &str,
-    frames: &str,
+    video: &ProbeStream,
+    audio: Option<&ProbeStream>,
+    probe_format: &ProbeFormat,
     format: VideoFormat,
 ) -> Result<Details, Error> {
-    let width = width.parse().map_err(|_| UploadError::UnsupportedFormat)?;
-    let height = height.parse().map_err(|_| UploadError::UnsupportedFormat)?;
-    let frames = frames.parse().map_err(|_| UploadError::UnsupportedFormat)?;
+    let width = video.width.ok_or(UploadError::UnsupportedFormat)?;
+    let height = video.height.ok_or(UploadError::UnsupportedFormat)?;
+    let frames = video
+        .nb_read_frames
+        .as_deref()
+        .and_then(|frames| frames.parse().ok())
+        .ok_or(UploadError::UnsupportedFormat)?;
+
+    // Rotations are stored as display matrices on newer files, and as a tag on older ones
+    let rotation = video
+        .side_data_list
+        .iter()
+        .find_map(|side_data| side_data.rotation)
+        .or_else(|| {
+            let rotate = video.tags.rotate.as_deref()?.parse::<f64>().ok()?;
+            Some(-rotate)
+        });
 
     Ok(Details {
         mime_type: format.to_mime(),
         width,
         height,
         frames: Some(frames),
+        duration: probe_format
+            .duration
+            .as_deref()
+            .and_then(|duration| duration.parse().ok()),
+        frame_rate: video.avg_frame_rate.as_deref().and_then(parse_frame_rate),
+        video_codec: video.codec_name.clone(),
+        audio_codec: audio.and_then(|audio| audio.codec_name.clone()),
+        bitrate: probe_format
+            .bit_rate
+            .as_deref()
+            .and_then(|bit_rate| bit_rate.parse().ok()),
+        has_audio: Some(audio.is_some()),
+        color_space: video.color_space.as_deref().and_then(crate::magick::known),
+        orientation: Some(orientation(rotation.unwrap_or(0.0)).to_string()),
     })
 }
 
+/// Name a rotation the way ImageMagick names EXIF orientations. Display matrices rotate
+/// counterclockwise, while orientations are named for the clockwise turn that shows the video
+/// upright
+fn orientation(rotation: f64) -> &'static str {
+    match (-(rotation.round() as i64)).rem_euclid(360) {
+        90 => "RightTop",
+        180 => "BottomRight",
+        270 => "LeftBottom",
+        _ => "TopLeft",
+    }
+}
+
 async fn pixel_format(input_file: &str) -> Result<String, Error> {
     let process = Process::run(
         "ffprobe",
@@ -557,6 +717,7 @@ pub(crate) async fn thumbnail<S: Store>(
     from: S::Identifier,
     input_format: VideoFormat,
     format: ThumbnailFormat,
+    frame: ThumbnailFrame,
 ) -> Result<impl AsyncRead + Unpin, Error> {
     let input_file = crate::tmp_file::tmp_file(Some(input_format.to_file_extension()));
     let input_file_str = input_file.to_str().ok_or(UploadError::Path)?;
@@ -572,24 +733,404 @@ pub(crate) async fn thumbnail<S: Store>(
         .await?;
     tmp_one.close().await?;
 
+    let res = async {
+        let seconds = match frame {
+            ThumbnailFrame::First => None,
+            ThumbnailFrame::At(position) => {
+                Some(seek_seconds(position, duration(input_file_str).await?))
+            }
+            ThumbnailFrame::Representative => representative_seconds(input_file_str).await?,
+        };
+
+        extract_frame(input_file_str, seconds, format, output_file_str).await
+    }
+    .await;
+
+    tokio::fs::remove_file(input_file).await?;
+
+    if let Err(e) = res {
+        let _ = tokio::fs::remove_file(&output_file).await;
+        return Err(e);
+    }
+
+    let tmp_two = crate::file::File::open(&output_file).await?;
+    let stream = tmp_two.read_to_stream(None, None).await?;
+    let reader = tokio_util::io::StreamReader::new(stream);
+    let clean_reader = crate::tmp_file::cleanup_tmpfile(reader, output_file);
+
+    Ok(Box::pin(clean_reader))
+}
+
+async fn extract_frame(
+    input_file: &str,
+    seconds: Option<f64>,
+    format: ThumbnailFormat,
+    output_file: &str,
+) -> Result<(), Error> {
+    let mut command = Command::new("ffmpeg");
+    command.arg("-hide_banner");
+
+    // Seeking before the input skips decoding everything up to the chosen frame
+    if let Some(seconds) = seconds {
+        command.args(["-ss", &format!("{seconds:.3}")]);
+    }
+
+    Process::spawn(command.args([
+        "-i",
+        input_file,
+        "-frames:v",
+        "1",
+        "-codec",
+        format.as_ffmpeg_codec(),
+        "-f",
+        format.as_ffmpeg_format(),
+        output_file,
+    ]))?
+    .wait()
+    .await?;
+
+    Ok(())
+}
+
+async fn duration(input_file: &str) -> Result<Option<f64>, Error> {
     let process = Process::run(
+        "ffprobe",
+        &[
+            "-v",
+            "0",
+            "-show_entries",
+            "format=duration",
+            "-of",
+            "compact=p=0:nk=1",
+            input_file,
+        ],
+    )?;
+
+    let mut output = Vec::new();
+    process.read().read_to_end(&mut output).await?;
+
+    // Some gifs don't report a duration
+    let duration = String::from_utf8_lossy(&output).trim().parse::<f64>().ok();
+
+    Ok(duration.filter(|duration| duration.is_finite() && *duration > 0.0))
+}
+
+fn seek_seconds(position: FramePosition, duration: Option<f64>) -> f64 {
+    let last = duration.map_or(f64::MAX, |duration| (duration - END_MARGIN).max(0.0));
+
+    match (position, duration) {
+        (FramePosition::Seconds(seconds), _) => seconds.min(last),
+        (FramePosition::Percent(percent), Some(duration)) => (duration * percent / 100.0).min(last),
+        (FramePosition::Percent(_), None) => 0.0,
+    }
+}
+
+/// Extract frames evenly spaced through a video into a directory, returning the time and path of
+/// each. Files are numbered in order from zero, so they can be read back as an image sequence
+async fn sample_frames(
+    input_file: &str,
+    duration: Option<f64>,
+    count: u32,
+    dir: &Path,
+) -> Result<Vec<(f64, PathBuf)>, Error> {
+    // Without a duration, every position resolves to the first frame
+    let count = if duration.is_some() { count } else { 1 };
+
+    let mut samples = Vec::new();
+
+    for i in 0..count {
+        let position = (f64::from(i) + 0.5) * 100.0 / f64::from(count);
+        let seconds = seek_seconds(FramePosition::Percent(position), duration);
+
+        let path = dir.join(format!(
+            "{:03}{}",
+            samples.len(),
+            ThumbnailFormat::Jpeg.to_file_extension()
+        ));
+        let path_str = path.to_str().ok_or(UploadError::Path)?;
+
+        extract_frame(input_file, Some(seconds), ThumbnailFormat::Jpeg, path_str).await?;
+
+        // Seeks that land between the last frame and the end of the video produce nothing
+        if tokio::fs::metadata(&path).await.is_ok() {
+            samples.push((seconds, path));
+        }
+    }
+
+    Ok(samples)
+}
+
+/// Sample frames from across a video, returning the time of the most detailed one that isn't
+/// black. Without a known duration there's nothing to sample, and the first frame is used
+async fn representative_seconds(input_file: &str) -> Result<Option<f64>, Error> {
+    let Some(duration) = duration(input_file).await? else {
+        return Ok(None);
+    };
+
+    let samples_dir = crate::tmp_file::tmp_file(None);
+    tokio::fs::create_dir_all(&samples_dir).await?;
+
+    let res = score_samples(input_file, duration, &samples_dir).await;
+
+    tokio::fs::remove_dir_all(&samples_dir).await?;
+
+    Ok(pick_representative(&res?))
+}
+
+/// Extract evenly spaced frames, returning the time of each with its mean brightness and
+/// standard deviation
+async fn score_samples(
+    input_file: &str,
+    duration: f64,
+    samples_dir: &Path,
+) -> Result<Vec<(f64, f64, f64)>, Error> {
+    let (times, paths): (Vec<_>, Vec<_>) = sample_frames(
+        input_file,
+        Some(duration),
+        REPRESENTATIVE_SAMPLES,
+        samples_dir,
+    )
+    .await?
+    .into_iter()
+    .unzip();
+
+    if paths.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut output = Vec::new();
+    Process::spawn(
+        Command::new("magick")
+            .args([
+                "identify",
+                "-format",
+                "%[fx:mean] %[fx:standard_deviation]\n",
+            ])
+            .args(&paths),
+    )?
+    .read()
+    .read_to_end(&mut output)
+    .await?;
+
+    let scores = String::from_utf8_lossy(&output)
+        .lines()
+        .map(|line| {
+            let (mean, deviation) = line.split_once(' ')?;
+            Some((mean.parse().ok()?, deviation.parse().ok()?))
+        })
+        .collect::<Option<Vec<(f64, f64)>>>()
+        .ok_or(UploadError::UnsupportedFormat)?;
+
+    Ok(times
+        .into_iter()
+        .zip(scores)
+        .map(|(seconds, (mean, deviation))| (seconds, mean, deviation))
+        .collect())
+}
+
+/// Prefer the most detailed frame that isn't black, or the brightest if they all are
+fn pick_representative(samples: &[(f64, f64, f64)]) -> Option<f64> {
+    let detailed = samples
+        .iter()
+        .filter(|(_, mean, _)| *mean >= BLACK_THRESHOLD)
+        .max_by(|(_, _, left), (_, _, right)| left.total_cmp(right));
+
+    let brightest = || {
+        samples
+            .iter()
+            .max_by(|(_, left, _), (_, right, _)| left.total_cmp(right))
+    };
+
+    detailed.or_else(brightest).map(|(seconds, _, _)| *seconds)
+}
+
+/// Tile frames sampled evenly from a gif or video into a single png, returning it with the time
+/// of each frame in the order they were tiled
+#[tracing::instrument(skip(store))]
+pub(crate) async fn storyboard<S: Store>(
+    store: S,
+    from: S::Identifier,
+    input_format: VideoFormat,
+    frames: u32,
+    tile_width: u32,
+) -> Result<(impl AsyncRead + Unpin, Vec<f64>), Error> {
+    let input_file = crate::tmp_file::tmp_file(Some(input_format.to_file_extension()));
+    let frames_dir = crate::tmp_file::tmp_file(None);
+    let output_file = crate::tmp_file::tmp_file(Some(".png"));
+
+    let res = async {
+        crate::store::file_store::safe_create_parent(&input_file).await?;
+        tokio::fs::create_dir_all(&frames_dir).await?;
+
+        let mut tmp_one = crate::file::File::create(&input_file).await?;
+        tmp_one
+            .write_from_stream(store.to_stream(&from, None, None).await?)
+            .await?;
+        tmp_one.close().await?;
+
+        let input_file_str = input_file.to_str().ok_or(UploadError::Path)?;
+        let output_file_str = output_file.to_str().ok_or(UploadError::Path)?;
+
+        let duration = duration(input_file_str).await?;
+        let times = sample_frames(input_file_str, duration, frames, &frames_dir)
+            .await?
+            .into_iter()
+            .map(|(seconds, _)| seconds)
+            .collect::<Vec<_>>();
+
+        if times.is_empty() {
+            return Err(UploadError::UnsupportedFormat.into());
+        }
+
+        tile_frames(&frames_dir, times.len(), tile_width, output_file_str).await?;
+
+        Ok(times) as Result<Vec<f64>, Error>
+    }
+    .await;
+
+    remove_tmp(&input_file, tokio::fs::remove_file(&input_file).await);
+    remove_tmp(&frames_dir, tokio::fs::remove_dir_all(&frames_dir).await);
+
+    let times = match res {
+        Ok(times) => times,
+        Err(e) => {
+            remove_tmp(&output_file, tokio::fs::remove_file(&output_file).await);
+            return Err(e);
+        }
+    };
+
+    let tmp_two = crate::file::File::open(&output_file).await?;
+    let stream = tmp_two.read_to_stream(None, None).await?;
+    let reader = tokio_util::io::StreamReader::new(stream);
+    let clean_reader = crate::tmp_file::cleanup_tmpfile(reader, output_file);
+
+    Ok((Box::pin(clean_reader), times))
+}
+
+async fn tile_frames(
+    frames_dir: &Path,
+    count: usize,
+    tile_width: u32,
+    output_file: &str,
+) -> Result<(), Error> {
+    let frames_pattern =
+        frames_dir.join(format!("%03d{}", ThumbnailFormat::Jpeg.to_file_extension()));
+    let frames_pattern = frames_pattern.to_str().ok_or(UploadError::Path)?;
+
+    let (columns, rows) = crate::storyboard::grid(count);
+
+    // Heights are kept even, and every frame of a video shares a size, so the tiles line up
+    let filter = format!("scale={tile_width}:-2,tile={columns}x{rows}");
+
+    Process::run(
         "ffmpeg",
         &[
             "-hide_banner",
             "-i",
-            input_file_str,
+            frames_pattern,
+            "-vf",
+            &filter,
             "-frames:v",
             "1",
             "-codec",
-            format.as_ffmpeg_codec(),
+            "png",
             "-f",
-            format.as_ffmpeg_format(),
-            output_file_str,
+            "image2",
+            output_file,
+        ],
+    )?
+    .wait()
+    .await?;
+
+    Ok(())
+}
+
+async fn frame_rate(input_file: &str) -> Result<Option<f64>, Error> {
+    let process = Process::run(
+        "ffprobe",
+        &[
+            "-v",
+            "0",
+            "-select_streams",
+            "v:0",
+            "-show_entries",
+            "stream=avg_frame_rate",
+            "-of",
+            "compact=p=0:nk=1",
+            input_file,
         ],
     )?;
 
-    process.wait().await?;
-    tokio::fs::remove_file(input_file).await?;
+    let mut output = Vec::new();
+    process.read().read_to_end(&mut output).await?;
+    Ok(parse_frame_rate(String::from_utf8_lossy(&output).trim()))
+}
+
+// ffprobe reports frame rates as fractions, and some gifs report `0/0`
+fn parse_frame_rate(s: &str) -> Option<f64> {
+    let (numerator, denominator) = s.split_once('/')?;
+    let numerator: f64 = numerator.parse().ok()?;
+    let denominator: f64 = denominator.parse().ok()?;
+
+    let rate = numerator / denominator;
+
+    (rate.is_finite() && rate > 0.0).then_some(rate)
+}
+
+/// Process every frame of a gif or video with ImageMagick, and encode the result as an animation
+///
+/// Frames are sampled at no more than the configured frame rate, and anything past the configured
+/// duration is dropped, so the work done per request stays bounded
+#[tracing::instrument(skip(store, args, media))]
+pub(crate) async fn animate<S: Store>(
+    store: S,
+    from: S::Identifier,
+    input_format: VideoFormat,
+    format: AnimationFormat,
+    args: Vec<String>,
+    media: &MediaConfiguration,
+) -> Result<impl AsyncRead + Unpin, Error> {
+    let input_file = crate::tmp_file::tmp_file(Some(input_format.to_file_extension()));
+    let frames_dir = crate::tmp_file::tmp_file(None);
+    let processed_dir = crate::tmp_file::tmp_file(None);
+    let output_file = crate::tmp_file::tmp_file(Some(format.to_file_extension()));
+
+    let res = async {
+        crate::store::file_store::safe_create_parent(&input_file).await?;
+        tokio::fs::create_dir_all(&frames_dir).await?;
+        tokio::fs::create_dir_all(&processed_dir).await?;
+
+        let mut tmp_one = crate::file::File::create(&input_file).await?;
+        tmp_one
+            .write_from_stream(store.to_stream(&from, None, None).await?)
+            .await?;
+        tmp_one.close().await?;
+
+        animate_files(
+            input_file.to_str().ok_or(UploadError::Path)?,
+            &frames_dir,
+            &processed_dir,
+            output_file.to_str().ok_or(UploadError::Path)?,
+            format,
+            args,
+            media,
+        )
+        .await
+    }
+    .await;
+
+    // Intermediate files are removed no matter where animating stopped
+    remove_tmp(&input_file, tokio::fs::remove_file(&input_file).await);
+    remove_tmp(&frames_dir, tokio::fs::remove_dir_all(&frames_dir).await);
+    remove_tmp(
+        &processed_dir,
+        tokio::fs::remove_dir_all(&processed_dir).await,
+    );
+
+    if let Err(e) = res {
+        remove_tmp(&output_file, tokio::fs::remove_file(&output_file).await);
+        return Err(e);
+    }
 
     let tmp_two = crate::file::File::open(&output_file).await?;
     let stream = tmp_two.read_to_stream(None, None).await?;
@@ -598,3 +1139,199 @@ pub(crate) async fn thumbnail<S: Store>(
 
     Ok(Box::pin(clean_reader))
 }
+
+fn remove_tmp(path: &Path, res: std::io::Result<()>) {
+    match res {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => tracing::warn!("Failed to remove {}: {e}", path.display()),
+    }
+}
+
+// Frames are processed in batches, so few processes are spawned without one command line growing
+// with the configured frame rate and duration
+const FRAMES_PER_PROCESS: usize = 50;
+
+async fn animate_files(
+    input_file: &str,
+    frames_dir: &Path,
+    processed_dir: &Path,
+    output_file: &str,
+    format: AnimationFormat,
+    args: Vec<String>,
+    media: &MediaConfiguration,
+) -> Result<(), Error> {
+    let max_frame_rate = f64::from(media.animation.max_frame_rate.max(1));
+    let frame_rate = frame_rate(input_file)
+        .await?
+        .map_or(max_frame_rate, |rate| rate.min(max_frame_rate));
+    let frame_rate = format!("{frame_rate:.3}");
+    let max_duration = media.animation.max_duration.to_string();
+
+    let frames_pattern = frames_dir.join("%05d.png");
+    let frames_pattern = frames_pattern.to_str().ok_or(UploadError::Path)?;
+
+    Process::run(
+        "ffmpeg",
+        &[
+            "-hide_banner",
+            "-i",
+            input_file,
+            "-t",
+            &max_duration,
+            "-vf",
+            &format!("fps={frame_rate}"),
+            "-an",
+            "-f",
+            "image2",
+            frames_pattern,
+        ],
+    )?
+    .wait()
+    .await?;
+
+    let mut frames = Vec::new();
+    let mut entries = tokio::fs::read_dir(frames_dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        frames.push(entry.file_name());
+    }
+    frames.sort();
+
+    for batch in frames.chunks(FRAMES_PER_PROCESS) {
+        // Settings like `-gravity` stay inside each frame's parentheses
+        let mut command = Command::new("magick");
+        command.arg("-respect-parentheses");
+
+        for frame in batch {
+            let input = frames_dir.join(frame);
+            let output = processed_dir.join(frame);
+            let output = format!("PNG:{}", output.to_str().ok_or(UploadError::Path)?);
+
+            // Each frame is processed inside its own parentheses, since commands like overlays
+            // composite the last two images in ImageMagick's list
+            command
+                .arg("(")
+                .arg(input)
+                .args(&args)
+                .args(["-write", &output, "+delete", ")"]);
+        }
+
+        // Every frame was written and removed from the list, so there's nothing left to output
+        command.args(["xc:", "null:"]);
+
+        Process::spawn(&mut command)?.wait().await?;
+    }
+
+    let processed_pattern = processed_dir.join("%05d.png");
+    let processed_pattern = processed_pattern.to_str().ok_or(UploadError::Path)?;
+
+    Process::spawn(
+        Command::new("ffmpeg")
+            .args([
+                "-hide_banner",
+                "-framerate",
+                &frame_rate,
+                "-i",
+                processed_pattern,
+            ])
+            .args(format.encode_args())
+            .arg(output_file),
+    )?
+    .wait()
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        orientation, parse_details, parse_frame_rate, pick_representative, seek_seconds, END_MARGIN,
+    };
+    use crate::processor::FramePosition;
+
+    #[test]
+    fn parses_frame_rates() {
+        assert_eq!(parse_frame_rate("30/1"), Some(30.0));
+        assert_eq!(parse_frame_rate("25/2"), Some(12.5));
+        assert!(parse_frame_rate("30000/1001").unwrap() > 29.97);
+        assert_eq!(parse_frame_rate("0/0"), None);
+        assert_eq!(parse_frame_rate("N/A"), None);
+    }
+
+    #[test]
+    fn seeks_stay_inside_videos() {
+        assert_eq!(seek_seconds(FramePosition::Seconds(2.0), Some(10.0)), 2.0);
+        assert_eq!(
+            seek_seconds(FramePosition::Seconds(20.0), Some(10.0)),
+            10.0 - END_MARGIN
+        );
+        assert_eq!(seek_seconds(FramePosition::Percent(50.0), Some(10.0)), 5.0);
+        assert_eq!(
+            seek_seconds(FramePosition::Percent(100.0), Some(10.0)),
+            10.0 - END_MARGIN
+        );
+        assert_eq!(seek_seconds(FramePosition::Seconds(20.0), None), 20.0);
+        assert_eq!(seek_seconds(FramePosition::Percent(50.0), None), 0.0);
+    }
+
+    #[test]
+    fn representative_frames_skip_black_frames() {
+        let samples = [(0.5, 0.01, 0.4), (1.5, 0.4, 0.2), (2.5, 0.6, 0.3)];
+        assert_eq!(pick_representative(&samples), Some(2.5));
+
+        let black = [(0.5, 0.01, 0.01), (1.5, 0.05, 0.02)];
+        assert_eq!(pick_representative(&black), Some(1.5));
+
+        assert_eq!(pick_representative(&[]), None);
+    }
+
+    #[test]
+    fn parses_probe_output() {
+        let output = br#"{
+            "streams": [
+                {
+                    "codec_name": "h264",
+                    "codec_type": "video",
+                    "width": 1920,
+                    "height": 1080,
+                    "avg_frame_rate": "30/1",
+                    "nb_read_frames": "300",
+                    "color_space": "bt709",
+                    "side_data_list": [{ "rotation": -90 }]
+                },
+                { "codec_name": "aac", "codec_type": "audio" }
+            ],
+            "format": {
+                "format_name": "mov,mp4,m4a,3gp,3g2,mj2",
+                "duration": "10.000000",
+                "bit_rate": "4000000",
+                "tags": { "major_brand": "isom" }
+            }
+        }"#;
+
+        let details = parse_details(output).unwrap().unwrap();
+        assert_eq!(details.mime_type, crate::magick::video_mp4());
+        assert_eq!((details.width, details.height), (1920, 1080));
+        assert_eq!(details.frames, Some(300));
+        assert_eq!(details.duration, Some(10.0));
+        assert_eq!(details.frame_rate, Some(30.0));
+        assert_eq!(details.video_codec.as_deref(), Some("h264"));
+        assert_eq!(details.audio_codec.as_deref(), Some("aac"));
+        assert_eq!(details.bitrate, Some(4_000_000));
+        assert_eq!(details.has_audio, Some(true));
+        assert_eq!(details.color_space.as_deref(), Some("bt709"));
+        assert_eq!(details.orientation.as_deref(), Some("RightTop"));
+
+        assert!(parse_details(b"").unwrap().is_none());
+        assert!(parse_details(b"{}").unwrap().is_none());
+    }
+
+    #[test]
+    fn orientations() {
+        assert_eq!(orientation(0.0), "TopLeft");
+        assert_eq!(orientation(-90.0), "RightTop");
+        assert_eq!(orientation(180.0), "BottomRight");
+        assert_eq!(orientation(90.0), "LeftBottom");
+    }
+}
@@ -1,14 +1,15 @@
 use crate::{
     concurrent_processor::CancelSafeProcessor,
-    config::ImageFormat,
+    config::TargetFormat,
     details::Details,
-    error::Error,
-    ffmpeg::{ThumbnailFormat, VideoFormat},
+    error::{Error, UploadError},
+    ffmpeg::{ThumbnailFormat, ThumbnailFrame, VideoFormat},
+    processor::OVERLAY_PREFIX,
     repo::{Alias, FullRepo},
     store::Store,
 };
 use actix_web::web::Bytes;
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Instant};
 use tokio::io::AsyncReadExt;
 use tracing::Instrument;
 
@@ -17,7 +18,7 @@ use tracing::Instrument;
 pub(crate) async fn generate<R: FullRepo, S: Store + 'static>(
     repo: &R,
     store: &S,
-    format: ImageFormat,
+    format: TargetFormat,
     alias: Alias,
     thumbnail_path: PathBuf,
     thumbnail_args: Vec<String>,
@@ -48,7 +49,7 @@ pub(crate) async fn generate<R: FullRepo, S: Store + 'static>(
 async fn process<R: FullRepo, S: Store + 'static>(
     repo: &R,
     store: &S,
-    format: ImageFormat,
+    format: TargetFormat,
     alias: Alias,
     thumbnail_path: PathBuf,
     thumbnail_args: Vec<String>,
@@ -56,38 +57,99 @@ async fn process<R: FullRepo, S: Store + 'static>(
     thumbnail_format: Option<ThumbnailFormat>,
     hash: R::Bytes,
 ) -> Result<(Details, Bytes), Error> {
+    let start = Instant::now();
     let permit = crate::PROCESS_SEMAPHORE.acquire().await;
+    metrics::histogram!(
+        "pict_rs_process_semaphore_wait_seconds",
+        start.elapsed().as_secs_f64(),
+        "operation" => "generate"
+    );
 
-    let identifier = if let Some(identifier) = repo
-        .still_identifier_from_alias::<S::Identifier>(&alias)
-        .await?
-    {
-        identifier
-    } else {
-        let identifier = repo.identifier(hash.clone()).await?;
-        let reader = crate::ffmpeg::thumbnail(
-            store.clone(),
-            identifier,
-            input_format.unwrap_or(VideoFormat::Mp4),
-            thumbnail_format.unwrap_or(ThumbnailFormat::Jpeg),
-        )
-        .await?;
-        let motion_identifier = store.save_async_read(reader).await?;
-
-        repo.relate_motion_identifier(hash.clone(), &motion_identifier)
-            .await?;
+    let (frame, thumbnail_args) = crate::processor::take_frame_position(thumbnail_args);
+    // Still images only have the one frame to choose from
+    let frame = frame.filter(|_| input_format.is_some());
 
-        motion_identifier
+    let identifier = match format {
+        // A chosen frame is taken from the original, rather than the frame kept for the hash
+        TargetFormat::Image(_) if frame.is_some() => repo.identifier(hash.clone()).await?,
+        TargetFormat::Image(_) => {
+            if let Some(identifier) = repo
+                .still_identifier_from_alias::<S::Identifier>(&alias)
+                .await?
+            {
+                identifier
+            } else {
+                motion_identifier(
+                    repo,
+                    store,
+                    hash.clone(),
+                    input_format.unwrap_or(VideoFormat::Mp4),
+                    thumbnail_format.unwrap_or(ThumbnailFormat::Jpeg),
+                )
+                .await?
+            }
+        }
+        // Animations are made from the original rather than its first frame
+        TargetFormat::Animation { .. } if input_format.is_some() => {
+            repo.identifier(hash.clone()).await?
+        }
+        TargetFormat::Animation { .. } => return Err(UploadError::NotAnimated.into()),
     };
 
-    let mut processed_reader =
-        crate::magick::process_image_store_read(store.clone(), identifier, thumbnail_args, format)?;
+    let overlays = crate::processor::overlay_aliases(&thumbnail_args)
+        .map(Alias::from_existing)
+        .collect::<Vec<_>>();
+    let (thumbnail_args, overlay_files) = prepare_overlays(repo, store, thumbnail_args).await?;
 
     let mut vec = Vec::new();
-    processed_reader
-        .read_to_end(&mut vec)
-        .instrument(tracing::info_span!("Reading processed image to vec"))
-        .await?;
+    let res = async {
+        match format {
+            TargetFormat::Image(format) => {
+                if let Some(position) = frame {
+                    let reader = crate::ffmpeg::thumbnail(
+                        store.clone(),
+                        identifier,
+                        input_format.unwrap_or(VideoFormat::Mp4),
+                        thumbnail_format.unwrap_or(ThumbnailFormat::Jpeg),
+                        ThumbnailFrame::At(position),
+                    )
+                    .await?;
+
+                    crate::magick::process_image_async_read(reader, thumbnail_args, format)?
+                        .read_to_end(&mut vec)
+                        .await?;
+                } else {
+                    crate::magick::process_image_store_read(
+                        store.clone(),
+                        identifier,
+                        thumbnail_args,
+                        format,
+                    )?
+                    .read_to_end(&mut vec)
+                    .await?;
+                }
+            }
+            TargetFormat::Animation { animation } => {
+                crate::ffmpeg::animate(
+                    store.clone(),
+                    identifier,
+                    input_format.unwrap_or(VideoFormat::Mp4),
+                    animation,
+                    thumbnail_args,
+                    &crate::CONFIG.media,
+                )
+                .await?
+                .read_to_end(&mut vec)
+                .await?;
+            }
+        }
+
+        Ok(()) as Result<(), Error>
+    }
+    .instrument(tracing::info_span!("Reading processed image to vec"))
+    .await;
+    remove_overlays(overlay_files).await;
+    res?;
     let bytes = Bytes::from(vec);
 
     drop(permit);
@@ -97,11 +159,125 @@ async fn process<R: FullRepo, S: Store + 'static>(
     let identifier = store.save_bytes(bytes.clone()).await?;
     repo.relate_details(&identifier, &details).await?;
     repo.relate_variant_identifier(
-        hash,
+        hash.clone(),
         thumbnail_path.to_string_lossy().to_string(),
         &identifier,
     )
     .await?;
+    repo.mark_accessed(
+        hash.clone(),
+        thumbnail_path.to_string_lossy().to_string(),
+        time::OffsetDateTime::now_utc(),
+    )
+    .await?;
+
+    for overlay in overlays {
+        repo.relate_overlay_variant(
+            &overlay,
+            hash.clone(),
+            thumbnail_path.to_string_lossy().to_string(),
+        )
+        .await?;
+    }
 
     Ok((details, bytes)) as Result<(Details, Bytes), Error>
 }
+
+/// Extract the frame used when a gif or video is processed into a still image, and keep it for
+/// later requests
+#[tracing::instrument(skip(repo, store, hash))]
+pub(crate) async fn motion_identifier<R: FullRepo, S: Store + 'static>(
+    repo: &R,
+    store: &S,
+    hash: R::Bytes,
+    input_format: VideoFormat,
+    thumbnail_format: ThumbnailFormat,
+) -> Result<S::Identifier, Error> {
+    let frame = if crate::CONFIG.media.representative_frame {
+        ThumbnailFrame::Representative
+    } else {
+        ThumbnailFrame::First
+    };
+
+    let identifier = repo.identifier(hash.clone()).await?;
+    let reader = crate::ffmpeg::thumbnail(
+        store.clone(),
+        identifier,
+        input_format,
+        thumbnail_format,
+        frame,
+    )
+    .await?;
+    let motion_identifier = store.save_async_read(reader).await?;
+
+    repo.relate_motion_identifier(hash, &motion_identifier)
+        .await?;
+
+    Ok(motion_identifier)
+}
+
+/// Write the original of each overlay in a command to a temporary file for ImageMagick to read,
+/// returning the command with the overlays pointed at those files
+#[tracing::instrument(skip(repo, store))]
+pub(crate) async fn prepare_overlays<R: FullRepo, S: Store>(
+    repo: &R,
+    store: &S,
+    args: Vec<String>,
+) -> Result<(Vec<String>, Vec<PathBuf>), Error> {
+    let mut files = Vec::new();
+
+    match write_overlays(repo, store, args, &mut files).await {
+        Ok(prepared) => Ok((prepared, files)),
+        Err(e) => {
+            // Overlays written before the failure would otherwise be left behind
+            remove_overlays(files).await;
+            Err(e)
+        }
+    }
+}
+
+async fn write_overlays<R: FullRepo, S: Store>(
+    repo: &R,
+    store: &S,
+    args: Vec<String>,
+    files: &mut Vec<PathBuf>,
+) -> Result<Vec<String>, Error> {
+    let mut prepared = Vec::with_capacity(args.len());
+
+    for arg in args {
+        let Some(alias) = arg.strip_prefix(OVERLAY_PREFIX) else {
+            prepared.push(arg);
+            continue;
+        };
+
+        let alias = Alias::from_existing(alias);
+        let identifier = match repo.still_identifier_from_alias(&alias).await? {
+            Some(identifier) => identifier,
+            None => repo.identifier(repo.hash(&alias).await?).await?,
+        };
+
+        let file = crate::tmp_file::tmp_file(None);
+        crate::store::file_store::safe_create_parent(&file).await?;
+
+        let mut tmp = crate::file::File::create(&file).await?;
+        files.push(file.clone());
+
+        tmp.write_from_stream(store.to_stream(&identifier, None, None).await?)
+            .await?;
+        tmp.close().await?;
+
+        // Only the first frame of an animated overlay is used
+        let path = file.to_str().ok_or(UploadError::Path)?;
+        prepared.push(format!("{path}[0]"));
+    }
+
+    Ok(prepared)
+}
+
+pub(crate) async fn remove_overlays(files: Vec<PathBuf>) {
+    for file in files {
+        if let Err(e) = tokio::fs::remove_file(&file).await {
+            tracing::warn!("Failed to remove overlay file: {e}");
+        }
+    }
+}
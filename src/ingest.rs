@@ -2,7 +2,7 @@ use crate::{
     bytes_stream::BytesStream,
     either::Either,
     error::{Error, UploadError},
-    magick::ValidInputType,
+    ffmpeg::{FileFormat, ThumbnailFormat, VideoFormat},
     repo::{Alias, AliasRepo, DeleteToken, FullRepo, HashRepo},
     store::Store,
     CONFIG,
@@ -10,6 +10,7 @@ use crate::{
 use actix_web::web::Bytes;
 use futures_util::{Stream, StreamExt};
 use sha2::{Digest, Sha256};
+use std::time::Instant;
 use tracing::{Instrument, Span};
 
 mod hasher;
@@ -51,9 +52,46 @@ pub(crate) async fn ingest<R, S>(
 ) -> Result<Session<R, S>, Error>
 where
     R: FullRepo + 'static,
-    S: Store,
+    S: Store + 'static,
 {
+    let start = Instant::now();
+
+    let res = do_ingest(repo, store, stream, declared_alias, should_validate).await;
+
+    match &res {
+        Ok(_) => {
+            metrics::increment_counter!("pict_rs_uploads_total");
+            metrics::histogram!(
+                "pict_rs_upload_duration_seconds",
+                start.elapsed().as_secs_f64()
+            );
+        }
+        Err(e) => {
+            metrics::increment_counter!("pict_rs_ingest_failures_total", "error" => e.name());
+        }
+    }
+
+    res
+}
+
+async fn do_ingest<R, S>(
+    repo: &R,
+    store: &S,
+    stream: impl Stream<Item = Result<Bytes, Error>> + Unpin + 'static,
+    declared_alias: Option<Alias>,
+    should_validate: bool,
+) -> Result<Session<R, S>, Error>
+where
+    R: FullRepo + 'static,
+    S: Store + 'static,
+{
+    let start = Instant::now();
     let permit = crate::PROCESS_SEMAPHORE.acquire().await;
+    metrics::histogram!(
+        "pict_rs_process_semaphore_wait_seconds",
+        start.elapsed().as_secs_f64(),
+        "operation" => "ingest"
+    );
 
     let bytes = aggregate(stream).await?;
 
@@ -61,12 +99,28 @@ where
     let (input_type, validated_reader) =
         crate::validate::validate_bytes(bytes, &CONFIG.media, should_validate).await?;
 
+    let mut overlay_files = Vec::new();
     let processed_reader = if let Some(operations) = CONFIG.media.preprocess_steps() {
         if let Some(format) = input_type.to_format() {
-            let (_, magick_args) = crate::processor::build_chain(operations, format.as_ext())?;
-
-            let processed_reader =
-                crate::magick::process_image_async_read(validated_reader, magick_args, format)?;
+            let (_, magick_args) =
+                crate::processor::build_chain(operations, format.as_ext(), None)?;
+            // Only still images are preprocessed, so there's no frame to choose
+            let (_, magick_args) = crate::processor::take_frame_position(magick_args);
+            let (magick_args, files) =
+                crate::generate::prepare_overlays(repo, store, magick_args).await?;
+
+            let processed_reader = match crate::magick::process_image_async_read(
+                validated_reader,
+                magick_args,
+                format,
+            ) {
+                Ok(processed_reader) => processed_reader,
+                Err(e) => {
+                    crate::generate::remove_overlays(files).await;
+                    return Err(e.into());
+                }
+            };
+            overlay_files = files;
 
             Either::left(processed_reader)
         } else {
@@ -79,7 +133,19 @@ where
     let hasher_reader = Hasher::new(processed_reader, Sha256::new());
     let hasher = hasher_reader.hasher();
 
-    let identifier = store.save_async_read(hasher_reader).await?;
+    let res = store.save_async_read(hasher_reader).await;
+    crate::generate::remove_overlays(overlay_files).await;
+    let identifier = res?;
+
+    // Near-duplicate detection is best-effort, so a failure here doesn't fail the upload
+    let perceptual_hash =
+        match crate::perceptual_hash::perceptual_hash(store, &identifier, input_type).await {
+            Ok(perceptual_hash) => Some(perceptual_hash),
+            Err(e) => {
+                tracing::warn!("Failed to compute perceptual hash: {e}");
+                None
+            }
+        };
 
     drop(permit);
 
@@ -92,14 +158,70 @@ where
 
     let hash = hasher.borrow_mut().finalize_reset().to_vec();
 
+    // Checked before the session takes the hash, since dropping the session would otherwise clean
+    // up media that's already stored under it
+    if repo.is_blocked(hash.clone().into()).await? {
+        return Err(UploadError::Blocked.into());
+    }
+
     session.hash = Some(hash.clone());
 
-    save_upload(repo, store, &hash, &identifier).await?;
+    save_upload(repo, store, &hash, &identifier, perceptual_hash).await?;
+
+    if CONFIG.media.representative_frame {
+        if let FileFormat::Video(format) = input_type.to_file_format() {
+            save_representative_frame(repo, store, &hash, format).await;
+        }
+    }
 
     if let Some(alias) = declared_alias {
         session.add_existing_alias(&hash, alias).await?
     } else {
-        session.create_alias(&hash, input_type).await?;
+        session.create_alias(&hash, input_type.as_ext()).await?;
+    }
+
+    Ok(session)
+}
+
+/// Attach a new alias to media that has already been ingested, without uploading it again
+#[tracing::instrument(skip(repo, hash), fields(hash = hex::encode(&hash)))]
+pub(crate) async fn ingest_existing<R, S>(repo: &R, hash: Vec<u8>) -> Result<Session<R, S>, Error>
+where
+    R: FullRepo + 'static,
+    S: Store,
+{
+    if repo.is_blocked(hash.clone().into()).await? {
+        return Err(UploadError::Blocked.into());
+    }
+
+    // A hash without any aliases is about to be cleaned up, so it's treated as missing
+    let existing = repo.aliases(hash.clone().into()).await?;
+    let extension = existing
+        .first()
+        .ok_or(UploadError::MissingAlias)?
+        .extension()
+        .unwrap_or_default()
+        .to_string();
+
+    // Only the new alias belongs to this session. The hash and its files are left alone if the
+    // session is dropped
+    let mut session = Session {
+        repo: repo.clone(),
+        hash: None,
+        alias: None,
+        identifier: None,
+    };
+
+    session.create_alias(&hash, &extension).await?;
+
+    // The last alias may have been cleaned up between reading the aliases and creating this one,
+    // and the hash along with it. Dropping the session removes the new alias again
+    if let Err(e) = repo.identifier::<S::Identifier>(hash.into()).await {
+        return Err(if e.is_missing() {
+            UploadError::MissingAlias.into()
+        } else {
+            e
+        });
     }
 
     Ok(session)
@@ -111,6 +233,7 @@ async fn save_upload<R, S>(
     store: &S,
     hash: &[u8],
     identifier: &S::Identifier,
+    perceptual_hash: Option<u64>,
 ) -> Result<(), Error>
 where
     S: Store,
@@ -124,9 +247,52 @@ where
     repo.relate_identifier(hash.to_vec().into(), identifier)
         .await?;
 
+    if let Some(perceptual_hash) = perceptual_hash {
+        repo.relate_perceptual_hash(hash.to_vec().into(), perceptual_hash)
+            .await?;
+    }
+
     Ok(())
 }
 
+/// Picking a frame takes several passes over the video, so it's done once during the upload
+/// rather than on the first request to process it. Failures are left for processing to retry
+#[tracing::instrument(level = "trace", skip_all)]
+async fn save_representative_frame<R, S>(repo: &R, store: &S, hash: &[u8], format: VideoFormat)
+where
+    S: Store + 'static,
+    R: FullRepo,
+{
+    match repo
+        .motion_identifier::<S::Identifier>(hash.to_vec().into())
+        .await
+    {
+        Ok(Some(_)) => return,
+        Ok(None) => {}
+        Err(e) => {
+            tracing::warn!("Failed to check for a representative frame: {e}");
+            return;
+        }
+    }
+
+    let permit = crate::PROCESS_SEMAPHORE.acquire().await;
+
+    let res = crate::generate::motion_identifier(
+        repo,
+        store,
+        hash.to_vec().into(),
+        format,
+        ThumbnailFormat::Jpeg,
+    )
+    .await;
+
+    drop(permit);
+
+    if let Err(e) = res {
+        tracing::warn!("Failed to save a representative frame: {e}");
+    }
+}
+
 impl<R, S> Session<R, S>
 where
     R: FullRepo + 'static,
@@ -177,9 +343,9 @@ where
     }
 
     #[tracing::instrument(level = "debug", skip(self, hash))]
-    async fn create_alias(&mut self, hash: &[u8], input_type: ValidInputType) -> Result<(), Error> {
+    async fn create_alias(&mut self, hash: &[u8], extension: &str) -> Result<(), Error> {
         loop {
-            let alias = Alias::generate(input_type.as_ext().to_string());
+            let alias = Alias::generate(extension.to_string());
 
             if AliasRepo::create(&self.repo, &alias).await?.is_ok() {
                 self.alias = Some(alias.clone());
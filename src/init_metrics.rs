@@ -0,0 +1,79 @@
+use crate::{error::Error, queue, repo::QueueRepo};
+use actix_web::{web, App, HttpResponse, HttpServer};
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+use std::net::SocketAddr;
+
+// Seconds, covering quick cache hits through to slow video ingests
+const DURATION_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0,
+];
+
+pub(super) fn init_metrics<R>(address: SocketAddr, repo: R) -> color_eyre::Result<()>
+where
+    R: QueueRepo + Clone + Send + 'static,
+{
+    let handle = PrometheusBuilder::new()
+        .set_buckets(DURATION_BUCKETS)?
+        .install_recorder()?;
+
+    describe();
+
+    println!("Starting prometheus metrics on {address}");
+
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(web::Data::new(handle.clone()))
+            .app_data(web::Data::new(repo.clone()))
+            .route("/metrics", web::get().to(metrics::<R>))
+    })
+    .workers(1)
+    .bind(address)?
+    .run();
+
+    tracing::trace_span!(parent: None, "Spawn task").in_scope(|| actix_rt::spawn(server));
+
+    Ok(())
+}
+
+fn describe() {
+    metrics::describe_counter!("pict_rs_uploads_total", "Media successfully ingested");
+    metrics::describe_histogram!(
+        "pict_rs_upload_duration_seconds",
+        "Time taken to ingest media, including validation and preprocessing"
+    );
+    metrics::describe_counter!(
+        "pict_rs_ingest_failures_total",
+        "Media that failed to ingest, by error"
+    );
+    metrics::describe_counter!(
+        "pict_rs_process_total",
+        "Processed media requests, by whether the variant already existed"
+    );
+    metrics::describe_gauge!(
+        "pict_rs_queue_depth",
+        "Jobs waiting to be picked up, by queue"
+    );
+    metrics::describe_histogram!(
+        "pict_rs_process_semaphore_wait_seconds",
+        "Time spent waiting for permission to run ImageMagick or ffmpeg"
+    );
+    metrics::describe_counter!(
+        "pict_rs_served_bytes_total",
+        "Bytes of media served from the store"
+    );
+}
+
+async fn metrics<R: QueueRepo>(
+    handle: web::Data<PrometheusHandle>,
+    repo: web::Data<R>,
+) -> Result<HttpResponse, Error> {
+    // Queue depth lives in the repo, so it's read when scraped rather than tracked as jobs move
+    for queue in queue::QUEUES {
+        let length = repo.queue_length(queue).await?;
+        metrics::gauge!("pict_rs_queue_depth", length as f64, "queue" => *queue);
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(handle.render()))
+}
@@ -10,16 +10,22 @@ mod ffmpeg;
 mod file;
 mod generate;
 mod ingest;
+mod init_metrics;
 mod init_tracing;
 mod magick;
 mod middleware;
+mod negotiate;
+mod perceptual_hash;
+mod placeholder;
 mod process;
 mod processor;
 mod queue;
 mod range;
 mod repo;
 mod serde_str;
+mod signature;
 mod store;
+mod storyboard;
 mod stream;
 mod tmp_file;
 mod validate;
@@ -27,7 +33,9 @@ mod validate;
 use actix_form_data::{Field, Form, FormData, Multipart, Value};
 use actix_web::{
     guard,
-    http::header::{CacheControl, CacheDirective, LastModified, Range, ACCEPT_RANGES},
+    http::header::{
+        CacheControl, CacheDirective, HeaderValue, LastModified, Range, ACCEPT, ACCEPT_RANGES, VARY,
+    },
     web, App, HttpRequest, HttpResponse, HttpResponseBuilder, HttpServer,
 };
 use awc::Client;
@@ -51,18 +59,18 @@ use tracing_futures::Instrument;
 
 use self::{
     backgrounded::Backgrounded,
-    config::{Configuration, ImageFormat, Operation},
+    config::{AnimationFormat, Configuration, ImageFormat, Operation, TargetFormat},
     details::Details,
     either::Either,
     error::{Error, UploadError},
     ingest::Session,
     init_tracing::init_tracing,
-    magick::{details_hint, ValidInputType},
-    middleware::{Deadline, Internal},
+    magick::details_hint,
+    middleware::{Deadline, Internal, Signed},
     queue::queue_generate,
     repo::{
-        Alias, DeleteToken, FullRepo, HashRepo, IdentifierRepo, Repo, SettingsRepo, UploadId,
-        UploadResult,
+        Alias, CachedRepo, DeleteToken, FullRepo, HashRepo, IdentifierRepo, Repo, SettingsRepo,
+        UploadId, UploadResult, VariantAccessRepo,
     },
     serde_str::Serde,
     store::{
@@ -70,6 +78,7 @@ use self::{
         object_store::{ObjectStore, ObjectStoreConfig},
         Identifier, Store, StoreConfig,
     },
+    storyboard::{Manifest, Storyboard},
     stream::{StreamLimit, StreamTimeout},
 };
 
@@ -122,6 +131,53 @@ async fn ensure_details<R: FullRepo, S: Store + 'static>(
     }
 }
 
+/// Mark an alias as cached, expiring after the configured cache duration unless it is accessed
+async fn mark_cached<R: CachedRepo>(repo: &R, alias: &Alias) -> Result<(), Error> {
+    let expires_at =
+        time::OffsetDateTime::now_utc() + time::Duration::hours(CONFIG.media.cache_duration);
+
+    repo.mark_cached(alias, expires_at).await
+}
+
+/// Push back the expiry of a cached alias. Aliases that aren't cached are left alone
+async fn touch_cached<R: CachedRepo>(repo: &R, alias: &Alias) -> Result<(), Error> {
+    if repo.cached_expiry(alias).await?.is_some() {
+        mark_cached(repo, alias).await?;
+    }
+
+    Ok(())
+}
+
+/// Queue generation of the presets marked `eager` for newly ingested media
+///
+/// The media has already been stored by the time this runs, so failures are logged rather than
+/// failing the upload
+async fn queue_eager_variants<R: FullRepo>(repo: &R, alias: &Alias) {
+    for (name, preset) in &CONFIG.media.presets {
+        if !preset.eager {
+            continue;
+        }
+
+        let res = async {
+            let (format, alias, process_path, process_args) =
+                prepare_preset(repo, name, alias.clone()).await?;
+
+            queue_generate(repo, format, alias, process_path, process_args).await
+        }
+        .await;
+
+        if let Err(e) = res {
+            tracing::warn!("Failed to queue eager preset {name}: {}", format!("{e}"));
+        }
+    }
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+struct UploadQuery {
+    #[serde(default)]
+    cached: bool,
+}
+
 struct Upload<R: FullRepo + 'static, S: Store + 'static>(Value<Session<R, S>>);
 
 impl<R: FullRepo, S: Store + 'static> FormData for Upload<R, S> {
@@ -230,10 +286,11 @@ impl<R: FullRepo, S: Store + 'static> FormData for Import<R, S> {
 #[tracing::instrument(name = "Uploaded files", skip(value, repo, store))]
 async fn upload<R: FullRepo, S: Store + 'static>(
     Multipart(Upload(value)): Multipart<Upload<R, S>>,
+    query: web::Query<UploadQuery>,
     repo: web::Data<R>,
     store: web::Data<S>,
 ) -> Result<HttpResponse, Error> {
-    handle_upload(value, repo, store).await
+    handle_upload(value, query.cached, repo, store).await
 }
 
 /// Handle responding to succesful uploads
@@ -243,13 +300,14 @@ async fn import<R: FullRepo, S: Store + 'static>(
     repo: web::Data<R>,
     store: web::Data<S>,
 ) -> Result<HttpResponse, Error> {
-    handle_upload(value, repo, store).await
+    handle_upload(value, false, repo, store).await
 }
 
 /// Handle responding to succesful uploads
 #[tracing::instrument(name = "Uploaded files", skip(value, repo, store))]
 async fn handle_upload<R: FullRepo, S: Store + 'static>(
     value: Value<Session<R, S>>,
+    cached: bool,
     repo: web::Data<R>,
     store: web::Data<S>,
 ) -> Result<HttpResponse, Error> {
@@ -260,6 +318,7 @@ async fn handle_upload<R: FullRepo, S: Store + 'static>(
         .ok_or(UploadError::NoFiles)?;
 
     let mut files = Vec::new();
+    let mut aliases = Vec::new();
     let images = images
         .into_iter()
         .filter_map(|i| i.file())
@@ -268,8 +327,13 @@ async fn handle_upload<R: FullRepo, S: Store + 'static>(
     for image in &images {
         if let Some(alias) = image.result.alias() {
             tracing::debug!("Uploaded {} as {:?}", image.filename, alias);
+            aliases.push(alias.clone());
             let delete_token = image.result.delete_token().await?;
 
+            if cached {
+                mark_cached(&repo, alias).await?;
+            }
+
             let details = ensure_details(&repo, &store, alias).await?;
 
             files.push(serde_json::json!({
@@ -284,6 +348,10 @@ async fn handle_upload<R: FullRepo, S: Store + 'static>(
         image.result.disarm();
     }
 
+    for alias in &aliases {
+        queue_eager_variants(&**repo, alias).await;
+    }
+
     Ok(HttpResponse::Created().json(&serde_json::json!({
         "msg": "ok",
         "files": files
@@ -342,6 +410,7 @@ impl<R: FullRepo, S: Store + 'static> FormData for BackgroundedUpload<R, S> {
 #[tracing::instrument(name = "Uploaded files", skip(value, repo))]
 async fn upload_backgrounded<R: FullRepo, S: Store>(
     Multipart(BackgroundedUpload(value)): Multipart<BackgroundedUpload<R, S>>,
+    query: web::Query<UploadQuery>,
     repo: web::Data<R>,
 ) -> Result<HttpResponse, Error> {
     let images = value
@@ -364,7 +433,7 @@ async fn upload_backgrounded<R: FullRepo, S: Store>(
             .expect("Identifier exists")
             .to_bytes()?;
 
-        queue::queue_ingest(&repo, identifier, upload_id, None, true).await?;
+        queue::queue_ingest(&repo, identifier, upload_id, None, true, query.cached).await?;
 
         files.push(serde_json::json!({
             "upload_id": upload_id.to_string(),
@@ -381,6 +450,46 @@ async fn upload_backgrounded<R: FullRepo, S: Store>(
     })))
 }
 
+#[derive(Debug, serde::Deserialize)]
+struct ExistingQuery {
+    hash: String,
+
+    #[serde(default)]
+    cached: bool,
+}
+
+/// Create a new alias for media that's already been uploaded, identified by its hex SHA-256
+#[tracing::instrument(name = "Aliasing existing file", skip(repo, store))]
+async fn upload_existing<R: FullRepo + 'static, S: Store + 'static>(
+    query: web::Query<ExistingQuery>,
+    repo: web::Data<R>,
+    store: web::Data<S>,
+) -> Result<HttpResponse, Error> {
+    let hash = hex::decode(&query.hash).map_err(|_| UploadError::InvalidHash)?;
+
+    let mut session = ingest::ingest_existing::<R, S>(&repo, hash).await?;
+
+    let alias = session.alias().expect("alias should exist").to_owned();
+    let delete_token = session.delete_token().await?;
+
+    if query.cached {
+        mark_cached(&repo, &alias).await?;
+    }
+
+    let details = ensure_details(&repo, &store, &alias).await?;
+
+    session.disarm();
+
+    Ok(HttpResponse::Created().json(serde_json::json!({
+        "msg": "ok",
+        "files": [{
+            "file": alias.to_string(),
+            "delete_token": delete_token.to_string(),
+            "details": details,
+        }]
+    })))
+}
+
 #[derive(Debug, serde::Deserialize)]
 struct ClaimQuery {
     upload_id: Serde<UploadId>,
@@ -430,6 +539,9 @@ struct UrlQuery {
 
     #[serde(default)]
     backgrounded: bool,
+
+    #[serde(default)]
+    cached: bool,
 }
 
 /// download an image from a URL
@@ -451,15 +563,16 @@ async fn download<R: FullRepo + 'static, S: Store + 'static>(
         .limit((CONFIG.media.max_file_size * MEGABYTES) as u64);
 
     if query.backgrounded {
-        do_download_backgrounded(stream, repo, store).await
+        do_download_backgrounded(stream, query.cached, repo, store).await
     } else {
-        do_download_inline(stream, repo, store).await
+        do_download_inline(stream, query.cached, repo, store).await
     }
 }
 
 #[tracing::instrument(name = "Downloading file inline", skip(stream, repo, store))]
 async fn do_download_inline<R: FullRepo + 'static, S: Store + 'static>(
     stream: impl Stream<Item = Result<web::Bytes, Error>> + Unpin + 'static,
+    cached: bool,
     repo: web::Data<R>,
     store: web::Data<S>,
 ) -> Result<HttpResponse, Error> {
@@ -468,10 +581,16 @@ async fn do_download_inline<R: FullRepo + 'static, S: Store + 'static>(
     let alias = session.alias().expect("alias should exist").to_owned();
     let delete_token = session.delete_token().await?;
 
+    if cached {
+        mark_cached(&repo, &alias).await?;
+    }
+
     let details = ensure_details(&repo, &store, &alias).await?;
 
     session.disarm();
 
+    queue_eager_variants(&**repo, &alias).await;
+
     Ok(HttpResponse::Created().json(&serde_json::json!({
         "msg": "ok",
         "files": [{
@@ -485,6 +604,7 @@ async fn do_download_inline<R: FullRepo + 'static, S: Store + 'static>(
 #[tracing::instrument(name = "Downloading file in background", skip(stream, repo, store))]
 async fn do_download_backgrounded<R: FullRepo + 'static, S: Store + 'static>(
     stream: impl Stream<Item = Result<web::Bytes, Error>> + Unpin + 'static,
+    cached: bool,
     repo: web::Data<R>,
     store: web::Data<S>,
 ) -> Result<HttpResponse, Error> {
@@ -496,7 +616,7 @@ async fn do_download_backgrounded<R: FullRepo + 'static, S: Store + 'static>(
         .expect("Identifier exists")
         .to_bytes()?;
 
-    queue::queue_ingest(&repo, identifier, upload_id, None, true).await?;
+    queue::queue_ingest(&repo, identifier, upload_id, None, true, cached).await?;
 
     backgrounded.disarm();
 
@@ -526,10 +646,19 @@ async fn delete<R: FullRepo>(
 
 type ProcessQuery = Vec<(String, String)>;
 
-fn prepare_process(
+/// The extension that lets `process` pick a format from the request's `Accept` header
+const AUTO_EXT: &str = "auto";
+
+async fn prepare_process<R: FullRepo>(
+    repo: &R,
+    req: &HttpRequest,
     query: web::Query<ProcessQuery>,
     ext: &str,
-) -> Result<(ImageFormat, Alias, PathBuf, Vec<String>), Error> {
+) -> Result<(TargetFormat, Alias, PathBuf, Vec<String>), Error> {
+    if CONFIG.media.presets_only {
+        return Err(UploadError::PresetsOnly.into());
+    }
+
     let (alias, operations) =
         query
             .into_inner()
@@ -549,30 +678,130 @@ fn prepare_process(
 
     let alias = Alias::from_existing(&alias);
 
+    let animated = operations
+        .iter()
+        .any(|(k, v)| k == "animated" && v == "true");
+
     let operations = operations
         .into_iter()
-        .filter(|(k, _)| CONFIG.media.filters.contains(&k.to_lowercase()))
+        .filter(|(k, _)| {
+            CONFIG.media.filters.contains(&k.to_lowercase())
+                || processor::ENCODING_OPTIONS.contains(&k.as_str())
+        })
         .collect::<Vec<_>>();
 
-    let format = ext
-        .parse::<ImageFormat>()
-        .map_err(|_| UploadError::UnsupportedFormat)?;
+    let format = if animated {
+        TargetFormat::Animation {
+            animation: ext
+                .parse::<AnimationFormat>()
+                .map_err(|_| UploadError::UnsupportedFormat)?,
+        }
+    } else if ext == AUTO_EXT {
+        let accept = req
+            .headers()
+            .get(ACCEPT)
+            .and_then(|value| value.to_str().ok());
+
+        TargetFormat::Image(
+            negotiate::negotiate(accept, &CONFIG.media.auto_formats)
+                .ok_or(UploadError::UnsupportedFormat)?,
+        )
+    } else {
+        TargetFormat::Image(
+            ext.parse::<ImageFormat>()
+                .map_err(|_| UploadError::UnsupportedFormat)?,
+        )
+    };
+
+    prepare_operations(repo, alias, operations, format).await
+}
+
+/// Negotiated responses depend on the `Accept` header, so caches need to keep them apart
+fn vary_accept(ext: &str, mut response: HttpResponse) -> HttpResponse {
+    if ext == AUTO_EXT {
+        response
+            .headers_mut()
+            .insert(VARY, HeaderValue::from_static("accept"));
+    }
+
+    response
+}
+
+/// Presets are set by the server operator, so they aren't limited to the enabled filters
+async fn prepare_preset<R: FullRepo>(
+    repo: &R,
+    name: &str,
+    alias: Alias,
+) -> Result<(TargetFormat, Alias, PathBuf, Vec<String>), Error> {
+    let preset = CONFIG
+        .media
+        .presets
+        .get(name)
+        .ok_or(UploadError::MissingPreset)?;
+
+    let operations = preset.operations().ok_or(UploadError::ParsePath)?;
+
+    prepare_operations(repo, alias, operations, TargetFormat::Image(preset.format)).await
+}
 
-    let ext = format.to_string();
+async fn prepare_operations<R: FullRepo>(
+    repo: &R,
+    alias: Alias,
+    operations: Vec<(String, String)>,
+    format: TargetFormat,
+) -> Result<(TargetFormat, Alias, PathBuf, Vec<String>), Error> {
+    let operations = match format {
+        // Animations are encoded by ffmpeg, so ImageMagick's encoder settings would only change
+        // the variant path without changing the output
+        TargetFormat::Animation { .. } => operations
+            .into_iter()
+            .filter(|(k, _)| !processor::ENCODING_OPTIONS.contains(&k.as_str()))
+            .collect(),
+        TargetFormat::Image(_) => CONFIG.media.encoding.apply(operations),
+    };
 
-    let (thumbnail_path, thumbnail_args) = self::processor::build_chain(&operations, &ext)?;
+    let ext = format.to_path_segment();
+
+    let focal_point = repo.focal_point(&alias).await?;
+
+    let (thumbnail_path, thumbnail_args) =
+        self::processor::build_chain(&operations, &ext, focal_point)?;
 
     Ok((format, alias, thumbnail_path, thumbnail_args))
 }
 
-#[tracing::instrument(name = "Fetching derived details", skip(repo))]
+#[tracing::instrument(name = "Fetching derived details", skip(req, repo))]
 async fn process_details<R: FullRepo, S: Store>(
+    req: HttpRequest,
     query: web::Query<ProcessQuery>,
     ext: web::Path<String>,
     repo: web::Data<R>,
 ) -> Result<HttpResponse, Error> {
-    let (_, alias, thumbnail_path, _) = prepare_process(query, ext.as_str())?;
+    let (_, alias, thumbnail_path, _) = prepare_process(&repo, &req, query, ext.as_str()).await?;
+
+    let response = do_process_details::<R, S>(&repo, alias, thumbnail_path).await?;
+
+    Ok(vary_accept(&ext, response))
+}
+
+#[tracing::instrument(name = "Fetching preset details", skip(repo))]
+async fn preset_details<R: FullRepo, S: Store>(
+    path_entries: web::Path<(String, Serde<Alias>)>,
+    repo: web::Data<R>,
+) -> Result<HttpResponse, Error> {
+    let (name, alias) = path_entries.into_inner();
+
+    let (_, alias, thumbnail_path, _) =
+        prepare_preset(&repo, &name, Serde::into_inner(alias)).await?;
+
+    do_process_details::<R, S>(&repo, alias, thumbnail_path).await
+}
 
+async fn do_process_details<R: FullRepo, S: Store>(
+    repo: &R,
+    alias: Alias,
+    thumbnail_path: PathBuf,
+) -> Result<HttpResponse, Error> {
     let hash = repo.hash(&alias).await?;
     let identifier = repo
         .variant_identifier::<S::Identifier>(hash, thumbnail_path.to_string_lossy().to_string())
@@ -587,24 +816,81 @@ async fn process_details<R: FullRepo, S: Store>(
 }
 
 /// Process files
-#[tracing::instrument(name = "Serving processed image", skip(repo, store))]
+#[tracing::instrument(name = "Serving processed image", skip(req, repo, store))]
 async fn process<R: FullRepo, S: Store + 'static>(
+    req: HttpRequest,
     range: Option<web::Header<Range>>,
     query: web::Query<ProcessQuery>,
     ext: web::Path<String>,
     repo: web::Data<R>,
     store: web::Data<S>,
 ) -> Result<HttpResponse, Error> {
-    let (format, alias, thumbnail_path, thumbnail_args) = prepare_process(query, ext.as_str())?;
+    let (format, alias, thumbnail_path, thumbnail_args) =
+        prepare_process(&repo, &req, query, ext.as_str()).await?;
+
+    let response = do_process(
+        range,
+        format,
+        alias,
+        thumbnail_path,
+        thumbnail_args,
+        repo,
+        store,
+    )
+    .await?;
+
+    Ok(vary_accept(&ext, response))
+}
+
+/// Process files with a configured preset
+#[tracing::instrument(name = "Serving preset image", skip(repo, store))]
+async fn preset<R: FullRepo, S: Store + 'static>(
+    range: Option<web::Header<Range>>,
+    path_entries: web::Path<(String, Serde<Alias>)>,
+    repo: web::Data<R>,
+    store: web::Data<S>,
+) -> Result<HttpResponse, Error> {
+    let (name, alias) = path_entries.into_inner();
 
+    let (format, alias, thumbnail_path, thumbnail_args) =
+        prepare_preset(&repo, &name, Serde::into_inner(alias)).await?;
+
+    do_process(
+        range,
+        format,
+        alias,
+        thumbnail_path,
+        thumbnail_args,
+        repo,
+        store,
+    )
+    .await
+}
+
+async fn do_process<R: FullRepo, S: Store + 'static>(
+    range: Option<web::Header<Range>>,
+    format: TargetFormat,
+    alias: Alias,
+    thumbnail_path: PathBuf,
+    thumbnail_args: Vec<String>,
+    repo: web::Data<R>,
+    store: web::Data<S>,
+) -> Result<HttpResponse, Error> {
     let path_string = thumbnail_path.to_string_lossy().to_string();
     let hash = repo.hash(&alias).await?;
 
+    touch_cached(&repo, &alias).await?;
+
     let identifier_opt = repo
-        .variant_identifier::<S::Identifier>(hash.clone(), path_string)
+        .variant_identifier::<S::Identifier>(hash.clone(), path_string.clone())
         .await?;
 
     if let Some(identifier) = identifier_opt {
+        metrics::increment_counter!("pict_rs_process_total", "result" => "hit");
+
+        repo.mark_accessed(hash, path_string, time::OffsetDateTime::now_utc())
+            .await?;
+
         let details = repo.details(&identifier).await?;
 
         let details = if let Some(details) = details {
@@ -615,7 +901,7 @@ async fn process<R: FullRepo, S: Store + 'static>(
             let new_details = Details::from_store(
                 (**store).clone(),
                 identifier.clone(),
-                Some(ValidInputType::from_format(format)),
+                Some(format.as_hint()),
             )
             .await?;
             tracing::debug!("storing details for {:?}", identifier);
@@ -627,6 +913,8 @@ async fn process<R: FullRepo, S: Store + 'static>(
         return ranged_file_resp(&store, identifier, range, details).await;
     }
 
+    metrics::increment_counter!("pict_rs_process_total", "result" => "generated");
+
     let original_details = ensure_details(&repo, &store, &alias).await?;
 
     let (details, bytes) = generate::generate(
@@ -674,23 +962,58 @@ async fn process<R: FullRepo, S: Store + 'static>(
     ))
 }
 
-#[tracing::instrument(name = "Serving processed image headers", skip(repo, store))]
+#[tracing::instrument(name = "Serving processed image headers", skip(req, repo, store))]
 async fn process_head<R: FullRepo, S: Store + 'static>(
+    req: HttpRequest,
     range: Option<web::Header<Range>>,
     query: web::Query<ProcessQuery>,
     ext: web::Path<String>,
     repo: web::Data<R>,
     store: web::Data<S>,
 ) -> Result<HttpResponse, Error> {
-    let (format, alias, thumbnail_path, _) = prepare_process(query, ext.as_str())?;
+    let (format, alias, thumbnail_path, _) =
+        prepare_process(&repo, &req, query, ext.as_str()).await?;
+
+    let response = do_process_head(range, format, alias, thumbnail_path, repo, store).await?;
+
+    Ok(vary_accept(&ext, response))
+}
+
+#[tracing::instrument(name = "Serving preset image headers", skip(repo, store))]
+async fn preset_head<R: FullRepo, S: Store + 'static>(
+    range: Option<web::Header<Range>>,
+    path_entries: web::Path<(String, Serde<Alias>)>,
+    repo: web::Data<R>,
+    store: web::Data<S>,
+) -> Result<HttpResponse, Error> {
+    let (name, alias) = path_entries.into_inner();
+
+    let (format, alias, thumbnail_path, _) =
+        prepare_preset(&repo, &name, Serde::into_inner(alias)).await?;
 
+    do_process_head(range, format, alias, thumbnail_path, repo, store).await
+}
+
+async fn do_process_head<R: FullRepo, S: Store + 'static>(
+    range: Option<web::Header<Range>>,
+    format: TargetFormat,
+    alias: Alias,
+    thumbnail_path: PathBuf,
+    repo: web::Data<R>,
+    store: web::Data<S>,
+) -> Result<HttpResponse, Error> {
     let path_string = thumbnail_path.to_string_lossy().to_string();
     let hash = repo.hash(&alias).await?;
+
+    touch_cached(&repo, &alias).await?;
     let identifier_opt = repo
-        .variant_identifier::<S::Identifier>(hash.clone(), path_string)
+        .variant_identifier::<S::Identifier>(hash.clone(), path_string.clone())
         .await?;
 
     if let Some(identifier) = identifier_opt {
+        repo.mark_accessed(hash, path_string, time::OffsetDateTime::now_utc())
+            .await?;
+
         let details = repo.details(&identifier).await?;
 
         let details = if let Some(details) = details {
@@ -701,7 +1024,7 @@ async fn process_head<R: FullRepo, S: Store + 'static>(
             let new_details = Details::from_store(
                 (**store).clone(),
                 identifier.clone(),
-                Some(ValidInputType::from_format(format)),
+                Some(format.as_hint()),
             )
             .await?;
             tracing::debug!("storing details for {:?}", identifier);
@@ -716,14 +1039,140 @@ async fn process_head<R: FullRepo, S: Store + 'static>(
     Ok(HttpResponse::NotFound().finish())
 }
 
+#[derive(Debug, serde::Deserialize)]
+struct StoryboardQuery {
+    src: Serde<Alias>,
+    frames: Option<u32>,
+    width: Option<u32>,
+}
+
+fn prepare_storyboard(query: StoryboardQuery, ext: &str) -> Result<(Alias, Storyboard), Error> {
+    if CONFIG.media.presets_only {
+        return Err(UploadError::PresetsOnly.into());
+    }
+
+    let format = ext
+        .parse::<ImageFormat>()
+        .map_err(|_| UploadError::UnsupportedFormat)?;
+
+    let storyboard = Storyboard::new(query.frames, query.width, format)?;
+
+    Ok((Serde::into_inner(query.src), storyboard))
+}
+
+/// Serve a sprite of frames sampled from a gif or video
+#[tracing::instrument(name = "Serving storyboard", skip(repo, store))]
+async fn storyboard<R: FullRepo, S: Store + 'static>(
+    range: Option<web::Header<Range>>,
+    query: web::Query<StoryboardQuery>,
+    ext: web::Path<String>,
+    repo: web::Data<R>,
+    store: web::Data<S>,
+) -> Result<HttpResponse, Error> {
+    let (alias, storyboard) = prepare_storyboard(query.into_inner(), ext.as_str())?;
+
+    let (identifier, _) = ensure_storyboard(&repo, &store, &alias, storyboard).await?;
+
+    let details = if let Some(details) = repo.details(&identifier).await? {
+        details
+    } else {
+        let new_details = Details::from_store(
+            (**store).clone(),
+            identifier.clone(),
+            Some(storyboard.format().as_hint()),
+        )
+        .await?;
+        repo.relate_details(&identifier, &new_details).await?;
+        new_details
+    };
+
+    ranged_file_resp(&store, identifier, range, details).await
+}
+
+/// Describe where each frame sits in a storyboard sprite
+#[tracing::instrument(name = "Serving storyboard manifest", skip(repo, store))]
+async fn storyboard_manifest<R: FullRepo, S: Store + 'static>(
+    query: web::Query<StoryboardQuery>,
+    ext: web::Path<String>,
+    repo: web::Data<R>,
+    store: web::Data<S>,
+) -> Result<HttpResponse, Error> {
+    let (alias, storyboard) = prepare_storyboard(query.into_inner(), ext.as_str())?;
+
+    let (_, identifier) = ensure_storyboard(&repo, &store, &alias, storyboard).await?;
+
+    let mut bytes = Vec::new();
+    store.read_into(&identifier, &mut bytes).await?;
+    let manifest: Manifest = serde_json::from_slice(&bytes)?;
+
+    Ok(HttpResponse::Ok().json(&manifest))
+}
+
+/// Find the sprite and manifest of a storyboard, generating both if either is missing
+async fn ensure_storyboard<R: FullRepo, S: Store + 'static>(
+    repo: &R,
+    store: &S,
+    alias: &Alias,
+    storyboard: Storyboard,
+) -> Result<(S::Identifier, S::Identifier), Error> {
+    let hash = repo.hash(alias).await?;
+    let sprite_path = storyboard.sprite_path().to_string_lossy().to_string();
+    let manifest_path = storyboard.manifest_path().to_string_lossy().to_string();
+
+    touch_cached(repo, alias).await?;
+
+    let sprite = repo
+        .variant_identifier::<S::Identifier>(hash.clone(), sprite_path.clone())
+        .await?;
+    let manifest = repo
+        .variant_identifier::<S::Identifier>(hash.clone(), manifest_path.clone())
+        .await?;
+
+    if let (Some(sprite), Some(manifest)) = (sprite, manifest) {
+        metrics::increment_counter!("pict_rs_storyboard_total", "result" => "hit");
+
+        let now = time::OffsetDateTime::now_utc();
+        repo.mark_accessed(hash.clone(), sprite_path, now).await?;
+        repo.mark_accessed(hash, manifest_path, now).await?;
+
+        return Ok((sprite, manifest));
+    }
+
+    metrics::increment_counter!("pict_rs_storyboard_total", "result" => "generated");
+
+    let original_details = ensure_details(repo, store, alias).await?;
+
+    storyboard::generate(
+        repo,
+        store,
+        storyboard,
+        original_details.to_input_format(),
+        hash.clone(),
+    )
+    .await?;
+
+    let sprite = repo
+        .variant_identifier::<S::Identifier>(hash.clone(), sprite_path)
+        .await?
+        .ok_or(UploadError::MissingAlias)?;
+    let manifest = repo
+        .variant_identifier::<S::Identifier>(hash, manifest_path)
+        .await?
+        .ok_or(UploadError::MissingAlias)?;
+
+    Ok((sprite, manifest))
+}
+
 /// Process files
-#[tracing::instrument(name = "Spawning image process", skip(repo))]
+#[tracing::instrument(name = "Spawning image process", skip(req, repo))]
 async fn process_backgrounded<R: FullRepo, S: Store>(
+    req: HttpRequest,
     query: web::Query<ProcessQuery>,
     ext: web::Path<String>,
     repo: web::Data<R>,
 ) -> Result<HttpResponse, Error> {
-    let (target_format, source, process_path, process_args) = prepare_process(query, ext.as_str())?;
+    let (target_format, source, process_path, process_args) =
+        prepare_process(&repo, &req, query, ext.as_str()).await?;
 
     let path_string = process_path.to_string_lossy().to_string();
     let hash = repo.hash(&source).await?;
@@ -766,6 +1215,8 @@ async fn serve<R: FullRepo, S: Store + 'static>(
 
     let identifier = repo.identifier_from_alias::<S::Identifier>(&alias).await?;
 
+    touch_cached(&repo, &alias).await?;
+
     let details = ensure_details(&repo, &store, &alias).await?;
 
     ranged_file_resp(&store, identifier, range, details).await
@@ -782,6 +1233,8 @@ async fn serve_head<R: FullRepo, S: Store + 'static>(
 
     let identifier = repo.identifier_from_alias::<S::Identifier>(&alias).await?;
 
+    touch_cached(&repo, &alias).await?;
+
     let details = ensure_details(&repo, &store, &alias).await?;
 
     ranged_file_head_resp(&store, identifier, range, details).await
@@ -862,6 +1315,12 @@ async fn ranged_file_resp<S: Store + 'static>(
         (HttpResponse::Ok(), Either::right(stream))
     };
 
+    let stream = stream.inspect(|res| {
+        if let Ok(bytes) = res {
+            metrics::counter!("pict_rs_served_bytes_total", bytes.len() as u64);
+        }
+    });
+
     Ok(srv_response(
         builder,
         stream,
@@ -919,20 +1378,69 @@ async fn clean_variants<R: FullRepo>(repo: web::Data<R>) -> Result<HttpResponse,
     Ok(HttpResponse::NoContent().finish())
 }
 
+#[tracing::instrument(name = "Spawning details backfill", skip(repo))]
+async fn backfill_details<R: FullRepo>(repo: web::Data<R>) -> Result<HttpResponse, Error> {
+    queue::queue_backfill_details(&repo).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct OrphanQuery {
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[tracing::instrument(name = "Cleaning orphaned files", skip(repo, store))]
+async fn clean_orphans<R: FullRepo, S: Store>(
+    query: web::Query<OrphanQuery>,
+    repo: web::Data<R>,
+    store: web::Data<S>,
+) -> Result<HttpResponse, Error> {
+    if !query.dry_run {
+        queue::cleanup_orphans(&repo).await?;
+        return Ok(HttpResponse::NoContent().finish());
+    }
+
+    let orphans = queue::find_orphans(&repo, &store)
+        .await?
+        .iter()
+        .map(Identifier::string_repr)
+        .collect::<Vec<_>>();
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "msg": "ok",
+        "count": orphans.len(),
+        "orphans": orphans,
+    })))
+}
+
 #[derive(Debug, serde::Deserialize)]
 struct AliasQuery {
     alias: Serde<Alias>,
 }
 
+#[derive(Debug, serde::Deserialize)]
+struct PurgeQuery {
+    alias: Serde<Alias>,
+
+    #[serde(default)]
+    block: bool,
+}
+
 #[tracing::instrument(name = "Purging file", skip(repo))]
 async fn purge<R: FullRepo>(
-    query: web::Query<AliasQuery>,
+    query: web::Query<PurgeQuery>,
     repo: web::Data<R>,
 ) -> Result<HttpResponse, Error> {
-    let alias = query.into_inner().alias;
+    let PurgeQuery { alias, block } = query.into_inner();
     let aliases = repo.aliases_from_alias(&alias).await?;
 
     let hash = repo.hash(&alias).await?;
+
+    if block {
+        repo.block(hash.clone()).await?;
+    }
+
     queue::cleanup_hash(&repo, hash).await?;
 
     Ok(HttpResponse::Ok().json(&serde_json::json!({
@@ -955,6 +1463,413 @@ async fn aliases<R: FullRepo>(
     })))
 }
 
+const MAX_HASHES_LIMIT: usize = 100;
+// Filters can skip most hashes, so a page stops early rather than walking the whole repo
+const MAX_HASHES_SCANNED: usize = 1000;
+
+fn default_hashes_limit() -> usize {
+    20
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct HashesQuery {
+    /// hex-encoded hash to continue listing after
+    after: Option<String>,
+
+    #[serde(default = "default_hashes_limit")]
+    limit: usize,
+
+    /// Either a full media type like `image/png`, or a top-level type like `video`
+    content_type: Option<String>,
+
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    created_after: Option<time::OffsetDateTime>,
+
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    created_before: Option<time::OffsetDateTime>,
+}
+
+impl HashesQuery {
+    fn is_filtered(&self) -> bool {
+        self.content_type.is_some() || self.created_after.is_some() || self.created_before.is_some()
+    }
+
+    fn matches(&self, details: &Details) -> bool {
+        if let Some(content_type) = &self.content_type {
+            let mime = details.content_type();
+
+            let matches = if content_type.contains('/') {
+                mime.essence_str() == content_type
+            } else {
+                mime.type_() == content_type.as_str()
+            };
+
+            if !matches {
+                return false;
+            }
+        }
+
+        let created_at = details.created_at();
+
+        self.created_after
+            .map(|after| created_at >= after)
+            .unwrap_or(true)
+            && self
+                .created_before
+                .map(|before| created_at < before)
+                .unwrap_or(true)
+    }
+}
+
+/// List every hash in the repo, along with what's stored for it
+#[tracing::instrument(name = "Listing hashes", skip(repo, store))]
+async fn hashes<R: FullRepo, S: Store>(
+    query: web::Query<HashesQuery>,
+    repo: web::Data<R>,
+    store: web::Data<S>,
+) -> Result<HttpResponse, Error> {
+    let query = query.into_inner();
+
+    let after = query
+        .after
+        .as_deref()
+        .map(hex::decode)
+        .transpose()
+        .map_err(|_| UploadError::InvalidHash)?;
+    let limit = query.limit.clamp(1, MAX_HASHES_LIMIT);
+
+    let mut entries = Vec::new();
+    let mut cursor = after.map(R::Bytes::from);
+    let mut scanned = 0;
+    let mut exhausted = false;
+
+    while entries.len() < limit && scanned < MAX_HASHES_SCANNED {
+        let batch_size = (limit - entries.len()).min(MAX_HASHES_SCANNED - scanned);
+        let batch = repo.hashes_after(cursor.clone(), batch_size).await?;
+
+        if batch.len() < batch_size {
+            exhausted = true;
+        }
+
+        for hash in batch {
+            scanned += 1;
+            cursor = Some(hash.clone());
+
+            if let Some(entry) = hash_entry::<R, S>(&repo, &store, &query, hash).await? {
+                entries.push(entry);
+            }
+        }
+
+        if exhausted {
+            break;
+        }
+    }
+
+    let next = if exhausted {
+        None
+    } else {
+        cursor.map(hex::encode)
+    };
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "msg": "ok",
+        "hashes": entries,
+        "next": next,
+    })))
+}
+
+async fn hash_entry<R: FullRepo, S: Store>(
+    repo: &R,
+    store: &S,
+    query: &HashesQuery,
+    hash: R::Bytes,
+) -> Result<Option<serde_json::Value>, Error> {
+    // Skip hashes whose ingest hasn't finished yet
+    let identifier = match repo.identifier::<S::Identifier>(hash.clone()).await {
+        Ok(identifier) => identifier,
+        Err(e) if e.is_missing() => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    let details = repo.details(&identifier).await?;
+
+    if query.is_filtered() && !details.as_ref().map(|d| query.matches(d)).unwrap_or(false) {
+        return Ok(None);
+    }
+
+    let aliases = repo.aliases(hash.clone()).await?;
+    let motion_identifier = repo
+        .motion_identifier::<S::Identifier>(hash.clone())
+        .await?;
+    let variants = repo.variants::<S::Identifier>(hash.clone()).await?;
+
+    let mut total_bytes = stored_size(store, &identifier, details.as_ref()).await?;
+    for identifier in motion_identifier
+        .iter()
+        .chain(variants.iter().map(|(_, identifier)| identifier))
+    {
+        let details = repo.details(identifier).await?;
+        total_bytes += stored_size(store, identifier, details.as_ref()).await?;
+    }
+
+    Ok(Some(serde_json::json!({
+        "hash": hex::encode(&hash),
+        "aliases": aliases.iter().map(|a| a.to_string()).collect::<Vec<_>>(),
+        "alias_count": aliases.len(),
+        "identifier": identifier.string_repr(),
+        "variant_count": variants.len(),
+        "total_bytes": total_bytes,
+        "details": details,
+    })))
+}
+
+/// Prefer the size recorded in details, only asking the store for files that don't have one
+async fn stored_size<S: Store>(
+    store: &S,
+    identifier: &S::Identifier,
+    details: Option<&Details>,
+) -> Result<u64, Error> {
+    if let Some(size) = details.and_then(Details::size) {
+        return Ok(size);
+    }
+
+    // A file missing from the store shouldn't fail the whole page
+    match store.len(identifier).await {
+        Ok(size) => Ok(size),
+        Err(e) => {
+            tracing::warn!("Couldn't get size for {:?}: {}", identifier, e);
+            Ok(0)
+        }
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct HashQuery {
+    hash: String,
+}
+
+/// Look up stored media by its hex SHA-256
+#[tracing::instrument(name = "Fetching hash", skip(repo, store))]
+async fn hash<R: FullRepo, S: Store + 'static>(
+    query: web::Query<HashQuery>,
+    repo: web::Data<R>,
+    store: web::Data<S>,
+) -> Result<HttpResponse, Error> {
+    let hash = hex::decode(&query.hash).map_err(|_| UploadError::InvalidHash)?;
+
+    let identifier = repo
+        .identifier::<S::Identifier>(hash.clone().into())
+        .await?;
+    let aliases = repo.aliases(hash.clone().into()).await?;
+    let perceptual_hash = repo.perceptual_hash(hash.clone().into()).await?;
+
+    let details = if let Some(alias) = aliases.first() {
+        Some(ensure_details(&repo, &store, alias).await?)
+    } else {
+        repo.details(&identifier).await?
+    };
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "msg": "ok",
+        "hash": hex::encode(&hash),
+        "aliases": aliases.iter().map(|a| a.to_string()).collect::<Vec<_>>(),
+        "identifier": identifier.string_repr(),
+        "perceptual_hash": perceptual_hash.map(|p| format!("{p:016x}")),
+        "details": details,
+    })))
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct BlocklistQuery {
+    hash: String,
+}
+
+/// Prevent media with the given hex SHA-256 from being uploaded
+#[tracing::instrument(name = "Blocking hash", skip(repo))]
+async fn block_hash<R: FullRepo>(
+    query: web::Query<BlocklistQuery>,
+    repo: web::Data<R>,
+) -> Result<HttpResponse, Error> {
+    let hash = hex::decode(&query.hash).map_err(|_| UploadError::InvalidHash)?;
+
+    repo.block(hash.into()).await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[tracing::instrument(name = "Unblocking hash", skip(repo))]
+async fn unblock_hash<R: FullRepo>(
+    query: web::Query<BlocklistQuery>,
+    repo: web::Data<R>,
+) -> Result<HttpResponse, Error> {
+    let hash = hex::decode(&query.hash).map_err(|_| UploadError::InvalidHash)?;
+
+    repo.unblock(hash.into()).await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[tracing::instrument(name = "Listing blocked hashes", skip(repo))]
+async fn blocklist<R: FullRepo>(repo: web::Data<R>) -> Result<HttpResponse, Error> {
+    let hashes = repo.blocked().await?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "msg": "ok",
+        "hashes": hashes.iter().map(hex::encode).collect::<Vec<_>>(),
+    })))
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct FocalPointQuery {
+    alias: String,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct SetFocalPointQuery {
+    alias: String,
+    x: f64,
+    y: f64,
+}
+
+#[tracing::instrument(name = "Fetching focal point", skip(repo))]
+async fn focal_point<R: FullRepo>(
+    query: web::Query<FocalPointQuery>,
+    repo: web::Data<R>,
+) -> Result<HttpResponse, Error> {
+    let alias = Alias::from_existing(&query.alias);
+
+    // Ensure the alias exists before reporting on it
+    repo.hash(&alias).await?;
+
+    let focal_point = repo.focal_point(&alias).await?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "msg": "ok",
+        "alias": alias.to_string(),
+        "focal_point": focal_point,
+    })))
+}
+
+/// Set the point that aspect crops and cover resizes of an alias are centered on
+#[tracing::instrument(name = "Setting focal point", skip(repo))]
+async fn set_focal_point<R: FullRepo>(
+    query: web::Query<SetFocalPointQuery>,
+    repo: web::Data<R>,
+) -> Result<HttpResponse, Error> {
+    let alias = Alias::from_existing(&query.alias);
+    let focal_point =
+        processor::FocalPoint::new(query.x, query.y).ok_or(UploadError::InvalidFocalPoint)?;
+
+    repo.hash(&alias).await?;
+    repo.relate_focal_point(&alias, focal_point).await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[tracing::instrument(name = "Removing focal point", skip(repo))]
+async fn remove_focal_point<R: FullRepo>(
+    query: web::Query<FocalPointQuery>,
+    repo: web::Data<R>,
+) -> Result<HttpResponse, Error> {
+    let alias = Alias::from_existing(&query.alias);
+
+    repo.hash(&alias).await?;
+    repo.remove_focal_point(&alias).await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+fn default_similar_distance() -> u32 {
+    10
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct SimilarQuery {
+    /// hex-encoded perceptual hash to compare against
+    perceptual_hash: Option<String>,
+
+    /// hex-encoded SHA-256 of stored media, whose perceptual hash is compared against
+    hash: Option<String>,
+
+    #[serde(default = "default_similar_distance")]
+    distance: u32,
+}
+
+/// Find stored media that looks like the given perceptual hash, or like already-stored media
+#[tracing::instrument(name = "Finding similar hashes", skip(repo))]
+async fn similar<R: FullRepo>(
+    query: web::Query<SimilarQuery>,
+    repo: web::Data<R>,
+) -> Result<HttpResponse, Error> {
+    let query = query.into_inner();
+
+    let perceptual_hash = match (query.perceptual_hash, query.hash) {
+        (Some(perceptual_hash), _) => {
+            u64::from_str_radix(&perceptual_hash, 16).map_err(|_| UploadError::InvalidHash)?
+        }
+        (None, Some(hash)) => {
+            let hash = hex::decode(hash).map_err(|_| UploadError::InvalidHash)?;
+
+            repo.perceptual_hash(hash.into())
+                .await?
+                .ok_or(UploadError::MissingPerceptualHash)?
+        }
+        (None, None) => return Err(UploadError::MissingPerceptualHash.into()),
+    };
+    let max_distance = query.distance.min(perceptual_hash::HASH_BITS);
+
+    let mut similar = repo.similar_hashes(perceptual_hash, max_distance).await?;
+    similar.sort_by_key(|(_, other)| perceptual_hash::distance(perceptual_hash, *other));
+
+    let mut matches = Vec::with_capacity(similar.len());
+
+    for (hash, other) in similar {
+        let aliases = repo.aliases(hash.clone()).await?;
+
+        matches.push(serde_json::json!({
+            "hash": hex::encode(&hash),
+            "perceptual_hash": format!("{other:016x}"),
+            "distance": perceptual_hash::distance(perceptual_hash, other),
+            "aliases": aliases.iter().map(|a| a.to_string()).collect::<Vec<_>>(),
+        }));
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "msg": "ok",
+        "perceptual_hash": format!("{perceptual_hash:016x}"),
+        "matches": matches,
+    })))
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct SignQuery {
+    url: String,
+}
+
+/// Sign a process URL so it's accepted when signing is enabled
+#[tracing::instrument(name = "Signing URL")]
+async fn sign_url(query: web::Query<SignQuery>) -> Result<HttpResponse, Error> {
+    let key = CONFIG
+        .server
+        .signing_key
+        .as_deref()
+        .ok_or(UploadError::SigningDisabled)?;
+
+    let (base, query_string) = query.url.split_once('?').unwrap_or((&query.url, ""));
+
+    // Only the path is signed, so full URLs can be served from any host
+    let path = url::Url::parse(base)
+        .map(|url| url.path().to_string())
+        .unwrap_or_else(|_| base.to_string());
+
+    let signed = signature::sign(key, &path, query_string);
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "msg": "ok",
+        "url": format!("{base}?{signed}"),
+    })))
+}
+
 #[tracing::instrument(name = "Fetching identifier", skip(repo))]
 async fn identifier<R: FullRepo, S: Store>(
     query: web::Query<AliasQuery>,
@@ -1003,6 +1918,13 @@ async fn launch<R: FullRepo + 'static, SC: StoreConfig + 'static>(
     repo.requeue_in_progress(CONFIG.server.worker_id.as_bytes().to_vec())
         .await?;
 
+    if let Some(address) = CONFIG.tracing.prometheus.address {
+        init_metrics::init_metrics(address, repo.clone())?;
+    }
+
+    tracing::trace_span!(parent: None, "Spawn task")
+        .in_scope(|| actix_rt::spawn(queue::schedule_cleanup(repo.clone())));
+
     HttpServer::new(move || {
         let store = store_config.clone().build();
         let repo = repo.clone();
@@ -1063,13 +1985,30 @@ async fn launch<R: FullRepo + 'static, SC: StoreConfig + 'static>(
                     )
                     .service(
                         web::resource("/process.{ext}")
+                            .wrap(Signed(CONFIG.server.signing_key.clone()))
                             .route(web::get().to(process::<R, SC::Store>))
                             .route(web::head().to(process_head::<R, SC::Store>)),
                     )
                     .service(
                         web::resource("/process_backgrounded.{ext}")
+                            .wrap(Signed(CONFIG.server.signing_key.clone()))
                             .route(web::get().to(process_backgrounded::<R, SC::Store>)),
                     )
+                    .service(
+                        web::resource("/storyboard.{ext}")
+                            .wrap(Signed(CONFIG.server.signing_key.clone()))
+                            .route(web::get().to(storyboard::<R, SC::Store>)),
+                    )
+                    .service(
+                        web::resource("/storyboard.{ext}/manifest")
+                            .wrap(Signed(CONFIG.server.signing_key.clone()))
+                            .route(web::get().to(storyboard_manifest::<R, SC::Store>)),
+                    )
+                    .service(
+                        web::resource("/preset/{name}/{filename}")
+                            .route(web::get().to(preset::<R, SC::Store>))
+                            .route(web::head().to(preset_head::<R, SC::Store>)),
+                    )
                     .service(
                         web::scope("/details")
                             .service(
@@ -1079,6 +2018,10 @@ async fn launch<R: FullRepo + 'static, SC: StoreConfig + 'static>(
                             .service(
                                 web::resource("/process.{ext}")
                                     .route(web::get().to(process_details::<R, SC::Store>)),
+                            )
+                            .service(
+                                web::resource("/preset/{name}/{filename}")
+                                    .route(web::get().to(preset_details::<R, SC::Store>)),
                             ),
                     ),
             )
@@ -1088,11 +2031,36 @@ async fn launch<R: FullRepo + 'static, SC: StoreConfig + 'static>(
                         CONFIG.server.api_key.as_ref().map(|s| s.to_owned()),
                     ))
                     .service(web::resource("/import").route(web::post().to(import::<R, SC::Store>)))
+                    .service(
+                        web::resource("/existing")
+                            .route(web::post().to(upload_existing::<R, SC::Store>)),
+                    )
                     .service(
                         web::resource("/variants").route(web::delete().to(clean_variants::<R>)),
                     )
+                    .service(
+                        web::resource("/orphans")
+                            .route(web::post().to(clean_orphans::<R, SC::Store>)),
+                    )
+                    .service(web::resource("/details").route(web::post().to(backfill_details::<R>)))
                     .service(web::resource("/purge").route(web::post().to(purge::<R>)))
                     .service(web::resource("/aliases").route(web::get().to(aliases::<R>)))
+                    .service(web::resource("/hashes").route(web::get().to(hashes::<R, SC::Store>)))
+                    .service(web::resource("/hash").route(web::get().to(hash::<R, SC::Store>)))
+                    .service(web::resource("/similar").route(web::get().to(similar::<R>)))
+                    .service(web::resource("/sign").route(web::get().to(sign_url)))
+                    .service(
+                        web::resource("/blocklist")
+                            .route(web::get().to(blocklist::<R>))
+                            .route(web::post().to(block_hash::<R>))
+                            .route(web::delete().to(unblock_hash::<R>)),
+                    )
+                    .service(
+                        web::resource("/focal_point")
+                            .route(web::get().to(focal_point::<R>))
+                            .route(web::post().to(set_focal_point::<R>))
+                            .route(web::delete().to(remove_focal_point::<R>)),
+                    )
                     .service(
                         web::resource("/identifier")
                             .route(web::get().to(identifier::<R, SC::Store>)),
@@ -1117,7 +2085,8 @@ where
             let to = FileStore::build(path.clone(), repo.clone()).await?.build();
 
             match repo {
-                Repo::Sled(repo) => migrate_store(repo, from, to).await?,
+                Repo::Sled(repo) => migrate_store(&**repo, from, to).await?,
+                Repo::Postgres(repo) => migrate_store(repo, from, to).await?,
             }
         }
         config::Store::ObjectStorage(config::ObjectStorage {
@@ -1147,7 +2116,8 @@ where
             .build();
 
             match repo {
-                Repo::Sled(repo) => migrate_store(repo, from, to).await?,
+                Repo::Sled(repo) => migrate_store(&**repo, from, to).await?,
+                Repo::Postgres(repo) => migrate_store(repo, from, to).await?,
             }
         }
     }
@@ -1214,11 +2184,21 @@ pub fn install_tracing() -> color_eyre::Result<()> {
 /// This must be called after `init_config`, or else the default configuration builder will run and
 /// fail.
 pub async fn run() -> color_eyre::Result<()> {
-    let repo = Repo::open(CONFIG.repo.clone())?;
+    if let Operation::MigrateRepo { from, to } = (*OPERATION).clone() {
+        let from = Repo::open(from).await?;
+        from.migrate_from_db(CONFIG.old_db.path.clone()).await?;
+
+        let to = Repo::open(to).await?;
+        from.migrate_to(&to).await?;
+
+        return Ok(());
+    }
+
+    let repo = Repo::open(CONFIG.repo.clone()).await?;
     repo.migrate_from_db(CONFIG.old_db.path.clone()).await?;
 
     match (*OPERATION).clone() {
-        Operation::Run => (),
+        Operation::Run | Operation::MigrateRepo { .. } => (),
         Operation::MigrateStore { from, to } => {
             match from {
                 config::Store::Filesystem(config::Filesystem { path }) => {
@@ -1265,7 +2245,8 @@ pub async fn run() -> color_eyre::Result<()> {
 
             let store = FileStore::build(path, repo.clone()).await?;
             match repo {
-                Repo::Sled(sled_repo) => launch::<_, FileStore>(sled_repo, store).await,
+                Repo::Sled(sled_repo) => launch::<_, FileStore>(*sled_repo, store).await,
+                Repo::Postgres(postgres_repo) => launch::<_, FileStore>(postgres_repo, store).await,
             }
         }
         config::Store::ObjectStorage(config::ObjectStorage {
@@ -1294,7 +2275,10 @@ pub async fn run() -> color_eyre::Result<()> {
             .await?;
 
             match repo {
-                Repo::Sled(sled_repo) => launch::<_, ObjectStoreConfig>(sled_repo, store).await,
+                Repo::Sled(sled_repo) => launch::<_, ObjectStoreConfig>(*sled_repo, store).await,
+                Repo::Postgres(postgres_repo) => {
+                    launch::<_, ObjectStoreConfig>(postgres_repo, store).await
+                }
             }
         }
     }
@@ -26,6 +26,14 @@ fn image_webp() -> mime::Mime {
     "image/webp".parse().unwrap()
 }
 
+fn image_avif() -> mime::Mime {
+    "image/avif".parse().unwrap()
+}
+
+fn image_jxl() -> mime::Mime {
+    "image/jxl".parse().unwrap()
+}
+
 pub(crate) fn video_mp4() -> mime::Mime {
     "video/mp4".parse().unwrap()
 }
@@ -42,6 +50,8 @@ pub(crate) enum ValidInputType {
     Png,
     Jpeg,
     Webp,
+    Avif,
+    Jxl,
 }
 
 impl ValidInputType {
@@ -53,6 +63,8 @@ impl ValidInputType {
             Self::Png => "PNG",
             Self::Jpeg => "JPEG",
             Self::Webp => "WEBP",
+            Self::Avif => "AVIF",
+            Self::Jxl => "JXL",
         }
     }
 
@@ -64,6 +76,8 @@ impl ValidInputType {
             Self::Png => ".png",
             Self::Jpeg => ".jpeg",
             Self::Webp => ".webp",
+            Self::Avif => ".avif",
+            Self::Jxl => ".jxl",
         }
     }
 
@@ -92,6 +106,8 @@ impl ValidInputType {
             ImageFormat::Jpeg => ValidInputType::Jpeg,
             ImageFormat::Png => ValidInputType::Png,
             ImageFormat::Webp => ValidInputType::Webp,
+            ImageFormat::Avif => ValidInputType::Avif,
+            ImageFormat::Jxl => ValidInputType::Jxl,
         }
     }
 
@@ -100,19 +116,36 @@ impl ValidInputType {
             Self::Jpeg => Some(ImageFormat::Jpeg),
             Self::Png => Some(ImageFormat::Png),
             Self::Webp => Some(ImageFormat::Webp),
+            Self::Avif => Some(ImageFormat::Avif),
+            Self::Jxl => Some(ImageFormat::Jxl),
             _ => None,
         }
     }
 }
 
+/// What ImageMagick or ffprobe found out about a file. Fields after `frames` are only filled in
+/// when the tool that probed the file reports them
 #[derive(Debug)]
 pub(crate) struct Details {
     pub(crate) mime_type: mime::Mime,
     pub(crate) width: usize,
     pub(crate) height: usize,
     pub(crate) frames: Option<usize>,
+    /// In seconds
+    pub(crate) duration: Option<f64>,
+    pub(crate) frame_rate: Option<f64>,
+    pub(crate) video_codec: Option<String>,
+    pub(crate) audio_codec: Option<String>,
+    /// In bits per second
+    pub(crate) bitrate: Option<u64>,
+    pub(crate) has_audio: Option<bool>,
+    pub(crate) color_space: Option<String>,
+    pub(crate) orientation: Option<String>,
 }
 
+// One line per frame
+const DETAILS_FORMAT: &str = "%w %h | %m | %[colorspace] | %[orientation]\n";
+
 #[tracing::instrument(level = "debug", skip(input))]
 pub(crate) fn convert_bytes_read(
     input: Bytes,
@@ -157,7 +190,7 @@ pub(crate) async fn details_bytes(
 
     let process = Process::run(
         "magick",
-        &["identify", "-ping", "-format", "%w %h | %m\n", &last_arg],
+        &["identify", "-ping", "-format", DETAILS_FORMAT, &last_arg],
     )?;
 
     let mut reader = process.bytes_read(input);
@@ -197,7 +230,7 @@ pub(crate) async fn details_store<S: Store + 'static>(
 
     let process = Process::run(
         "magick",
-        &["identify", "-ping", "-format", "%w %h | %m\n", &last_arg],
+        &["identify", "-ping", "-format", DETAILS_FORMAT, &last_arg],
     )?;
 
     let mut reader = process.store_read(store, identifier);
@@ -214,7 +247,7 @@ pub(crate) async fn details_store<S: Store + 'static>(
 pub(crate) async fn details_file(path_str: &str) -> Result<Details, Error> {
     let process = Process::run(
         "magick",
-        &["identify", "-ping", "-format", "%w %h | %m\n", path_str],
+        &["identify", "-ping", "-format", DETAILS_FORMAT, path_str],
     )?;
 
     let mut reader = process.read();
@@ -261,10 +294,13 @@ fn parse_details(s: std::borrow::Cow<'_, str>) -> Result<Details, Error> {
         .trim();
     tracing::debug!("format: {}", format);
 
-    if !lines.all(|item| item.ends_with(format)) {
+    if !lines.all(|item| item.split('|').nth(1).map(str::trim) == Some(format)) {
         return Err(UploadError::UnsupportedFormat.into());
     }
 
+    let color_space = segments.next().map(str::trim).and_then(known);
+    let orientation = segments.next().map(str::trim).and_then(known);
+
     let mime_type = match format {
         "MP4" => video_mp4(),
         "WEBM" => video_webm(),
@@ -272,6 +308,8 @@ fn parse_details(s: std::borrow::Cow<'_, str>) -> Result<Details, Error> {
         "PNG" => mime::IMAGE_PNG,
         "JPEG" => mime::IMAGE_JPEG,
         "WEBP" => image_webp(),
+        "AVIF" => image_avif(),
+        "JXL" => image_jxl(),
         _ => return Err(UploadError::UnsupportedFormat.into()),
     };
 
@@ -280,9 +318,25 @@ fn parse_details(s: std::borrow::Cow<'_, str>) -> Result<Details, Error> {
         width,
         height,
         frames: if frames > 1 { Some(frames) } else { None },
+        duration: None,
+        frame_rate: None,
+        video_codec: None,
+        audio_codec: None,
+        bitrate: None,
+        has_audio: None,
+        color_space,
+        orientation,
     })
 }
 
+// ImageMagick and ffprobe both report properties they couldn't find as some form of "unknown"
+pub(crate) fn known(value: &str) -> Option<String> {
+    match value {
+        "" | "Undefined" | "unknown" => None,
+        value => Some(value.to_string()),
+    }
+}
+
 pub(crate) async fn input_type_bytes(input: Bytes) -> Result<(Details, ValidInputType), Error> {
     let details = details_bytes(input, None).await?;
     let input_type = details.validate_input()?;
@@ -342,6 +396,8 @@ impl Details {
             (mime::IMAGE, mime::PNG) => ValidInputType::Png,
             (mime::IMAGE, mime::JPEG) => ValidInputType::Jpeg,
             (mime::IMAGE, subtype) if subtype.as_str() == "webp" => ValidInputType::Webp,
+            (mime::IMAGE, subtype) if subtype.as_str() == "avif" => ValidInputType::Avif,
+            (mime::IMAGE, subtype) if subtype.as_str() == "jxl" => ValidInputType::Jxl,
             _ => return Err(UploadError::UnsupportedFormat.into()),
         };
 
@@ -60,6 +60,26 @@ pin_project_lite::pin_project! {
     }
 }
 
+pub(crate) struct Signed(pub(crate) Option<String>);
+pub(crate) struct SignedMiddleware<S>(Option<String>, S);
+#[derive(Clone, Debug, thiserror::Error)]
+#[error("Invalid signature")]
+pub(crate) struct SignatureError;
+
+pin_project_lite::pin_project! {
+    #[project = SignedFutureProj]
+    #[project_replace = SignedFutureProjReplace]
+    pub(crate) enum SignedFuture<F> {
+        Signed {
+            #[pin]
+            future: F,
+        },
+        Error {
+            error: Option<SignatureError>,
+        },
+    }
+}
+
 impl ResponseError for ApiError {
     fn status_code(&self) -> StatusCode {
         StatusCode::UNAUTHORIZED
@@ -249,3 +269,82 @@ where
         }
     }
 }
+
+impl ResponseError for SignatureError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::FORBIDDEN
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code())
+            .content_type("application/json")
+            .body(
+                serde_json::to_string(&serde_json::json!({ "msg": self.to_string() }))
+                    .unwrap_or_else(|_| r#"{"msg":"forbidden"}"#.to_string()),
+            )
+    }
+}
+
+impl<S> Transform<S, ServiceRequest> for Signed
+where
+    S: Service<ServiceRequest, Error = actix_web::Error>,
+    S::Future: 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type InitError = ();
+    type Transform = SignedMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(SignedMiddleware(self.0.clone(), service)))
+    }
+}
+
+impl<S> Service<ServiceRequest> for SignedMiddleware<S>
+where
+    S: Service<ServiceRequest, Error = actix_web::Error>,
+    S::Future: 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = SignedFuture<S::Future>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.1.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        // Without a signing key, every request is allowed
+        let verified = self
+            .0
+            .as_deref()
+            .map(|key| crate::signature::verify(key, req.path(), req.query_string()))
+            .unwrap_or(true);
+
+        if verified {
+            SignedFuture::Signed {
+                future: self.1.call(req),
+            }
+        } else {
+            SignedFuture::Error {
+                error: Some(SignatureError),
+            }
+        }
+    }
+}
+
+impl<F, T, E> Future for SignedFuture<F>
+where
+    F: Future<Output = Result<T, E>>,
+    E: From<SignatureError>,
+{
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.as_mut().project() {
+            SignedFutureProj::Signed { future } => future.poll(cx),
+            SignedFutureProj::Error { error } => Poll::Ready(Err(error.take().unwrap().into())),
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use crate::config::ImageFormat;
+
+/// Pick the format to serve from a request's `Accept` header
+///
+/// `formats` are listed from most to least preferred. Wildcards like `image/*` don't count as
+/// accepting a format, since browsers send them whether or not they can decode newer formats, so
+/// the last format is the fallback for clients that don't name any of the others
+pub(crate) fn negotiate(accept: Option<&str>, formats: &[ImageFormat]) -> Option<ImageFormat> {
+    let (fallback, preferred) = formats.split_last()?;
+
+    let accepted = accept.map(parse_accept).unwrap_or_default();
+
+    let mut best: Option<(ImageFormat, f32)> = None;
+
+    for format in preferred {
+        let mime = format!("image/{format}");
+
+        let quality = accepted
+            .iter()
+            .find(|(media_range, _)| media_range.eq_ignore_ascii_case(&mime))
+            .map_or(0.0, |(_, quality)| *quality);
+
+        // Ties go to the format listed first
+        if quality > best.map_or(0.0, |(_, best_quality)| best_quality) {
+            best = Some((*format, quality));
+        }
+    }
+
+    Some(best.map_or(*fallback, |(format, _)| format))
+}
+
+// Media ranges with their quality, ignoring any other parameters
+fn parse_accept(accept: &str) -> Vec<(&str, f32)> {
+    accept
+        .split(',')
+        .filter_map(|item| {
+            let mut params = item.split(';').map(str::trim);
+
+            let media_range = params.next().filter(|range| !range.is_empty())?;
+
+            let quality = params
+                .filter_map(|param| param.split_once('='))
+                .find(|(key, _)| key.trim().eq_ignore_ascii_case("q"))
+                .map_or(Some(1.0), |(_, value)| value.trim().parse::<f32>().ok())?;
+
+            Some((media_range, quality.clamp(0.0, 1.0)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::negotiate;
+    use crate::config::ImageFormat;
+
+    const FORMATS: &[ImageFormat] = &[ImageFormat::Avif, ImageFormat::Webp, ImageFormat::Jpeg];
+
+    #[test]
+    fn prefers_server_order() {
+        let accept = "image/avif,image/webp,image/apng,image/*,*/*;q=0.8";
+        assert_eq!(negotiate(Some(accept), FORMATS), Some(ImageFormat::Avif));
+
+        let accept = "image/webp,*/*";
+        assert_eq!(negotiate(Some(accept), FORMATS), Some(ImageFormat::Webp));
+    }
+
+    #[test]
+    fn respects_quality() {
+        let accept = "image/avif;q=0.5, image/webp";
+        assert_eq!(negotiate(Some(accept), FORMATS), Some(ImageFormat::Webp));
+
+        let accept = "image/avif;q=0, image/webp;q=0";
+        assert_eq!(negotiate(Some(accept), FORMATS), Some(ImageFormat::Jpeg));
+    }
+
+    #[test]
+    fn falls_back_without_explicit_formats() {
+        assert_eq!(negotiate(None, FORMATS), Some(ImageFormat::Jpeg));
+        assert_eq!(negotiate(Some("*/*"), FORMATS), Some(ImageFormat::Jpeg));
+        assert_eq!(negotiate(Some("image/*"), FORMATS), Some(ImageFormat::Jpeg));
+        assert_eq!(negotiate(Some(""), FORMATS), Some(ImageFormat::Jpeg));
+        assert_eq!(negotiate(Some("image/webp"), &[]), None);
+    }
+}
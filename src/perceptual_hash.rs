@@ -0,0 +1,115 @@
+use crate::{
+    either::Either,
+    error::{Error, UploadError},
+    ffmpeg::{FileFormat, ThumbnailFormat, ThumbnailFrame},
+    magick::ValidInputType,
+    process::Process,
+    store::Store,
+};
+use tokio::io::AsyncReadExt;
+
+// dHash compares each pixel with its neighbour to the right, so one extra column is sampled
+const WIDTH: usize = 9;
+const HEIGHT: usize = 8;
+
+/// The number of bits in a perceptual hash, and so the largest possible distance between two
+pub(crate) const HASH_BITS: u32 = 64;
+
+pub(crate) const fn distance(left: u64, right: u64) -> u32 {
+    (left ^ right).count_ones()
+}
+
+/// Compute a difference hash for stored media. Videos and animations are hashed by their first
+/// frame
+#[tracing::instrument(skip(store))]
+pub(crate) async fn perceptual_hash<S: Store + 'static>(
+    store: &S,
+    identifier: &S::Identifier,
+    input_type: ValidInputType,
+) -> Result<u64, Error> {
+    let thumbnail = match input_type.to_file_format() {
+        FileFormat::Video(format) => Some(
+            crate::ffmpeg::thumbnail(
+                store.clone(),
+                identifier.clone(),
+                format,
+                ThumbnailFormat::Jpeg,
+                ThumbnailFrame::First,
+            )
+            .await?,
+        ),
+        FileFormat::Image(_) => None,
+    };
+
+    let resize = format!("{WIDTH}x{HEIGHT}!");
+
+    let process = Process::run(
+        "magick",
+        &[
+            "convert",
+            "-",
+            "-auto-orient",
+            "-colorspace",
+            "Gray",
+            "-resize",
+            &resize,
+            "-depth",
+            "8",
+            "gray:-",
+        ],
+    )?;
+
+    let mut reader = if let Some(thumbnail) = thumbnail {
+        Either::left(process.pipe_async_read(thumbnail))
+    } else {
+        Either::right(process.store_read(store.clone(), identifier.clone()))
+    };
+
+    let mut pixels = Vec::with_capacity(WIDTH * HEIGHT);
+    reader.read_to_end(&mut pixels).await?;
+
+    if pixels.len() != WIDTH * HEIGHT {
+        return Err(UploadError::UnsupportedFormat.into());
+    }
+
+    Ok(dhash(&pixels))
+}
+
+fn dhash(pixels: &[u8]) -> u64 {
+    pixels
+        .chunks_exact(WIDTH)
+        .flat_map(|row| row.windows(2))
+        .fold(0, |hash, pair| (hash << 1) | u64::from(pair[0] < pair[1]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{dhash, distance, HEIGHT, WIDTH};
+
+    #[test]
+    fn gradients() {
+        let rising = (0..HEIGHT)
+            .flat_map(|_| (0..WIDTH).map(|x| x as u8 * 10))
+            .collect::<Vec<_>>();
+        let falling = rising.iter().map(|p| 255 - p).collect::<Vec<_>>();
+
+        assert_eq!(dhash(&rising), u64::MAX);
+        assert_eq!(dhash(&falling), 0);
+
+        // Uniformly brightening an image doesn't change its hash
+        let brighter = rising.iter().map(|p| p + 100).collect::<Vec<_>>();
+        assert_eq!(distance(dhash(&rising), dhash(&brighter)), 0);
+    }
+
+    #[test]
+    fn small_changes() {
+        let mut pixels = (0..HEIGHT)
+            .flat_map(|_| (0..WIDTH).map(|x| x as u8 * 10))
+            .collect::<Vec<_>>();
+        let original = dhash(&pixels);
+
+        pixels[0] = 200;
+
+        assert_eq!(distance(original, dhash(&pixels)), 1);
+    }
+}
@@ -0,0 +1,268 @@
+use crate::{
+    either::Either,
+    error::{Error, UploadError},
+    ffmpeg::{ThumbnailFormat, ThumbnailFrame, VideoFormat},
+    process::Process,
+    store::Store,
+};
+use actix_web::web::Bytes;
+use std::f64::consts::PI;
+use tokio::io::AsyncReadExt;
+
+// Placeholders are blurry by design, so a small sample of the image is enough
+const SIZE: usize = 32;
+
+const BASE83: &[u8; 83] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz#$%*+,-.:;=?@[]^_{|}~";
+
+/// A preview clients can show while media loads
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Placeholder {
+    /// A BlurHash of the first frame
+    pub(crate) blurhash: String,
+    /// The most common color of the first frame, as a hex RGB string
+    pub(crate) dominant_color: String,
+}
+
+/// Compute a placeholder for media that ImageMagick can read. Videos aren't supported
+pub(crate) async fn placeholder_bytes(
+    input: Bytes,
+    width: usize,
+    height: usize,
+) -> Result<Placeholder, Error> {
+    let pixels = read_pixels(sample()?.bytes_read(input)).await?;
+
+    Ok(placeholder(&pixels, width, height))
+}
+
+/// Compute a placeholder for stored media. Videos are sampled from their first frame
+#[tracing::instrument(skip(store))]
+pub(crate) async fn placeholder_store<S: Store + 'static>(
+    store: &S,
+    identifier: &S::Identifier,
+    video_format: Option<VideoFormat>,
+    width: usize,
+    height: usize,
+) -> Result<Placeholder, Error> {
+    let process = sample()?;
+
+    let reader = match video_format {
+        Some(VideoFormat::Mp4 | VideoFormat::Webm) => {
+            let thumbnail = crate::ffmpeg::thumbnail(
+                store.clone(),
+                identifier.clone(),
+                video_format.unwrap_or(VideoFormat::Mp4),
+                ThumbnailFormat::Jpeg,
+                ThumbnailFrame::First,
+            )
+            .await?;
+
+            Either::left(process.pipe_async_read(thumbnail))
+        }
+        // ImageMagick reads the first frame of a gif on its own
+        Some(VideoFormat::Gif) | None => {
+            Either::right(process.store_read(store.clone(), identifier.clone()))
+        }
+    };
+
+    let pixels = read_pixels(reader).await?;
+
+    Ok(placeholder(&pixels, width, height))
+}
+
+fn sample() -> std::io::Result<Process> {
+    let resize = format!("{SIZE}x{SIZE}!");
+
+    Process::run(
+        "magick",
+        &[
+            "convert",
+            "-[0]",
+            "-auto-orient",
+            "-resize",
+            &resize,
+            "-depth",
+            "8",
+            "rgb:-",
+        ],
+    )
+}
+
+async fn read_pixels(mut reader: impl tokio::io::AsyncRead + Unpin) -> Result<Vec<u8>, Error> {
+    let mut pixels = Vec::with_capacity(SIZE * SIZE * 3);
+    reader.read_to_end(&mut pixels).await?;
+
+    if pixels.len() != SIZE * SIZE * 3 {
+        return Err(UploadError::UnsupportedFormat.into());
+    }
+
+    Ok(pixels)
+}
+
+fn placeholder(pixels: &[u8], width: usize, height: usize) -> Placeholder {
+    // The sample is square, so the image's own shape decides where detail is kept
+    let (components_x, components_y) = if width >= height { (4, 3) } else { (3, 4) };
+
+    Placeholder {
+        blurhash: blurhash(pixels, SIZE, SIZE, components_x, components_y),
+        dominant_color: dominant_color(pixels),
+    }
+}
+
+/// Encode RGB pixels as a BlurHash, following the reference implementation at
+/// https://github.com/woltapp/blurhash
+fn blurhash(
+    pixels: &[u8],
+    width: usize,
+    height: usize,
+    components_x: usize,
+    components_y: usize,
+) -> String {
+    let mut factors = Vec::with_capacity(components_x * components_y);
+
+    for j in 0..components_y {
+        for i in 0..components_x {
+            let normalization = if i == 0 && j == 0 { 1.0 } else { 2.0 };
+
+            let mut factor = [0.0; 3];
+            for y in 0..height {
+                for x in 0..width {
+                    let basis = (PI * i as f64 * x as f64 / width as f64).cos()
+                        * (PI * j as f64 * y as f64 / height as f64).cos();
+
+                    let pixel = &pixels[(y * width + x) * 3..][..3];
+                    for (channel, value) in factor.iter_mut().zip(pixel) {
+                        *channel += basis * srgb_to_linear(*value);
+                    }
+                }
+            }
+
+            let scale = normalization / (width * height) as f64;
+            factors.push(factor.map(|channel| channel * scale));
+        }
+    }
+
+    let (dc, ac) = factors.split_first().expect("At least one component");
+
+    let mut hash = String::new();
+    push_base83(&mut hash, (components_x - 1) + (components_y - 1) * 9, 1);
+
+    let max_value = if ac.is_empty() {
+        push_base83(&mut hash, 0, 1);
+        1.0
+    } else {
+        let actual_max = ac
+            .iter()
+            .flatten()
+            .fold(0.0_f64, |max, value| max.max(value.abs()));
+        let quantized_max = (actual_max * 166.0 - 0.5).floor().clamp(0.0, 82.0) as usize;
+
+        push_base83(&mut hash, quantized_max, 1);
+        (quantized_max + 1) as f64 / 166.0
+    };
+
+    let [r, g, b] = dc.map(linear_to_srgb);
+    push_base83(&mut hash, (r << 16) + (g << 8) + b, 4);
+
+    for factor in ac {
+        let [r, g, b] = factor.map(|value| {
+            let value = value / max_value;
+            let value = value.signum() * value.abs().sqrt();
+
+            (value * 9.0 + 9.5).floor().clamp(0.0, 18.0) as usize
+        });
+
+        push_base83(&mut hash, r * 19 * 19 + g * 19 + b, 2);
+    }
+
+    hash
+}
+
+fn push_base83(hash: &mut String, value: usize, length: u32) {
+    for i in (0..length).rev() {
+        let digit = (value / 83_usize.pow(i)) % 83;
+        hash.push(char::from(BASE83[digit]));
+    }
+}
+
+fn srgb_to_linear(value: u8) -> f64 {
+    let value = f64::from(value) / 255.0;
+
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f64) -> usize {
+    let value = value.clamp(0.0, 1.0);
+
+    if value <= 0.003_130_8 {
+        (value * 12.92 * 255.0 + 0.5) as usize
+    } else {
+        ((1.055 * value.powf(1.0 / 2.4) - 0.055) * 255.0 + 0.5) as usize
+    }
+}
+
+/// Group pixels by color, ignoring the lowest bits of each channel, and average the largest group
+fn dominant_color(pixels: &[u8]) -> String {
+    let mut buckets = vec![(0_usize, [0_usize; 3]); 1 << 12];
+
+    for pixel in pixels.chunks_exact(3) {
+        let index = (usize::from(pixel[0] >> 4) << 8)
+            | (usize::from(pixel[1] >> 4) << 4)
+            | usize::from(pixel[2] >> 4);
+
+        let (count, sums) = &mut buckets[index];
+        *count += 1;
+        for (sum, value) in sums.iter_mut().zip(pixel) {
+            *sum += usize::from(*value);
+        }
+    }
+
+    let (count, [r, g, b]) = buckets
+        .into_iter()
+        .max_by_key(|(count, _)| *count)
+        .unwrap_or_default();
+    let count = count.max(1);
+
+    format!("#{:02x}{:02x}{:02x}", r / count, g / count, b / count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{blurhash, dominant_color};
+
+    #[test]
+    fn solid_blurhash() {
+        let black = vec![0; 8 * 8 * 3];
+        assert_eq!(blurhash(&black, 8, 8, 4, 3), "L00000fQfQfQfQfQfQfQfQfQfQfQ");
+
+        // The reference basis samples cos(PI * i * x / width) from x = 0, which sums to 1 rather
+        // than 0 for odd components, so even a solid color has some AC. For white those factors
+        // are 2 * 8 / 64 = 0.25 along one axis and 2 / 64 along both, giving a maximum of 'f'
+        let white = vec![255; 8 * 8 * 3];
+        assert_eq!(blurhash(&white, 8, 8, 4, 3), "LfTSUA~qfQ~q~qt7fQt7fQfQfQfQ");
+    }
+
+    #[test]
+    fn blurhash_lengths() {
+        let gradient = (0..16 * 16)
+            .flat_map(|i| [(i % 16) as u8 * 16, (i / 16) as u8 * 16, 128])
+            .collect::<Vec<_>>();
+
+        // One character for the size, one for the maximum, four for the average, and two for
+        // each other component
+        assert_eq!(blurhash(&gradient, 16, 16, 4, 3).len(), 1 + 1 + 4 + 2 * 11);
+        assert_eq!(blurhash(&gradient, 16, 16, 1, 1).len(), 1 + 1 + 4);
+    }
+
+    #[test]
+    fn dominant_colors() {
+        let mut pixels = [250, 10, 10].repeat(10);
+        pixels.extend([0, 0, 255].repeat(4));
+
+        assert_eq!(dominant_color(&pixels), "#fa0a0a");
+    }
+}
@@ -1,6 +1,49 @@
-use crate::error::{Error, UploadError};
+use crate::{
+    config::ChromaSubsampling,
+    error::{Error, UploadError},
+};
 use std::path::PathBuf;
 
+/// Overlays are read from temporary files written just before processing. Until then, the
+/// command refers to the overlay's alias behind this prefix
+pub(crate) const OVERLAY_PREFIX: &str = "pict-rs-overlay:";
+
+/// The aliases of any overlays a command depends on
+pub(crate) fn overlay_aliases(args: &[String]) -> impl Iterator<Item = &str> {
+    args.iter()
+        .filter_map(|arg| arg.strip_prefix(OVERLAY_PREFIX))
+}
+
+/// Gifs and videos are processed from a single frame, extracted before ImageMagick runs. Until
+/// then, the command carries the chosen position behind this prefix
+pub(crate) const FRAME_PREFIX: &str = "pict-rs-frame:";
+
+/// Split the chosen frame out of a command, leaving the arguments meant for ImageMagick
+pub(crate) fn take_frame_position(args: Vec<String>) -> (Option<FramePosition>, Vec<String>) {
+    let mut position = None;
+
+    let args = args
+        .into_iter()
+        .filter(|arg| match arg.strip_prefix(FRAME_PREFIX) {
+            Some(value) => {
+                position = FramePosition::parse(value);
+                false
+            }
+            None => true,
+        })
+        .collect();
+
+    (position, args)
+}
+
+/// Encoder settings are limited by `[media.encoding]` rather than the list of enabled filters
+pub(crate) const ENCODING_OPTIONS: &[&str] = &[
+    Quality::NAME,
+    Lossless::NAME,
+    Progressive::NAME,
+    Subsampling::NAME,
+];
+
 pub(crate) trait Processor {
     const NAME: &'static str;
 
@@ -10,6 +53,28 @@ pub(crate) trait Processor {
 
     fn path(&self, path: PathBuf) -> PathBuf;
     fn command(&self, args: Vec<String>) -> Vec<String>;
+
+    /// Processors that crop can center on a focal point stored for the image being processed
+    fn with_focal_point(self, _: Option<FocalPoint>) -> Self
+    where
+        Self: Sized,
+    {
+        self
+    }
+}
+
+/// A point of interest in an image, as fractions of its width and height from the top left
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub(crate) struct FocalPoint {
+    pub(crate) x: f64,
+    pub(crate) y: f64,
+}
+
+/// How far into a gif or video to take a frame from
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum FramePosition {
+    Seconds(f64),
+    Percent(f64),
 }
 
 pub(crate) struct Identity;
@@ -17,6 +82,7 @@ pub(crate) struct Thumbnail(usize);
 pub(crate) struct Resize {
     filter: Option<ResizeFilter>,
     kind: ResizeKind,
+    focal_point: Option<FocalPoint>,
 }
 #[derive(Copy, Clone)]
 pub(crate) enum ResizeFilter {
@@ -27,13 +93,72 @@ pub(crate) enum ResizeFilter {
     Mitchell,
     RobidouxSharp,
 }
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub(crate) enum ResizeKind {
     Bounds(usize),
     Area(usize),
+    Contain(usize, usize),
+    Cover(usize, usize),
+    Fill(usize, usize),
+    Pad(usize, usize, Color),
+}
+/// A hex RGB or RGBA color, stored without its leading `#`
+#[derive(Clone)]
+pub(crate) struct Color(String);
+pub(crate) enum Crop {
+    Aspect {
+        width: usize,
+        height: usize,
+        focal_point: Option<FocalPoint>,
+    },
+    Pixels {
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    },
+    Percent {
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+    },
 }
-pub(crate) struct Crop(usize, usize);
 pub(crate) struct Blur(f64);
+pub(crate) struct Rotate(u16);
+pub(crate) struct Flip;
+pub(crate) struct Flop;
+pub(crate) struct Frame(FramePosition);
+pub(crate) struct Grayscale;
+pub(crate) struct Sharpen(f64);
+pub(crate) struct Brightness(i8);
+pub(crate) struct Contrast(i8);
+pub(crate) struct Saturation(u8);
+pub(crate) struct AutoLevel;
+pub(crate) struct Overlay {
+    alias: String,
+    gravity: Gravity,
+    x: i32,
+    y: i32,
+    opacity: u8,
+    scale: Option<u8>,
+}
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Gravity {
+    Center,
+    North,
+    NorthEast,
+    East,
+    SouthEast,
+    South,
+    SouthWest,
+    West,
+    NorthWest,
+}
+pub(crate) struct Quality(u8);
+pub(crate) struct Lossless(bool);
+pub(crate) struct Progressive(bool);
+pub(crate) struct Subsampling(ChromaSubsampling);
 
 impl ResizeFilter {
     fn from_str(s: &str) -> Option<Self> {
@@ -66,8 +191,108 @@ impl Default for ResizeFilter {
     }
 }
 
+impl FocalPoint {
+    pub(crate) fn new(x: f64, y: f64) -> Option<Self> {
+        if (0.0..=1.0).contains(&x) && (0.0..=1.0).contains(&y) {
+            Some(FocalPoint { x, y })
+        } else {
+            None
+        }
+    }
+
+    fn to_path_string(self) -> String {
+        format!("focus-{:.3}x{:.3}", self.x, self.y)
+    }
+
+    /// Crop to a `width` by `height` region centered as close to the focal point as the image
+    /// allows. The sizes are fx expressions, so they can depend on the dimensions of the image
+    fn crop_args(self, width: &str, height: &str) -> Vec<String> {
+        let x = format!("max(0,min(w-({width}),{:.3}*w-({width})/2))", self.x);
+        let y = format!("max(0,min(h-({height}),{:.3}*h-({height})/2))", self.y);
+
+        viewport_args(width, height, &x, &y)
+    }
+}
+
+/// Crop to a region given as fx expressions, which plain geometry arguments don't support
+fn viewport_args(width: &str, height: &str, x: &str, y: &str) -> Vec<String> {
+    vec![
+        "-set".to_string(),
+        "option:distort:viewport".to_string(),
+        format!("%[fx:round({width})]x%[fx:round({height})]+%[fx:round({x})]+%[fx:round({y})]"),
+        "-filter".to_string(),
+        "point".to_string(),
+        "-distort".to_string(),
+        "SRT".to_string(),
+        "0".to_string(),
+        "+repage".to_string(),
+    ]
+}
+
+// Matches the width and height limits in the imagemagick policy
+const MAX_DIMENSION: usize = 10_000;
+
+fn parse_dimensions(s: &str) -> Option<(usize, usize)> {
+    let (width, height) = s.split_once('x')?;
+
+    let width = width.parse().ok()?;
+    let height = height.parse().ok()?;
+
+    if width == 0 || height == 0 || width > MAX_DIMENSION || height > MAX_DIMENSION {
+        return None;
+    }
+
+    Some((width, height))
+}
+
+impl Color {
+    fn from_str(s: &str) -> Option<Self> {
+        if matches!(s.len(), 3 | 4 | 6 | 8) && s.chars().all(|c| c.is_ascii_hexdigit()) {
+            Some(Color(s.to_lowercase()))
+        } else {
+            None
+        }
+    }
+
+    fn to_magick_string(&self) -> String {
+        format!("#{}", self.0)
+    }
+}
+
+impl Default for Color {
+    fn default() -> Self {
+        Color(String::from("ffffff"))
+    }
+}
+
 impl ResizeKind {
     fn from_str(s: &str) -> Option<Self> {
+        if let Some((mode, rest)) = s.split_once('-') {
+            return match mode {
+                "contain" => {
+                    let (width, height) = parse_dimensions(rest)?;
+                    Some(ResizeKind::Contain(width, height))
+                }
+                "cover" => {
+                    let (width, height) = parse_dimensions(rest)?;
+                    Some(ResizeKind::Cover(width, height))
+                }
+                "fill" => {
+                    let (width, height) = parse_dimensions(rest)?;
+                    Some(ResizeKind::Fill(width, height))
+                }
+                "pad" => {
+                    let (dimensions, color) = match rest.split_once('-') {
+                        Some((dimensions, color)) => (dimensions, Color::from_str(color)?),
+                        None => (rest, Color::default()),
+                    };
+                    let (width, height) = parse_dimensions(dimensions)?;
+                    Some(ResizeKind::Pad(width, height, color))
+                }
+                _ => None,
+            };
+        }
+
         let kind = if s.starts_with('a') {
             let size = s.trim_start_matches('a').parse().ok()?;
             ResizeKind::Area(size)
@@ -79,10 +304,55 @@ impl ResizeKind {
         Some(kind)
     }
 
-    fn to_magick_string(self) -> String {
+    fn to_path_string(&self) -> String {
         match self {
-            Self::Area(size) => format!("{size}@>"),
-            Self::Bounds(size) => format!("{size}x{size}>"),
+            Self::Bounds(size) => size.to_string(),
+            Self::Area(size) => format!("a{size}"),
+            Self::Contain(width, height) => format!("contain-{width}x{height}"),
+            Self::Cover(width, height) => format!("cover-{width}x{height}"),
+            Self::Fill(width, height) => format!("fill-{width}x{height}"),
+            // The color is always included, so an explicit default shares a variant with an
+            // implicit one
+            Self::Pad(width, height, color) => format!("pad-{width}x{height}-{}", color.0),
+        }
+    }
+
+    fn to_magick_args(&self, focal_point: Option<FocalPoint>) -> Vec<String> {
+        match self {
+            Self::Area(size) => vec!["-resize".to_string(), format!("{size}@>")],
+            Self::Bounds(size) => vec!["-resize".to_string(), format!("{size}x{size}>")],
+            Self::Contain(width, height) => {
+                vec!["-resize".to_string(), format!("{width}x{height}")]
+            }
+            Self::Cover(width, height) => {
+                let mut args = vec!["-resize".to_string(), format!("{width}x{height}^")];
+
+                if let Some(focal_point) = focal_point {
+                    args.extend(focal_point.crop_args(&width.to_string(), &height.to_string()));
+                } else {
+                    args.extend([
+                        "-gravity".to_string(),
+                        "center".to_string(),
+                        "-extent".to_string(),
+                        format!("{width}x{height}"),
+                    ]);
+                }
+
+                args
+            }
+            Self::Fill(width, height) => {
+                vec!["-resize".to_string(), format!("{width}x{height}!")]
+            }
+            Self::Pad(width, height, color) => vec![
+                "-resize".to_string(),
+                format!("{width}x{height}"),
+                "-background".to_string(),
+                color.to_magick_string(),
+                "-gravity".to_string(),
+                "center".to_string(),
+                "-extent".to_string(),
+                format!("{width}x{height}"),
+            ],
         }
     }
 }
@@ -91,6 +361,7 @@ impl ResizeKind {
 pub(crate) fn build_chain(
     args: &[(String, String)],
     ext: &str,
+    focal_point: Option<FocalPoint>,
 ) -> Result<(PathBuf, Vec<String>), Error> {
     fn parse<P: Processor>(key: &str, value: &str) -> Result<Option<P>, Error> {
         if key == P::NAME {
@@ -101,8 +372,9 @@ pub(crate) fn build_chain(
     }
 
     macro_rules! parse {
-        ($inner:expr, $x:ident, $k:expr, $v:expr) => {{
+        ($inner:expr, $x:ident, $k:expr, $v:expr, $focal_point:expr) => {{
             if let Some(processor) = parse::<$x>($k, $v)? {
+                let processor = processor.with_focal_point($focal_point);
                 return Ok((processor.path($inner.0), processor.command($inner.1)));
             };
         }};
@@ -112,11 +384,26 @@ pub(crate) fn build_chain(
         args.iter()
             .fold(Ok((PathBuf::default(), vec![])), |inner, (name, value)| {
                 if let Ok(inner) = inner {
-                    parse!(inner, Identity, name, value);
-                    parse!(inner, Thumbnail, name, value);
-                    parse!(inner, Resize, name, value);
-                    parse!(inner, Crop, name, value);
-                    parse!(inner, Blur, name, value);
+                    parse!(inner, Identity, name, value, focal_point);
+                    parse!(inner, Thumbnail, name, value, focal_point);
+                    parse!(inner, Resize, name, value, focal_point);
+                    parse!(inner, Crop, name, value, focal_point);
+                    parse!(inner, Blur, name, value, focal_point);
+                    parse!(inner, Rotate, name, value, focal_point);
+                    parse!(inner, Flip, name, value, focal_point);
+                    parse!(inner, Flop, name, value, focal_point);
+                    parse!(inner, Frame, name, value, focal_point);
+                    parse!(inner, Grayscale, name, value, focal_point);
+                    parse!(inner, Sharpen, name, value, focal_point);
+                    parse!(inner, Brightness, name, value, focal_point);
+                    parse!(inner, Contrast, name, value, focal_point);
+                    parse!(inner, Saturation, name, value, focal_point);
+                    parse!(inner, AutoLevel, name, value, focal_point);
+                    parse!(inner, Overlay, name, value, focal_point);
+                    parse!(inner, Quality, name, value, focal_point);
+                    parse!(inner, Lossless, name, value, focal_point);
+                    parse!(inner, Progressive, name, value, focal_point);
+                    parse!(inner, Subsampling, name, value, focal_point);
 
                     Err(Error::from(UploadError::ParsePath))
                 } else {
@@ -184,47 +471,36 @@ impl Processor for Resize {
 
             let kind = ResizeKind::from_str(second)?;
 
-            Some(Resize { filter, kind })
+            Some(Resize {
+                filter,
+                kind,
+                focal_point: None,
+            })
         } else {
-            let size = v.parse().ok()?;
+            let kind = ResizeKind::from_str(v)?;
+
             Some(Resize {
                 filter: None,
-                kind: ResizeKind::Bounds(size),
+                kind,
+                focal_point: None,
             })
         }
     }
 
     fn path(&self, mut path: PathBuf) -> PathBuf {
         path.push(Self::NAME);
-        match self {
-            Resize {
-                filter: None,
-                kind: ResizeKind::Bounds(size),
-            } => {
-                path.push(size.to_string());
-            }
-            Resize {
-                filter: None,
-                kind: ResizeKind::Area(size),
-            } => {
-                let node = format!(".a{size}");
-                path.push(node);
-            }
-            Resize {
-                filter: Some(filter),
-                kind: ResizeKind::Bounds(size),
-            } => {
-                let node = format!("{}.{size}", filter.to_magick_str());
-                path.push(node);
-            }
-            Resize {
-                filter: Some(filter),
-                kind: ResizeKind::Area(size),
-            } => {
-                let node = format!("{}.a{size}", filter.to_magick_str());
-                path.push(node);
-            }
+
+        let kind = self.kind.to_path_string();
+        match (self.filter, &self.kind) {
+            (None, ResizeKind::Bounds(_)) => path.push(kind),
+            (None, _) => path.push(format!(".{kind}")),
+            (Some(filter), _) => path.push(format!("{}.{kind}", filter.to_magick_str())),
         }
+
+        if let Some(focal_point) = self.focal_point {
+            path.push(focal_point.to_path_string());
+        }
+
         path
     }
 
@@ -232,18 +508,63 @@ impl Processor for Resize {
         args.extend([
             "-filter".to_string(),
             self.filter.unwrap_or_default().to_magick_str().to_string(),
-            "-resize".to_string(),
-            self.kind.to_magick_string(),
         ]);
+        args.extend(self.kind.to_magick_args(self.focal_point));
 
         args
     }
+
+    fn with_focal_point(self, focal_point: Option<FocalPoint>) -> Self {
+        // Only cover resizes crop anything
+        if let ResizeKind::Cover(_, _) = self.kind {
+            Resize {
+                focal_point,
+                ..self
+            }
+        } else {
+            self
+        }
+    }
 }
 
 impl Processor for Crop {
     const NAME: &'static str = "crop";
 
     fn parse(_: &str, v: &str) -> Option<Self> {
+        if let Some(rect) = v.strip_prefix("percent-") {
+            let [x, y, width, height] = parse_rect::<f64>(rect)?;
+
+            if x < 0.0 || y < 0.0 || width <= 0.0 || height <= 0.0 {
+                return None;
+            }
+
+            if x + width > 100.0 || y + height > 100.0 {
+                return None;
+            }
+
+            return Some(Crop::Percent {
+                x,
+                y,
+                width,
+                height,
+            });
+        }
+
+        if v.contains(',') {
+            let [x, y, width, height] = parse_rect::<usize>(v)?;
+
+            if width == 0 || height == 0 || width > MAX_DIMENSION || height > MAX_DIMENSION {
+                return None;
+            }
+
+            return Some(Crop::Pixels {
+                x,
+                y,
+                width,
+                height,
+            });
+        }
+
         let mut iter = v.split('x');
         let first = iter.next()?;
         let second = iter.next()?;
@@ -259,25 +580,112 @@ impl Processor for Crop {
             return None;
         }
 
-        Some(Crop(width, height))
+        Some(Crop::Aspect {
+            width,
+            height,
+            focal_point: None,
+        })
     }
 
     fn path(&self, mut path: PathBuf) -> PathBuf {
         path.push(Self::NAME);
-        path.push(format!("{}x{}", self.0, self.1));
+
+        match self {
+            Crop::Aspect {
+                width,
+                height,
+                focal_point,
+            } => {
+                path.push(format!("{width}x{height}"));
+
+                if let Some(focal_point) = focal_point {
+                    path.push(focal_point.to_path_string());
+                }
+            }
+            Crop::Pixels {
+                x,
+                y,
+                width,
+                height,
+            } => path.push(format!("{x},{y},{width},{height}")),
+            Crop::Percent {
+                x,
+                y,
+                width,
+                height,
+            } => path.push(format!("percent-{x},{y},{width},{height}")),
+        }
+
         path
     }
 
     fn command(&self, mut args: Vec<String>) -> Vec<String> {
-        args.extend([
-            "-gravity".to_string(),
-            "center".to_string(),
-            "-crop".to_string(),
-            format!("{}:{}+0+0", self.0, self.1),
-        ]);
+        match self {
+            Crop::Aspect {
+                width,
+                height,
+                focal_point: Some(focal_point),
+            } => args.extend(focal_point.crop_args(
+                &format!("min(w,h*{width}/{height})"),
+                &format!("min(h,w*{height}/{width})"),
+            )),
+            Crop::Aspect { width, height, .. } => args.extend([
+                "-gravity".to_string(),
+                "center".to_string(),
+                "-crop".to_string(),
+                format!("{width}:{height}+0+0"),
+            ]),
+            Crop::Pixels {
+                x,
+                y,
+                width,
+                height,
+            } => args.extend([
+                "-gravity".to_string(),
+                "northwest".to_string(),
+                "-crop".to_string(),
+                format!("{width}x{height}+{x}+{y}"),
+                "+repage".to_string(),
+            ]),
+            Crop::Percent {
+                x,
+                y,
+                width,
+                height,
+            } => args.extend(viewport_args(
+                &format!("w*{width}/100"),
+                &format!("h*{height}/100"),
+                &format!("w*{x}/100"),
+                &format!("h*{y}/100"),
+            )),
+        }
 
         args
     }
+
+    fn with_focal_point(self, focal_point: Option<FocalPoint>) -> Self {
+        if let Crop::Aspect { width, height, .. } = self {
+            Crop::Aspect {
+                width,
+                height,
+                focal_point,
+            }
+        } else {
+            self
+        }
+    }
+}
+
+fn parse_rect<T: std::str::FromStr>(s: &str) -> Option<[T; 4]> {
+    let mut iter = s.split(',').map(|part| part.parse().ok());
+
+    let rect = [iter.next()??, iter.next()??, iter.next()??, iter.next()??];
+
+    if iter.next().is_some() {
+        return None;
+    }
+
+    Some(rect)
 }
 
 impl Processor for Blur {
@@ -300,3 +708,688 @@ impl Processor for Blur {
         args
     }
 }
+
+impl Processor for Rotate {
+    const NAME: &'static str = "rotate";
+
+    fn parse(_: &str, v: &str) -> Option<Self> {
+        let degrees = v.parse().ok()?;
+
+        if ![90, 180, 270].contains(&degrees) {
+            return None;
+        }
+
+        Some(Rotate(degrees))
+    }
+
+    fn path(&self, mut path: PathBuf) -> PathBuf {
+        path.push(Self::NAME);
+        path.push(self.0.to_string());
+        path
+    }
+
+    fn command(&self, mut args: Vec<String>) -> Vec<String> {
+        args.extend(["-rotate".to_string(), self.0.to_string()]);
+
+        args
+    }
+}
+
+impl Processor for Flip {
+    const NAME: &'static str = "flip";
+
+    fn parse(_: &str, _: &str) -> Option<Self> {
+        Some(Flip)
+    }
+
+    fn path(&self, mut path: PathBuf) -> PathBuf {
+        path.push(Self::NAME);
+        path
+    }
+
+    fn command(&self, mut args: Vec<String>) -> Vec<String> {
+        args.push("-flip".to_string());
+
+        args
+    }
+}
+
+impl Processor for Flop {
+    const NAME: &'static str = "flop";
+
+    fn parse(_: &str, _: &str) -> Option<Self> {
+        Some(Flop)
+    }
+
+    fn path(&self, mut path: PathBuf) -> PathBuf {
+        path.push(Self::NAME);
+        path
+    }
+
+    fn command(&self, mut args: Vec<String>) -> Vec<String> {
+        args.push("-flop".to_string());
+
+        args
+    }
+}
+
+impl FramePosition {
+    fn parse(s: &str) -> Option<Self> {
+        if let Some(percent) = s.strip_prefix("percent-") {
+            let percent = percent.parse::<f64>().ok()?;
+
+            return (0.0..=100.0)
+                .contains(&percent)
+                .then_some(FramePosition::Percent(percent));
+        }
+
+        let seconds = s.parse::<f64>().ok()?;
+
+        (seconds.is_finite() && seconds >= 0.0).then_some(FramePosition::Seconds(seconds))
+    }
+}
+
+impl std::fmt::Display for FramePosition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Seconds(seconds) => write!(f, "{seconds}"),
+            Self::Percent(percent) => write!(f, "percent-{percent}"),
+        }
+    }
+}
+
+impl Processor for Frame {
+    const NAME: &'static str = "frame";
+
+    fn parse(_: &str, v: &str) -> Option<Self> {
+        FramePosition::parse(v).map(Frame)
+    }
+
+    fn path(&self, mut path: PathBuf) -> PathBuf {
+        path.push(Self::NAME);
+        path.push(self.0.to_string());
+        path
+    }
+
+    fn command(&self, mut args: Vec<String>) -> Vec<String> {
+        args.push(format!("{FRAME_PREFIX}{}", self.0));
+
+        args
+    }
+}
+
+impl Processor for Grayscale {
+    const NAME: &'static str = "grayscale";
+
+    fn parse(_: &str, _: &str) -> Option<Self> {
+        Some(Grayscale)
+    }
+
+    fn path(&self, mut path: PathBuf) -> PathBuf {
+        path.push(Self::NAME);
+        path
+    }
+
+    fn command(&self, mut args: Vec<String>) -> Vec<String> {
+        args.extend(["-colorspace".to_string(), "Gray".to_string()]);
+
+        args
+    }
+}
+
+impl Processor for Sharpen {
+    const NAME: &'static str = "sharpen";
+
+    fn parse(_: &str, v: &str) -> Option<Self> {
+        let sigma = v.parse::<f64>().ok()?;
+
+        // Larger values are very slow without looking any different
+        if !(sigma > 0.0 && sigma <= 10.0) {
+            return None;
+        }
+
+        Some(Sharpen(sigma))
+    }
+
+    fn path(&self, mut path: PathBuf) -> PathBuf {
+        path.push(Self::NAME);
+        path.push(self.0.to_string());
+        path
+    }
+
+    fn command(&self, mut args: Vec<String>) -> Vec<String> {
+        args.extend(["-sharpen".to_string(), format!("0x{}", self.0)]);
+
+        args
+    }
+}
+
+fn parse_adjustment(v: &str) -> Option<i8> {
+    let adjustment = v.parse().ok()?;
+
+    if !(-100..=100).contains(&adjustment) {
+        return None;
+    }
+
+    Some(adjustment)
+}
+
+impl Processor for Brightness {
+    const NAME: &'static str = "brightness";
+
+    fn parse(_: &str, v: &str) -> Option<Self> {
+        parse_adjustment(v).map(Brightness)
+    }
+
+    fn path(&self, mut path: PathBuf) -> PathBuf {
+        path.push(Self::NAME);
+        path.push(self.0.to_string());
+        path
+    }
+
+    fn command(&self, mut args: Vec<String>) -> Vec<String> {
+        args.extend(["-brightness-contrast".to_string(), format!("{}x0", self.0)]);
+
+        args
+    }
+}
+
+impl Processor for Contrast {
+    const NAME: &'static str = "contrast";
+
+    fn parse(_: &str, v: &str) -> Option<Self> {
+        parse_adjustment(v).map(Contrast)
+    }
+
+    fn path(&self, mut path: PathBuf) -> PathBuf {
+        path.push(Self::NAME);
+        path.push(self.0.to_string());
+        path
+    }
+
+    fn command(&self, mut args: Vec<String>) -> Vec<String> {
+        args.extend(["-brightness-contrast".to_string(), format!("0x{}", self.0)]);
+
+        args
+    }
+}
+
+impl Processor for Saturation {
+    const NAME: &'static str = "saturation";
+
+    fn parse(_: &str, v: &str) -> Option<Self> {
+        let percent = v.parse().ok()?;
+
+        if percent > 200 {
+            return None;
+        }
+
+        Some(Saturation(percent))
+    }
+
+    fn path(&self, mut path: PathBuf) -> PathBuf {
+        path.push(Self::NAME);
+        path.push(self.0.to_string());
+        path
+    }
+
+    fn command(&self, mut args: Vec<String>) -> Vec<String> {
+        // Brightness and hue stay at 100%
+        args.extend(["-modulate".to_string(), format!("100,{}", self.0)]);
+
+        args
+    }
+}
+
+impl Processor for AutoLevel {
+    const NAME: &'static str = "autolevel";
+
+    fn parse(_: &str, _: &str) -> Option<Self> {
+        Some(AutoLevel)
+    }
+
+    fn path(&self, mut path: PathBuf) -> PathBuf {
+        path.push(Self::NAME);
+        path
+    }
+
+    fn command(&self, mut args: Vec<String>) -> Vec<String> {
+        args.push("-auto-level".to_string());
+
+        args
+    }
+}
+
+impl Gravity {
+    fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "center" => Some(Self::Center),
+            "north" => Some(Self::North),
+            "northeast" => Some(Self::NorthEast),
+            "east" => Some(Self::East),
+            "southeast" => Some(Self::SouthEast),
+            "south" => Some(Self::South),
+            "southwest" => Some(Self::SouthWest),
+            "west" => Some(Self::West),
+            "northwest" => Some(Self::NorthWest),
+            _ => None,
+        }
+    }
+
+    fn to_magick_str(self) -> &'static str {
+        match self {
+            Self::Center => "center",
+            Self::North => "north",
+            Self::NorthEast => "northeast",
+            Self::East => "east",
+            Self::SouthEast => "southeast",
+            Self::South => "south",
+            Self::SouthWest => "southwest",
+            Self::West => "west",
+            Self::NorthWest => "northwest",
+        }
+    }
+}
+
+impl Processor for Overlay {
+    const NAME: &'static str = "overlay";
+
+    fn parse(_: &str, v: &str) -> Option<Self> {
+        let mut iter = v.split(',');
+
+        let alias = iter.next().filter(|alias| !alias.is_empty())?.to_string();
+
+        let mut overlay = Overlay {
+            alias,
+            gravity: Gravity::SouthEast,
+            x: 0,
+            y: 0,
+            opacity: 100,
+            scale: None,
+        };
+
+        if let Some(gravity) = iter.next() {
+            overlay.gravity = Gravity::from_str(gravity)?;
+        }
+        if let Some(x) = iter.next() {
+            overlay.x = x.parse().ok()?;
+        }
+        if let Some(y) = iter.next() {
+            overlay.y = y.parse().ok()?;
+        }
+        if let Some(opacity) = iter.next() {
+            overlay.opacity = opacity.parse().ok()?;
+        }
+        if let Some(scale) = iter.next() {
+            overlay.scale = Some(scale.parse().ok()?);
+        }
+
+        if iter.next().is_some() {
+            return None;
+        }
+
+        let max_offset = MAX_DIMENSION as i32;
+        if overlay.x.abs() > max_offset || overlay.y.abs() > max_offset {
+            return None;
+        }
+
+        if !(1..=100).contains(&overlay.opacity) {
+            return None;
+        }
+
+        if matches!(overlay.scale, Some(scale) if !(1..=100).contains(&scale)) {
+            return None;
+        }
+
+        Some(overlay)
+    }
+
+    fn path(&self, mut path: PathBuf) -> PathBuf {
+        path.push(Self::NAME);
+        path.push(&self.alias);
+
+        let mut options = format!(
+            "{},{},{},{}",
+            self.gravity.to_magick_str(),
+            self.x,
+            self.y,
+            self.opacity
+        );
+        if let Some(scale) = self.scale {
+            options.push_str(&format!(",{scale}"));
+        }
+        path.push(options);
+
+        path
+    }
+
+    fn command(&self, mut args: Vec<String>) -> Vec<String> {
+        if let Some(scale) = self.scale {
+            // The overlay is sized relative to the width of the image it's placed on
+            args.extend([
+                "-set".to_string(),
+                "option:pict-rs:overlay-width".to_string(),
+                format!("%[fx:round(w*{scale}/100)]"),
+            ]);
+        }
+
+        // Only the first frame of an animated overlay is used
+        args.extend(["(".to_string(), format!("{OVERLAY_PREFIX}{}", self.alias)]);
+
+        if self.scale.is_some() {
+            args.extend([
+                "-resize".to_string(),
+                "%[pict-rs:overlay-width]x".to_string(),
+            ]);
+        }
+
+        if self.opacity < 100 {
+            args.extend([
+                "-alpha".to_string(),
+                "set".to_string(),
+                "-channel".to_string(),
+                "A".to_string(),
+                "-evaluate".to_string(),
+                "multiply".to_string(),
+                format!("{}", f64::from(self.opacity) / 100.0),
+                "+channel".to_string(),
+            ]);
+        }
+
+        args.extend([
+            ")".to_string(),
+            "-gravity".to_string(),
+            self.gravity.to_magick_str().to_string(),
+            "-geometry".to_string(),
+            format!("{:+}{:+}", self.x, self.y),
+            "-composite".to_string(),
+        ]);
+
+        args
+    }
+}
+
+impl Processor for Quality {
+    const NAME: &'static str = "quality";
+
+    fn parse(_: &str, v: &str) -> Option<Self> {
+        let quality = v.parse().ok()?;
+
+        if !(1..=100).contains(&quality) {
+            return None;
+        }
+
+        Some(Quality(quality))
+    }
+
+    fn path(&self, mut path: PathBuf) -> PathBuf {
+        path.push(Self::NAME);
+        path.push(self.0.to_string());
+        path
+    }
+
+    fn command(&self, mut args: Vec<String>) -> Vec<String> {
+        args.extend(["-quality".to_string(), self.0.to_string()]);
+
+        args
+    }
+}
+
+impl Processor for Lossless {
+    const NAME: &'static str = "lossless";
+
+    fn parse(_: &str, v: &str) -> Option<Self> {
+        Some(Lossless(v.parse().ok()?))
+    }
+
+    fn path(&self, mut path: PathBuf) -> PathBuf {
+        path.push(Self::NAME);
+        path.push(self.0.to_string());
+        path
+    }
+
+    fn command(&self, mut args: Vec<String>) -> Vec<String> {
+        args.extend(["-define".to_string(), format!("webp:lossless={}", self.0)]);
+
+        args
+    }
+}
+
+impl Processor for Progressive {
+    const NAME: &'static str = "progressive";
+
+    fn parse(_: &str, v: &str) -> Option<Self> {
+        Some(Progressive(v.parse().ok()?))
+    }
+
+    fn path(&self, mut path: PathBuf) -> PathBuf {
+        path.push(Self::NAME);
+        path.push(self.0.to_string());
+        path
+    }
+
+    fn command(&self, mut args: Vec<String>) -> Vec<String> {
+        let interlace = if self.0 { "Plane" } else { "None" };
+
+        args.extend(["-interlace".to_string(), interlace.to_string()]);
+
+        args
+    }
+}
+
+impl Processor for Subsampling {
+    const NAME: &'static str = "subsampling";
+
+    fn parse(_: &str, v: &str) -> Option<Self> {
+        Some(Subsampling(v.parse().ok()?))
+    }
+
+    fn path(&self, mut path: PathBuf) -> PathBuf {
+        path.push(Self::NAME);
+        path.push(self.0.to_short_str());
+        path
+    }
+
+    fn command(&self, mut args: Vec<String>) -> Vec<String> {
+        args.extend(["-sampling-factor".to_string(), self.0.to_string()]);
+
+        args
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        build_chain, overlay_aliases, take_frame_position, Crop, FocalPoint, FramePosition,
+        Processor, Resize,
+    };
+    use std::path::PathBuf;
+
+    fn resize_path(v: &str) -> Option<PathBuf> {
+        Resize::parse("resize", v).map(|resize| resize.path(PathBuf::new()))
+    }
+
+    fn crop_path(v: &str, focal_point: Option<FocalPoint>) -> Option<PathBuf> {
+        Crop::parse("crop", v).map(|crop| crop.with_focal_point(focal_point).path(PathBuf::new()))
+    }
+
+    #[test]
+    fn existing_resize_paths() {
+        assert_eq!(resize_path("300"), Some(PathBuf::from("resize/300")));
+        assert_eq!(
+            resize_path(".a10000"),
+            Some(PathBuf::from("resize/.a10000"))
+        );
+        assert_eq!(
+            resize_path("Mitchell.200"),
+            Some(PathBuf::from("resize/Mitchell.200"))
+        );
+    }
+
+    #[test]
+    fn dimension_resize_paths() {
+        assert_eq!(
+            resize_path("cover-300x200"),
+            Some(PathBuf::from("resize/.cover-300x200"))
+        );
+        assert_eq!(
+            resize_path("lanczos.fill-1200x630"),
+            Some(PathBuf::from("resize/Lanczos.fill-1200x630"))
+        );
+        assert_eq!(
+            resize_path("pad-300x200"),
+            resize_path("pad-300x200-FFFFFF")
+        );
+        assert_eq!(
+            resize_path("pad-300x200-000"),
+            Some(PathBuf::from("resize/.pad-300x200-000"))
+        );
+
+        assert!(resize_path("contain-0x200").is_none());
+        assert!(resize_path("pad-300x200-blue").is_none());
+        assert!(resize_path("stretch-300x200").is_none());
+    }
+
+    #[test]
+    fn adjustment_chain() {
+        let args = [
+            ("rotate", "90"),
+            ("flop", ""),
+            ("brightness", "-20"),
+            ("saturation", "150"),
+        ]
+        .map(|(k, v)| (k.to_string(), v.to_string()));
+
+        let (path, command) = build_chain(&args, "jpg", None).unwrap();
+
+        assert_eq!(
+            path,
+            PathBuf::from("rotate/90/flop/brightness/-20/saturation/150/jpg")
+        );
+        assert_eq!(
+            command,
+            [
+                "-rotate",
+                "90",
+                "-flop",
+                "-brightness-contrast",
+                "-20x0",
+                "-modulate",
+                "100,150"
+            ]
+        );
+
+        for (k, v) in [
+            ("rotate", "45"),
+            ("brightness", "101"),
+            ("saturation", "-1"),
+            ("sharpen", "0"),
+        ] {
+            assert!(build_chain(&[(k.to_string(), v.to_string())], "jpg", None).is_err());
+        }
+    }
+
+    #[test]
+    fn overlay_paths() {
+        let args = [("overlay", "logo.png,northwest,10,-5,50")]
+            .map(|(k, v)| (k.to_string(), v.to_string()));
+
+        let (path, command) = build_chain(&args, "jpg", None).unwrap();
+
+        assert_eq!(
+            path,
+            PathBuf::from("overlay/logo.png/northwest,10,-5,50/jpg")
+        );
+        assert_eq!(
+            overlay_aliases(&command).collect::<Vec<_>>(),
+            vec!["logo.png"]
+        );
+        assert!(command.contains(&String::from("+10-5")));
+
+        // Defaults are written out so equivalent requests share a variant
+        let args = [("overlay", "logo.png")].map(|(k, v)| (k.to_string(), v.to_string()));
+        assert_eq!(
+            build_chain(&args, "jpg", None).unwrap().0,
+            PathBuf::from("overlay/logo.png/southeast,0,0,100/jpg")
+        );
+
+        for v in [
+            "",
+            "logo.png,up",
+            "logo.png,north,0,0,0",
+            "logo.png,north,0,0,50,101",
+        ] {
+            let args = [(String::from("overlay"), v.to_string())];
+            assert!(build_chain(&args, "jpg", None).is_err());
+        }
+    }
+
+    #[test]
+    fn crop_paths() {
+        assert_eq!(crop_path("16x9", None), Some(PathBuf::from("crop/16x9")));
+        assert_eq!(
+            crop_path("10,20,300,200", None),
+            Some(PathBuf::from("crop/10,20,300,200"))
+        );
+        assert_eq!(
+            crop_path("percent-10,20,50.5,50", None),
+            Some(PathBuf::from("crop/percent-10,20,50.5,50"))
+        );
+
+        assert!(crop_path("10,20,0,200", None).is_none());
+        assert!(crop_path("10,20,300", None).is_none());
+        assert!(crop_path("percent-60,0,50,50", None).is_none());
+    }
+
+    #[test]
+    fn focal_point_paths() {
+        let focal_point = FocalPoint::new(0.25, 0.5);
+
+        assert_eq!(
+            crop_path("1x1", focal_point),
+            Some(PathBuf::from("crop/1x1/focus-0.250x0.500"))
+        );
+        assert_eq!(
+            Resize::parse("resize", "cover-300x200")
+                .map(|resize| resize.with_focal_point(focal_point).path(PathBuf::new())),
+            Some(PathBuf::from("resize/.cover-300x200/focus-0.250x0.500"))
+        );
+
+        // Rectangles and resizes that don't crop ignore the focal point
+        assert_eq!(
+            crop_path("10,20,300,200", focal_point),
+            crop_path("10,20,300,200", None)
+        );
+        assert_eq!(
+            Resize::parse("resize", "300")
+                .map(|resize| resize.with_focal_point(focal_point).path(PathBuf::new())),
+            resize_path("300")
+        );
+
+        assert!(FocalPoint::new(1.5, 0.5).is_none());
+    }
+
+    #[test]
+    fn frame_paths() {
+        let args =
+            [("frame", "2.50"), ("thumbnail", "100")].map(|(k, v)| (k.to_string(), v.to_string()));
+
+        let (path, command) = build_chain(&args, "jpg", None).unwrap();
+        assert_eq!(path, PathBuf::from("frame/2.5/thumbnail/100/jpg"));
+
+        let (position, command) = take_frame_position(command);
+        assert_eq!(position, Some(FramePosition::Seconds(2.5)));
+        assert_eq!(command, ["-sample", "100x100>"]);
+
+        let args = [(String::from("frame"), String::from("percent-50"))];
+        assert_eq!(
+            build_chain(&args, "jpg", None).unwrap().0,
+            PathBuf::from("frame/percent-50/jpg")
+        );
+
+        for v in ["", "-1", "inf", "percent-101", "percent-"] {
+            let args = [(String::from("frame"), v.to_string())];
+            assert!(build_chain(&args, "jpg", None).is_err());
+        }
+    }
+}
@@ -1,14 +1,20 @@
 use crate::{
-    config::ImageFormat,
+    config::TargetFormat,
     error::Error,
     repo::{
-        Alias, AliasRepo, DeleteToken, FullRepo, HashRepo, IdentifierRepo, QueueRepo, UploadId,
+        Alias, AliasRepo, DeleteToken, FullRepo, HashRepo, IdentifierRepo, QueueRepo, SettingsRepo,
+        UploadId,
     },
     serde_str::Serde,
     store::{Identifier, Store},
 };
 use base64::{prelude::BASE64_STANDARD, Engine};
-use std::{future::Future, path::PathBuf, pin::Pin};
+use std::{
+    future::Future,
+    path::PathBuf,
+    pin::Pin,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 use tracing::Instrument;
 
 mod cleanup;
@@ -43,11 +49,25 @@ impl<'de> serde::Deserialize<'de> for Base64Bytes {
 const CLEANUP_QUEUE: &str = "cleanup";
 const PROCESS_QUEUE: &str = "process";
 
+pub(crate) const QUEUES: &[&str] = &[CLEANUP_QUEUE, PROCESS_QUEUE];
+
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 30);
+const ORPHAN_SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 60 * 24);
+
+// unix timestamp of the last time the orphan sweep was queued, shared between restarts and
+// between servers using the same repo
+const LAST_ORPHAN_SWEEP: &str = "last-orphan-sweep";
+
+pub(crate) use cleanup::find_orphans;
+
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 enum Cleanup {
     Hash {
         hash: Base64Bytes,
     },
+    UnusedHash {
+        hash: Base64Bytes,
+    },
     Identifier {
         identifier: Base64Bytes,
     },
@@ -58,7 +78,13 @@ enum Cleanup {
     Variant {
         hash: Base64Bytes,
     },
+    OverlayVariants {
+        overlay: Serde<Alias>,
+    },
     AllVariants,
+    ExpiredAliases,
+    Orphans,
+    StaleVariants,
 }
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
@@ -68,13 +94,16 @@ enum Process {
         upload_id: Serde<UploadId>,
         declared_alias: Option<Serde<Alias>>,
         should_validate: bool,
+        #[serde(default)]
+        cached: bool,
     },
     Generate {
-        target_format: ImageFormat,
+        target_format: TargetFormat,
         source: Serde<Alias>,
         process_path: PathBuf,
         process_args: Vec<String>,
     },
+    BackfillDetails,
 }
 
 pub(crate) async fn cleanup_alias<R: QueueRepo>(
@@ -98,6 +127,16 @@ pub(crate) async fn cleanup_hash<R: QueueRepo>(repo: &R, hash: R::Bytes) -> Resu
     Ok(())
 }
 
+/// Clean up a hash only if it still has no aliases when the job runs, since a new alias may have
+/// been created for it in the meantime
+async fn cleanup_unused_hash<R: QueueRepo>(repo: &R, hash: R::Bytes) -> Result<(), Error> {
+    let job = serde_json::to_vec(&Cleanup::UnusedHash {
+        hash: Base64Bytes(hash.as_ref().to_vec()),
+    })?;
+    repo.push(CLEANUP_QUEUE, job.into()).await?;
+    Ok(())
+}
+
 pub(crate) async fn cleanup_identifier<R: QueueRepo, I: Identifier>(
     repo: &R,
     identifier: I,
@@ -117,24 +156,52 @@ async fn cleanup_variants<R: QueueRepo>(repo: &R, hash: R::Bytes) -> Result<(),
     Ok(())
 }
 
+async fn cleanup_overlay_variants<R: QueueRepo>(repo: &R, overlay: Alias) -> Result<(), Error> {
+    let job = serde_json::to_vec(&Cleanup::OverlayVariants {
+        overlay: Serde::new(overlay),
+    })?;
+    repo.push(CLEANUP_QUEUE, job.into()).await?;
+    Ok(())
+}
+
 pub(crate) async fn cleanup_all_variants<R: QueueRepo>(repo: &R) -> Result<(), Error> {
     let job = serde_json::to_vec(&Cleanup::AllVariants)?;
     repo.push(CLEANUP_QUEUE, job.into()).await?;
     Ok(())
 }
 
+pub(crate) async fn cleanup_expired_aliases<R: QueueRepo>(repo: &R) -> Result<(), Error> {
+    let job = serde_json::to_vec(&Cleanup::ExpiredAliases)?;
+    repo.push(CLEANUP_QUEUE, job.into()).await?;
+    Ok(())
+}
+
+pub(crate) async fn cleanup_orphans<R: QueueRepo>(repo: &R) -> Result<(), Error> {
+    let job = serde_json::to_vec(&Cleanup::Orphans)?;
+    repo.push(CLEANUP_QUEUE, job.into()).await?;
+    Ok(())
+}
+
+async fn cleanup_stale_variants<R: QueueRepo>(repo: &R) -> Result<(), Error> {
+    let job = serde_json::to_vec(&Cleanup::StaleVariants)?;
+    repo.push(CLEANUP_QUEUE, job.into()).await?;
+    Ok(())
+}
+
 pub(crate) async fn queue_ingest<R: QueueRepo>(
     repo: &R,
     identifier: Vec<u8>,
     upload_id: UploadId,
     declared_alias: Option<Alias>,
     should_validate: bool,
+    cached: bool,
 ) -> Result<(), Error> {
     let job = serde_json::to_vec(&Process::Ingest {
         identifier: Base64Bytes(identifier),
         declared_alias: declared_alias.map(Serde::new),
         upload_id: Serde::new(upload_id),
         should_validate,
+        cached,
     })?;
     repo.push(PROCESS_QUEUE, job.into()).await?;
     Ok(())
@@ -142,7 +209,7 @@ pub(crate) async fn queue_ingest<R: QueueRepo>(
 
 pub(crate) async fn queue_generate<R: QueueRepo>(
     repo: &R,
-    target_format: ImageFormat,
+    target_format: TargetFormat,
     source: Alias,
     process_path: PathBuf,
     process_args: Vec<String>,
@@ -157,6 +224,12 @@ pub(crate) async fn queue_generate<R: QueueRepo>(
     Ok(())
 }
 
+pub(crate) async fn queue_backfill_details<R: QueueRepo>(repo: &R) -> Result<(), Error> {
+    let job = serde_json::to_vec(&Process::BackfillDetails)?;
+    repo.push(PROCESS_QUEUE, job.into()).await?;
+    Ok(())
+}
+
 pub(crate) async fn process_cleanup<R: FullRepo, S: Store>(repo: R, store: S, worker_id: String) {
     process_jobs(&repo, &store, worker_id, CLEANUP_QUEUE, cleanup::perform).await
 }
@@ -169,6 +242,53 @@ pub(crate) async fn process_images<R: FullRepo + 'static, S: Store + 'static>(
     process_jobs(&repo, &store, worker_id, PROCESS_QUEUE, process::perform).await
 }
 
+/// Periodically queue cleanup jobs that aren't triggered by a request
+pub(crate) async fn schedule_cleanup<R: QueueRepo + SettingsRepo>(repo: R) {
+    let mut interval = actix_rt::time::interval(CLEANUP_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        if let Err(e) = cleanup_expired_aliases(&repo).await {
+            tracing::warn!("Failed to queue expired alias cleanup: {}", format!("{e}"));
+        }
+
+        if let Err(e) = schedule_orphan_sweep(&repo).await {
+            tracing::warn!("Failed to queue orphan cleanup: {}", format!("{e}"));
+        }
+
+        if crate::CONFIG.media.variant_max_age.is_some()
+            || crate::CONFIG.media.variant_storage_budget.is_some()
+        {
+            if let Err(e) = cleanup_stale_variants(&repo).await {
+                tracing::warn!("Failed to queue stale variant cleanup: {}", format!("{e}"));
+            }
+        }
+    }
+}
+
+async fn schedule_orphan_sweep<R: QueueRepo + SettingsRepo>(repo: &R) -> Result<(), Error> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    if let Some(bytes) = repo.get(LAST_ORPHAN_SWEEP).await? {
+        if let Ok(bytes) = <[u8; 8]>::try_from(bytes.as_ref()) {
+            let last_sweep = u64::from_be_bytes(bytes);
+
+            if now.saturating_sub(last_sweep) < ORPHAN_SWEEP_INTERVAL.as_secs() {
+                return Ok(());
+            }
+        }
+    }
+
+    repo.set(LAST_ORPHAN_SWEEP, now.to_be_bytes().to_vec().into())
+        .await?;
+
+    cleanup_orphans(repo).await
+}
+
 type LocalBoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + 'a>>;
 
 async fn process_jobs<R, S, F>(
@@ -1,4 +1,5 @@
 use crate::{
+    details::Details,
     error::{Error, UploadError},
     queue::{Base64Bytes, Cleanup, LocalBoxFuture},
     repo::{Alias, AliasRepo, DeleteToken, FullRepo, HashRepo, IdentifierRepo},
@@ -6,6 +7,17 @@ use crate::{
     store::{Identifier, Store},
 };
 use futures_util::StreamExt;
+use std::{
+    collections::HashSet,
+    time::{Duration, SystemTime},
+};
+
+// Objects newer than this might belong to an ingest or backgrounded upload that hasn't been
+// related to a hash yet
+const ORPHAN_GRACE_PERIOD: Duration = Duration::from_secs(60 * 60 * 24);
+
+// Set once variants generated before access tracking have been given an access time
+const VARIANT_ACCESS_BACKFILLED: &str = "variant-access-backfilled";
 
 pub(super) fn perform<'a, R, S>(
     repo: &'a R,
@@ -22,6 +34,9 @@ where
                 Cleanup::Hash {
                     hash: Base64Bytes(in_hash),
                 } => hash::<R, S>(repo, in_hash).await?,
+                Cleanup::UnusedHash {
+                    hash: Base64Bytes(in_hash),
+                } => unused_hash::<R, S>(repo, in_hash).await?,
                 Cleanup::Identifier {
                     identifier: Base64Bytes(in_identifier),
                 } => identifier(repo, store, in_identifier).await?,
@@ -39,7 +54,13 @@ where
                 Cleanup::Variant {
                     hash: Base64Bytes(hash),
                 } => variant::<R, S>(repo, hash).await?,
+                Cleanup::OverlayVariants { overlay } => {
+                    overlay_variants::<R, S>(repo, Serde::into_inner(overlay)).await?
+                }
                 Cleanup::AllVariants => all_variants::<R, S>(repo).await?,
+                Cleanup::ExpiredAliases => expired_aliases(repo).await?,
+                Cleanup::Orphans => orphans(repo, store).await?,
+                Cleanup::StaleVariants => stale_variants(repo, store).await?,
             },
             Err(e) => {
                 tracing::warn!("Invalid job: {}", format!("{e}"));
@@ -117,6 +138,19 @@ where
     Ok(())
 }
 
+#[tracing::instrument(skip_all)]
+async fn unused_hash<R, S>(repo: &R, in_hash: Vec<u8>) -> Result<(), Error>
+where
+    R: FullRepo,
+    S: Store,
+{
+    if !repo.aliases(in_hash.clone().into()).await?.is_empty() {
+        return Ok(());
+    }
+
+    hash::<R, S>(repo, in_hash).await
+}
+
 #[tracing::instrument(skip_all)]
 async fn alias<R>(repo: &R, alias: Alias, token: DeleteToken) -> Result<(), Error>
 where
@@ -132,10 +166,132 @@ where
     AliasRepo::cleanup(repo, &alias).await?;
     repo.remove_alias(hash.clone(), &alias).await?;
 
+    // Variants with this alias composited onto them can't be regenerated anymore
+    if !repo.overlay_variants(&alias).await?.is_empty() {
+        super::cleanup_overlay_variants(repo, alias).await?;
+    }
+
     if repo.aliases(hash.clone()).await?.is_empty() {
-        super::cleanup_hash(repo, hash).await?;
+        super::cleanup_unused_hash(repo, hash).await?;
+    }
+
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn expired_aliases<R>(repo: &R) -> Result<(), Error>
+where
+    R: FullRepo,
+{
+    for alias in repo.expired(time::OffsetDateTime::now_utc()).await? {
+        let token = match repo.delete_token(&alias).await {
+            Ok(token) => token,
+            // A cleanup queued by an earlier run already removed this alias
+            Err(e) if e.is_missing() => continue,
+            Err(e) => {
+                tracing::warn!("Failed to expire {alias}: {}", format!("{e}"));
+                continue;
+            }
+        };
+
+        if let Err(e) = super::cleanup_alias(repo, alias.clone(), token).await {
+            tracing::warn!("Failed to expire {alias}: {}", format!("{e}"));
+        }
+    }
+
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn orphans<R, S>(repo: &R, store: &S) -> Result<(), Error>
+where
+    R: FullRepo,
+    S: Store,
+{
+    let orphans = find_orphans(repo, store).await?;
+
+    tracing::info!("Cleaning up {} orphaned files", orphans.len());
+
+    for identifier in orphans {
+        super::cleanup_identifier(repo, identifier).await?;
+    }
+
+    Ok(())
+}
+
+/// Find objects in the store that no hash, variant, or details entry in the repo refers to
+pub(crate) async fn find_orphans<R, S>(repo: &R, store: &S) -> Result<Vec<S::Identifier>, Error>
+where
+    R: FullRepo,
+    S: Store,
+{
+    let mut known = HashSet::new();
+
+    let mut hash_stream = Box::pin(repo.hashes().await);
+
+    while let Some(res) = hash_stream.next().await {
+        let hash = res?;
+
+        // A hash is created before its identifier is related, so ingests that are in progress
+        // won't have one yet
+        match repo.identifier::<Vec<u8>>(hash.clone()).await {
+            Ok(identifier) => {
+                known.insert(identifier);
+            }
+            Err(e) if e.is_missing() => {}
+            Err(e) => return Err(e),
+        }
+
+        known.extend(repo.motion_identifier::<Vec<u8>>(hash.clone()).await?);
+        known.extend(
+            repo.variants::<Vec<u8>>(hash)
+                .await?
+                .into_iter()
+                .map(|(_, identifier)| identifier),
+        );
+    }
+
+    let cutoff = SystemTime::now() - ORPHAN_GRACE_PERIOD;
+
+    let mut orphans = Vec::new();
+    let mut object_stream = store.list().await?;
+
+    while let Some(res) = object_stream.next().await {
+        let object = res?;
+
+        if object.modified > cutoff || known.contains(&object.identifier.to_bytes()?) {
+            continue;
+        }
+
+        if repo.details(&object.identifier).await?.is_some() {
+            continue;
+        }
+
+        orphans.push(object.identifier);
+    }
+
+    Ok(orphans)
+}
+
+#[tracing::instrument(skip_all)]
+async fn overlay_variants<R, S>(repo: &R, overlay: Alias) -> Result<(), Error>
+where
+    R: FullRepo,
+    S: Store,
+{
+    for (hash, variant) in repo.overlay_variants(&overlay).await? {
+        // The variant may already be gone along with its source
+        if let Some(identifier) = repo
+            .variant_identifier::<S::Identifier>(hash.clone(), variant.clone())
+            .await?
+        {
+            repo.remove_variant(hash, variant).await?;
+            super::cleanup_identifier(repo, identifier).await?;
+        }
     }
 
+    repo.remove_overlay_variants(&overlay).await?;
+
     Ok(())
 }
 
@@ -168,3 +324,144 @@ where
 
     Ok(())
 }
+
+#[tracing::instrument(skip_all)]
+async fn stale_variants<R, S>(repo: &R, store: &S) -> Result<(), Error>
+where
+    R: FullRepo,
+    S: Store,
+{
+    backfill_variant_access(repo).await?;
+
+    let now = time::OffsetDateTime::now_utc();
+
+    if let Some(max_age) = crate::CONFIG.media.variant_max_age {
+        let stale = repo
+            .accessed_before(now - time::Duration::hours(max_age))
+            .await?;
+
+        tracing::info!(
+            "Removing {} variants that haven't been accessed",
+            stale.len()
+        );
+
+        for (hash, variant) in stale {
+            evict_variant::<R, S>(repo, hash, variant).await?;
+        }
+    }
+
+    if let Some(budget) = crate::CONFIG.media.variant_storage_budget {
+        let budget = budget.saturating_mul(1024 * 1024);
+
+        let mut sized = Vec::new();
+        let mut total: u64 = 0;
+
+        for (hash, variant) in repo.accessed_before(now).await? {
+            if let Some(identifier) = repo
+                .variant_identifier::<S::Identifier>(hash.clone(), variant.clone())
+                .await?
+            {
+                let Some(len) = variant_size(repo, store, &identifier).await? else {
+                    // A variant whose file can't be read can't be served either, so it's generated
+                    // again on its next request
+                    evict_variant::<R, S>(repo, hash, variant).await?;
+                    continue;
+                };
+
+                total = total.saturating_add(len);
+                sized.push((hash, variant, len));
+            }
+        }
+
+        // Least recently accessed variants come first
+        for (hash, variant, len) in sized {
+            if total <= budget {
+                break;
+            }
+
+            evict_variant::<R, S>(repo, hash, variant).await?;
+            total = total.saturating_sub(len);
+        }
+    }
+
+    Ok(())
+}
+
+/// Sizes are recorded in a variant's details when it's generated. Variants generated before that
+/// are measured once, and their details updated so later runs don't need the store
+async fn variant_size<R, S>(
+    repo: &R,
+    store: &S,
+    identifier: &S::Identifier,
+) -> Result<Option<u64>, Error>
+where
+    R: FullRepo,
+    S: Store,
+{
+    let details = repo.details(identifier).await?;
+
+    if let Some(size) = details.as_ref().and_then(Details::size) {
+        return Ok(Some(size));
+    }
+
+    let Ok(len) = store.len(identifier).await else {
+        return Ok(None);
+    };
+
+    if let Some(details) = details {
+        repo.relate_details(identifier, &details.with_size(Some(len)))
+            .await?;
+    }
+
+    Ok(Some(len))
+}
+
+async fn evict_variant<R, S>(repo: &R, hash: R::Bytes, variant: String) -> Result<(), Error>
+where
+    R: FullRepo,
+    S: Store,
+{
+    if let Some(identifier) = repo
+        .variant_identifier::<S::Identifier>(hash.clone(), variant.clone())
+        .await?
+    {
+        repo.remove_variant(hash, variant).await?;
+        super::cleanup_identifier(repo, identifier).await?;
+    }
+
+    Ok(())
+}
+
+/// Give variants that were generated before access was tracked an access time of now, so they're
+/// considered for eviction
+async fn backfill_variant_access<R>(repo: &R) -> Result<(), Error>
+where
+    R: FullRepo,
+{
+    if repo.get(VARIANT_ACCESS_BACKFILLED).await?.is_some() {
+        return Ok(());
+    }
+
+    let now = time::OffsetDateTime::now_utc();
+
+    let mut hash_stream = Box::pin(repo.hashes().await);
+
+    while let Some(res) = hash_stream.next().await {
+        let hash = res?;
+
+        for (variant, _) in repo.variants::<Vec<u8>>(hash.clone()).await? {
+            if repo
+                .accessed_at(hash.clone(), variant.clone())
+                .await?
+                .is_none()
+            {
+                repo.mark_accessed(hash.clone(), variant, now).await?;
+            }
+        }
+    }
+
+    repo.set(VARIANT_ACCESS_BACKFILLED, b"1".to_vec().into())
+        .await?;
+
+    Ok(())
+}
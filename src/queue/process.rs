@@ -1,5 +1,6 @@
 use crate::{
-    config::ImageFormat,
+    config::TargetFormat,
+    details::Details,
     error::Error,
     ingest::Session,
     queue::{Base64Bytes, LocalBoxFuture, Process},
@@ -7,7 +8,7 @@ use crate::{
     serde_str::Serde,
     store::{Identifier, Store},
 };
-use futures_util::TryStreamExt;
+use futures_util::{StreamExt, TryStreamExt};
 use std::path::PathBuf;
 
 pub(super) fn perform<'a, R, S>(
@@ -27,6 +28,7 @@ where
                     upload_id,
                     declared_alias,
                     should_validate,
+                    cached,
                 } => {
                     process_ingest(
                         repo,
@@ -35,6 +37,7 @@ where
                         Serde::into_inner(upload_id),
                         declared_alias.map(Serde::into_inner),
                         should_validate,
+                        cached,
                     )
                     .await?
                 }
@@ -54,6 +57,7 @@ where
                     )
                     .await?
                 }
+                Process::BackfillDetails => backfill_details(repo, store).await?,
             },
             Err(e) => {
                 tracing::warn!("Invalid job: {}", format!("{e}"));
@@ -72,10 +76,11 @@ async fn process_ingest<R, S>(
     upload_id: UploadId,
     declared_alias: Option<Alias>,
     should_validate: bool,
+    cached: bool,
 ) -> Result<(), Error>
 where
     R: FullRepo + 'static,
-    S: Store,
+    S: Store + 'static,
 {
     let fut = async {
         let unprocessed_identifier = S::Identifier::from_bytes(unprocessed_identifier)?;
@@ -90,6 +95,12 @@ where
 
         let token = session.delete_token().await?;
 
+        if cached {
+            if let Some(alias) = session.alias() {
+                crate::mark_cached(repo, alias).await?;
+            }
+        }
+
         store.remove(&unprocessed_identifier).await?;
 
         Ok((session, token)) as Result<(Session<R, S>, DeleteToken), Error>
@@ -98,9 +109,11 @@ where
     let result = match fut.await {
         Ok((mut session, token)) => {
             let alias = session.alias().take().expect("Alias should exist").clone();
-            let result = UploadResult::Success { alias, token };
             session.disarm();
-            result
+
+            crate::queue_eager_variants(repo, &alias).await;
+
+            UploadResult::Success { alias, token }
         }
         Err(e) => {
             tracing::warn!("Failed to ingest {}, {}", format!("{e}"), format!("{e:?}"));
@@ -120,7 +133,7 @@ where
 async fn generate<R: FullRepo, S: Store + 'static>(
     repo: &R,
     store: &S,
-    target_format: ImageFormat,
+    target_format: TargetFormat,
     source: Alias,
     process_path: PathBuf,
     process_args: Vec<String>,
@@ -153,3 +166,56 @@ async fn generate<R: FullRepo, S: Store + 'static>(
 
     Ok(())
 }
+
+/// Probe every stored original again, so details saved by older versions gain the fields they're
+/// missing
+#[tracing::instrument(skip_all)]
+async fn backfill_details<R: FullRepo, S: Store + 'static>(
+    repo: &R,
+    store: &S,
+) -> Result<(), Error> {
+    let mut hash_stream = Box::pin(repo.hashes().await);
+
+    while let Some(res) = hash_stream.next().await {
+        let hash = res?;
+
+        // One broken file shouldn't stop the rest from being probed
+        if let Err(e) = backfill_hash(repo, store, hash).await {
+            tracing::warn!("Failed to backfill details: {}", format!("{e}"));
+        }
+    }
+
+    Ok(())
+}
+
+async fn backfill_hash<R: FullRepo, S: Store + 'static>(
+    repo: &R,
+    store: &S,
+    hash: R::Bytes,
+) -> Result<(), Error> {
+    let identifier = match repo.identifier::<S::Identifier>(hash.clone()).await {
+        Ok(identifier) => identifier,
+        // Ingests in progress don't have an identifier yet
+        Err(e) if e.is_missing() => return Ok(()),
+        Err(e) => return Err(e),
+    };
+
+    let hint = repo
+        .aliases(hash)
+        .await?
+        .first()
+        .and_then(crate::magick::details_hint);
+
+    let permit = crate::PROCESS_SEMAPHORE.acquire().await;
+    let details = Details::from_store(store.clone(), identifier.clone(), hint).await;
+    drop(permit);
+
+    let mut details = details?;
+    if let Some(previous) = repo.details(&identifier).await? {
+        details = details.with_created_at(&previous);
+    }
+
+    repo.relate_details(&identifier, &details).await?;
+
+    Ok(())
+}
@@ -2,20 +2,26 @@ use crate::{
     config,
     details::Details,
     error::Error,
+    processor::FocalPoint,
+    serde_str::Serde,
     store::{file_store::FileId, Identifier},
 };
 use base64::{prelude::BASE64_STANDARD, Engine};
 use futures_util::Stream;
 use std::{fmt::Debug, path::PathBuf};
+use time::OffsetDateTime;
 use tracing::Instrument;
 use uuid::Uuid;
 
+mod migrate;
 mod old;
+pub(crate) mod postgres;
 pub(crate) mod sled;
 
 #[derive(Clone, Debug)]
 pub(crate) enum Repo {
-    Sled(self::sled::SledRepo),
+    Sled(Box<self::sled::SledRepo>),
+    Postgres(self::postgres::PostgresRepo),
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -47,12 +53,26 @@ pub(crate) enum UploadResult {
     Failure { message: String },
 }
 
+#[derive(serde::Deserialize, serde::Serialize)]
+enum InnerUploadResult {
+    Success {
+        alias: Serde<Alias>,
+        token: Serde<DeleteToken>,
+    },
+    Failure {
+        message: String,
+    },
+}
+
 #[async_trait::async_trait(?Send)]
 pub(crate) trait FullRepo:
     UploadRepo
     + SettingsRepo
     + IdentifierRepo
     + AliasRepo
+    + CachedRepo
+    + VariantAccessRepo
+    + BlocklistRepo
     + QueueRepo
     + HashRepo
     + Send
@@ -154,6 +174,9 @@ pub(crate) trait QueueRepo: BaseRepo {
     async fn push(&self, queue: &'static str, job: Self::Bytes) -> Result<(), Error>;
 
     async fn pop(&self, queue: &'static str, worker_id: Vec<u8>) -> Result<Self::Bytes, Error>;
+
+    /// The number of jobs waiting to be picked up by a worker
+    async fn queue_length(&self, queue: &'static str) -> Result<u64, Error>;
 }
 
 #[async_trait::async_trait(?Send)]
@@ -172,6 +195,10 @@ where
     async fn pop(&self, queue: &'static str, worker_id: Vec<u8>) -> Result<Self::Bytes, Error> {
         T::pop(self, queue, worker_id).await
     }
+
+    async fn queue_length(&self, queue: &'static str) -> Result<u64, Error> {
+        T::queue_length(self, queue).await
+    }
 }
 
 #[async_trait::async_trait(?Send)]
@@ -239,6 +266,13 @@ pub(crate) trait HashRepo: BaseRepo {
 
     async fn hashes(&self) -> Self::Stream;
 
+    /// Up to `limit` hashes in byte order, starting after `after`
+    async fn hashes_after(
+        &self,
+        after: Option<Self::Bytes>,
+        limit: usize,
+    ) -> Result<Vec<Self::Bytes>, Error>;
+
     async fn create(&self, hash: Self::Bytes) -> Result<Result<(), AlreadyExists>, Error>;
 
     async fn relate_alias(&self, hash: Self::Bytes, alias: &Alias) -> Result<(), Error>;
@@ -279,6 +313,20 @@ pub(crate) trait HashRepo: BaseRepo {
         hash: Self::Bytes,
     ) -> Result<Option<I>, Error>;
 
+    async fn relate_perceptual_hash(
+        &self,
+        hash: Self::Bytes,
+        perceptual_hash: u64,
+    ) -> Result<(), Error>;
+    async fn perceptual_hash(&self, hash: Self::Bytes) -> Result<Option<u64>, Error>;
+
+    /// Find hashes whose perceptual hash differs from the given one by at most `max_distance` bits
+    async fn similar_hashes(
+        &self,
+        perceptual_hash: u64,
+        max_distance: u32,
+    ) -> Result<Vec<(Self::Bytes, u64)>, Error>;
+
     async fn cleanup(&self, hash: Self::Bytes) -> Result<(), Error>;
 }
 
@@ -293,6 +341,14 @@ where
         T::hashes(self).await
     }
 
+    async fn hashes_after(
+        &self,
+        after: Option<Self::Bytes>,
+        limit: usize,
+    ) -> Result<Vec<Self::Bytes>, Error> {
+        T::hashes_after(self, after, limit).await
+    }
+
     async fn create(&self, hash: Self::Bytes) -> Result<Result<(), AlreadyExists>, Error> {
         T::create(self, hash).await
     }
@@ -364,6 +420,26 @@ where
         T::motion_identifier(self, hash).await
     }
 
+    async fn relate_perceptual_hash(
+        &self,
+        hash: Self::Bytes,
+        perceptual_hash: u64,
+    ) -> Result<(), Error> {
+        T::relate_perceptual_hash(self, hash, perceptual_hash).await
+    }
+
+    async fn perceptual_hash(&self, hash: Self::Bytes) -> Result<Option<u64>, Error> {
+        T::perceptual_hash(self, hash).await
+    }
+
+    async fn similar_hashes(
+        &self,
+        perceptual_hash: u64,
+        max_distance: u32,
+    ) -> Result<Vec<(Self::Bytes, u64)>, Error> {
+        T::similar_hashes(self, perceptual_hash, max_distance).await
+    }
+
     async fn cleanup(&self, hash: Self::Bytes) -> Result<(), Error> {
         T::cleanup(self, hash).await
     }
@@ -383,6 +459,21 @@ pub(crate) trait AliasRepo: BaseRepo {
     async fn relate_hash(&self, alias: &Alias, hash: Self::Bytes) -> Result<(), Error>;
     async fn hash(&self, alias: &Alias) -> Result<Self::Bytes, Error>;
 
+    async fn relate_focal_point(&self, alias: &Alias, focal_point: FocalPoint)
+        -> Result<(), Error>;
+    async fn focal_point(&self, alias: &Alias) -> Result<Option<FocalPoint>, Error>;
+    async fn remove_focal_point(&self, alias: &Alias) -> Result<(), Error>;
+
+    /// Record that a variant of `hash` has `overlay` composited onto it
+    async fn relate_overlay_variant(
+        &self,
+        overlay: &Alias,
+        hash: Self::Bytes,
+        variant: String,
+    ) -> Result<(), Error>;
+    async fn overlay_variants(&self, overlay: &Alias) -> Result<Vec<(Self::Bytes, String)>, Error>;
+    async fn remove_overlay_variants(&self, overlay: &Alias) -> Result<(), Error>;
+
     async fn cleanup(&self, alias: &Alias) -> Result<(), Error>;
 }
 
@@ -415,13 +506,158 @@ where
         T::hash(self, alias).await
     }
 
+    async fn relate_focal_point(
+        &self,
+        alias: &Alias,
+        focal_point: FocalPoint,
+    ) -> Result<(), Error> {
+        T::relate_focal_point(self, alias, focal_point).await
+    }
+
+    async fn focal_point(&self, alias: &Alias) -> Result<Option<FocalPoint>, Error> {
+        T::focal_point(self, alias).await
+    }
+
+    async fn remove_focal_point(&self, alias: &Alias) -> Result<(), Error> {
+        T::remove_focal_point(self, alias).await
+    }
+
+    async fn relate_overlay_variant(
+        &self,
+        overlay: &Alias,
+        hash: Self::Bytes,
+        variant: String,
+    ) -> Result<(), Error> {
+        T::relate_overlay_variant(self, overlay, hash, variant).await
+    }
+
+    async fn overlay_variants(&self, overlay: &Alias) -> Result<Vec<(Self::Bytes, String)>, Error> {
+        T::overlay_variants(self, overlay).await
+    }
+
+    async fn remove_overlay_variants(&self, overlay: &Alias) -> Result<(), Error> {
+        T::remove_overlay_variants(self, overlay).await
+    }
+
     async fn cleanup(&self, alias: &Alias) -> Result<(), Error> {
         T::cleanup(self, alias).await
     }
 }
 
+#[async_trait::async_trait(?Send)]
+pub(crate) trait CachedRepo: BaseRepo {
+    async fn mark_cached(&self, alias: &Alias, expires_at: OffsetDateTime) -> Result<(), Error>;
+
+    async fn cached_expiry(&self, alias: &Alias) -> Result<Option<OffsetDateTime>, Error>;
+
+    async fn expired(&self, now: OffsetDateTime) -> Result<Vec<Alias>, Error>;
+}
+
+#[async_trait::async_trait(?Send)]
+impl<T> CachedRepo for actix_web::web::Data<T>
+where
+    T: CachedRepo,
+{
+    async fn mark_cached(&self, alias: &Alias, expires_at: OffsetDateTime) -> Result<(), Error> {
+        T::mark_cached(self, alias, expires_at).await
+    }
+
+    async fn cached_expiry(&self, alias: &Alias) -> Result<Option<OffsetDateTime>, Error> {
+        T::cached_expiry(self, alias).await
+    }
+
+    async fn expired(&self, now: OffsetDateTime) -> Result<Vec<Alias>, Error> {
+        T::expired(self, now).await
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+pub(crate) trait VariantAccessRepo: BaseRepo {
+    async fn mark_accessed(
+        &self,
+        hash: Self::Bytes,
+        variant: String,
+        accessed_at: OffsetDateTime,
+    ) -> Result<(), Error>;
+
+    async fn accessed_at(
+        &self,
+        hash: Self::Bytes,
+        variant: String,
+    ) -> Result<Option<OffsetDateTime>, Error>;
+
+    /// Variants last accessed before `timestamp`, least recently accessed first
+    async fn accessed_before(
+        &self,
+        timestamp: OffsetDateTime,
+    ) -> Result<Vec<(Self::Bytes, String)>, Error>;
+}
+
+#[async_trait::async_trait(?Send)]
+impl<T> VariantAccessRepo for actix_web::web::Data<T>
+where
+    T: VariantAccessRepo,
+{
+    async fn mark_accessed(
+        &self,
+        hash: Self::Bytes,
+        variant: String,
+        accessed_at: OffsetDateTime,
+    ) -> Result<(), Error> {
+        T::mark_accessed(self, hash, variant, accessed_at).await
+    }
+
+    async fn accessed_at(
+        &self,
+        hash: Self::Bytes,
+        variant: String,
+    ) -> Result<Option<OffsetDateTime>, Error> {
+        T::accessed_at(self, hash, variant).await
+    }
+
+    async fn accessed_before(
+        &self,
+        timestamp: OffsetDateTime,
+    ) -> Result<Vec<(Self::Bytes, String)>, Error> {
+        T::accessed_before(self, timestamp).await
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+pub(crate) trait BlocklistRepo: BaseRepo {
+    async fn block(&self, hash: Self::Bytes) -> Result<(), Error>;
+
+    async fn unblock(&self, hash: Self::Bytes) -> Result<(), Error>;
+
+    async fn is_blocked(&self, hash: Self::Bytes) -> Result<bool, Error>;
+
+    async fn blocked(&self) -> Result<Vec<Self::Bytes>, Error>;
+}
+
+#[async_trait::async_trait(?Send)]
+impl<T> BlocklistRepo for actix_web::web::Data<T>
+where
+    T: BlocklistRepo,
+{
+    async fn block(&self, hash: Self::Bytes) -> Result<(), Error> {
+        T::block(self, hash).await
+    }
+
+    async fn unblock(&self, hash: Self::Bytes) -> Result<(), Error> {
+        T::unblock(self, hash).await
+    }
+
+    async fn is_blocked(&self, hash: Self::Bytes) -> Result<bool, Error> {
+        T::is_blocked(self, hash).await
+    }
+
+    async fn blocked(&self) -> Result<Vec<Self::Bytes>, Error> {
+        T::blocked(self).await
+    }
+}
+
 impl Repo {
-    pub(crate) fn open(config: config::Repo) -> color_eyre::Result<Self> {
+    pub(crate) async fn open(config: config::Repo) -> color_eyre::Result<Self> {
         match config {
             config::Repo::Sled(config::Sled {
                 mut path,
@@ -434,8 +670,11 @@ impl Repo {
                     .path(path)
                     .open()?;
 
-                Ok(Self::Sled(self::sled::SledRepo::new(db)?))
+                Ok(Self::Sled(Box::new(self::sled::SledRepo::new(db)?)))
             }
+            config::Repo::Postgres(config::Postgres { url }) => Ok(Self::Postgres(
+                self::postgres::PostgresRepo::connect(url).await?,
+            )),
         }
     }
 
@@ -448,27 +687,8 @@ impl Repo {
             let span = tracing::warn_span!("Migrating Database from 0.3 layout to 0.4 layout");
 
             match self {
-                Self::Sled(repo) => {
-                    async {
-                        for hash in old.hashes() {
-                            if let Err(e) = migrate_hash(repo, &old, hash).await {
-                                tracing::error!("Failed to migrate hash: {}", format!("{e:?}"));
-                            }
-                        }
-
-                        if let Ok(Some(value)) = old.setting(STORE_MIGRATION_PROGRESS.as_bytes()) {
-                            tracing::warn!("Setting STORE_MIGRATION_PROGRESS");
-                            let _ = repo.set(STORE_MIGRATION_PROGRESS, value).await;
-                        }
-
-                        if let Ok(Some(value)) = old.setting(GENERATOR_KEY.as_bytes()) {
-                            tracing::warn!("Setting GENERATOR_KEY");
-                            let _ = repo.set(GENERATOR_KEY, value).await;
-                        }
-                    }
-                    .instrument(span)
-                    .await;
-                }
+                Self::Sled(repo) => migrate_from_old(&**repo, &old).instrument(span).await,
+                Self::Postgres(repo) => migrate_from_old(repo, &old).instrument(span).await,
             }
         }
 
@@ -485,26 +705,8 @@ impl Repo {
         let span = tracing::warn_span!("Migrating File Identifiers from 0.3 format to 0.4 format");
 
         match self {
-            Self::Sled(repo) => {
-                async {
-                    use futures_util::StreamExt;
-                    let mut hashes = repo.hashes().await;
-
-                    while let Some(res) = hashes.next().await {
-                        let hash = res?;
-                        if let Err(e) = migrate_identifiers_for_hash(repo, hash).await {
-                            tracing::error!(
-                                "Failed to migrate identifiers for hash: {}",
-                                format!("{e:?}")
-                            );
-                        }
-                    }
-
-                    Ok(()) as color_eyre::Result<()>
-                }
-                .instrument(span)
-                .await?;
-            }
+            Self::Sled(repo) => migrate_identifiers(&**repo).instrument(span).await?,
+            Self::Postgres(repo) => migrate_identifiers(repo).instrument(span).await?,
         }
 
         self.mark_migrated_identifiers().await?;
@@ -512,15 +714,40 @@ impl Repo {
         Ok(())
     }
 
+    pub(crate) async fn migrate_to(&self, to: &Repo) -> color_eyre::Result<()> {
+        match (self, to) {
+            (Self::Sled(from), Self::Sled(to)) => {
+                self::migrate::migrate_repo(&**from, &**to).await?
+            }
+            (Self::Sled(from), Self::Postgres(to)) => {
+                self::migrate::migrate_repo(&**from, to).await?
+            }
+            (Self::Postgres(from), Self::Sled(to)) => {
+                self::migrate::migrate_repo(from, &**to).await?
+            }
+            (Self::Postgres(from), Self::Postgres(to)) => {
+                self::migrate::migrate_repo(from, to).await?
+            }
+        }
+
+        if let Self::Sled(to) = to {
+            to.flush().await?;
+        }
+
+        Ok(())
+    }
+
     async fn has_migrated(&self) -> color_eyre::Result<bool> {
         match self {
             Self::Sled(repo) => Ok(repo.get(REPO_MIGRATION_O1).await?.is_some()),
+            Self::Postgres(repo) => Ok(repo.get(REPO_MIGRATION_O1).await?.is_some()),
         }
     }
 
     async fn has_migrated_identifiers(&self) -> color_eyre::Result<bool> {
         match self {
             Self::Sled(repo) => Ok(repo.get(REPO_MIGRATION_02).await?.is_some()),
+            Self::Postgres(repo) => Ok(repo.get(REPO_MIGRATION_02).await?.is_some()),
         }
     }
 
@@ -529,6 +756,9 @@ impl Repo {
             Self::Sled(repo) => {
                 repo.set(REPO_MIGRATION_O1, b"1".to_vec().into()).await?;
             }
+            Self::Postgres(repo) => {
+                repo.set(REPO_MIGRATION_O1, b"1".to_vec()).await?;
+            }
         }
 
         Ok(())
@@ -539,6 +769,9 @@ impl Repo {
             Self::Sled(repo) => {
                 repo.set(REPO_MIGRATION_02, b"1".to_vec().into()).await?;
             }
+            Self::Postgres(repo) => {
+                repo.set(REPO_MIGRATION_02, b"1".to_vec()).await?;
+            }
         }
 
         Ok(())
@@ -550,13 +783,54 @@ const REPO_MIGRATION_02: &str = "repo-migration-02";
 const STORE_MIGRATION_PROGRESS: &str = "store-migration-progress";
 const GENERATOR_KEY: &str = "last-path";
 
-#[tracing::instrument(skip(repo, hash), fields(hash = hex::encode(&hash)))]
-async fn migrate_identifiers_for_hash<T>(repo: &T, hash: ::sled::IVec) -> color_eyre::Result<()>
+async fn migrate_from_old<T>(repo: &T, old: &old::Old)
+where
+    T: FullRepo,
+{
+    for hash in old.hashes() {
+        if let Err(e) = migrate_hash(repo, old, hash).await {
+            tracing::error!("Failed to migrate hash: {}", format!("{e:?}"));
+        }
+    }
+
+    if let Ok(Some(value)) = old.setting(STORE_MIGRATION_PROGRESS.as_bytes()) {
+        tracing::warn!("Setting STORE_MIGRATION_PROGRESS");
+        let _ = repo
+            .set(STORE_MIGRATION_PROGRESS, value.to_vec().into())
+            .await;
+    }
+
+    if let Ok(Some(value)) = old.setting(GENERATOR_KEY.as_bytes()) {
+        tracing::warn!("Setting GENERATOR_KEY");
+        let _ = repo.set(GENERATOR_KEY, value.to_vec().into()).await;
+    }
+}
+
+async fn migrate_identifiers<T>(repo: &T) -> color_eyre::Result<()>
 where
     T: FullRepo,
 {
-    let hash: T::Bytes = hash.to_vec().into();
+    use futures_util::StreamExt;
+    let mut hashes = Box::pin(repo.hashes().await);
+
+    while let Some(res) = hashes.next().await {
+        let hash = res?;
+        if let Err(e) = migrate_identifiers_for_hash(repo, hash).await {
+            tracing::error!(
+                "Failed to migrate identifiers for hash: {}",
+                format!("{e:?}")
+            );
+        }
+    }
 
+    Ok(())
+}
+
+#[tracing::instrument(skip(repo, hash), fields(hash = hex::encode(&hash)))]
+async fn migrate_identifiers_for_hash<T>(repo: &T, hash: T::Bytes) -> color_eyre::Result<()>
+where
+    T: FullRepo,
+{
     if let Some(motion_identifier) = repo.motion_identifier::<FileId>(hash.clone()).await? {
         if let Some(new_motion_identifier) = motion_identifier.normalize_for_migration() {
             migrate_identifier_details(repo, &motion_identifier, &new_motion_identifier).await?;
@@ -770,6 +1044,30 @@ impl DeleteToken {
     }
 }
 
+impl From<UploadResult> for InnerUploadResult {
+    fn from(u: UploadResult) -> Self {
+        match u {
+            UploadResult::Success { alias, token } => InnerUploadResult::Success {
+                alias: Serde::new(alias),
+                token: Serde::new(token),
+            },
+            UploadResult::Failure { message } => InnerUploadResult::Failure { message },
+        }
+    }
+}
+
+impl From<InnerUploadResult> for UploadResult {
+    fn from(i: InnerUploadResult) -> Self {
+        match i {
+            InnerUploadResult::Success { alias, token } => UploadResult::Success {
+                alias: Serde::into_inner(alias),
+                token: Serde::into_inner(token),
+            },
+            InnerUploadResult::Failure { message } => UploadResult::Failure { message },
+        }
+    }
+}
+
 impl UploadId {
     pub(crate) fn generate() -> Self {
         Self { id: Uuid::new_v4() }
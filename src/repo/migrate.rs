@@ -0,0 +1,189 @@
+use crate::{
+    error::Error,
+    repo::{
+        AliasRepo, BlocklistRepo, FullRepo, HashRepo, IdentifierRepo, GENERATOR_KEY,
+        REPO_MIGRATION_02, REPO_MIGRATION_O1, STORE_MIGRATION_PROGRESS,
+    },
+};
+use futures_util::StreamExt;
+use std::time::Duration;
+
+const REPO_MIGRATION_PROGRESS: &str = "repo-migration-progress";
+
+// Settings that describe the state of the media store rather than any one hash, and need to follow
+// the metadata into the new repo
+const MIGRATED_SETTINGS: &[&str] = &[
+    GENERATOR_KEY,
+    REPO_MIGRATION_O1,
+    REPO_MIGRATION_02,
+    STORE_MIGRATION_PROGRESS,
+];
+
+const PROGRESS_INTERVAL: u64 = 1000;
+
+pub(super) async fn migrate_repo<R1, R2>(from: &R1, to: &R2) -> Result<(), Error>
+where
+    R1: FullRepo,
+    R2: FullRepo,
+{
+    let mut failure_count = 0;
+
+    while let Err(e) = do_migrate_repo(from, to).await {
+        tracing::error!("Failed with {}", e.to_string());
+        failure_count += 1;
+
+        tokio::time::sleep(Duration::from_secs(5)).await;
+
+        if failure_count >= 50 {
+            tracing::error!("Exceeded 50 errors");
+            return Err(e);
+        }
+    }
+
+    Ok(())
+}
+
+async fn do_migrate_repo<R1, R2>(from: &R1, to: &R2) -> Result<(), Error>
+where
+    R1: FullRepo,
+    R2: FullRepo,
+{
+    for key in MIGRATED_SETTINGS {
+        if let Some(value) = from.get(key).await? {
+            to.set(key, value.as_ref().to_vec().into()).await?;
+        }
+    }
+
+    // Blocked hashes usually no longer have any media, so they aren't reached through the hashes
+    // below
+    for hash in BlocklistRepo::blocked(from).await? {
+        to.block(hash.as_ref().to_vec().into()).await?;
+    }
+
+    let stream = from.hashes().await;
+    let mut stream = Box::pin(stream);
+
+    // Progress is recorded in the destination repo, since that's the one that keeps being used
+    // after the migration finishes
+    let mut progress_opt = to.get(REPO_MIGRATION_PROGRESS).await?;
+
+    if progress_opt.is_some() {
+        tracing::warn!("Resuming previous repo migration");
+    }
+
+    let mut skipped: u64 = 0;
+    let mut migrated: u64 = 0;
+    let mut next_progress = PROGRESS_INTERVAL;
+
+    while let Some(hash) = stream.next().await {
+        let hash = hash?;
+
+        if let Some(progress) = &progress_opt {
+            if progress.as_ref() == hash.as_ref() {
+                progress_opt.take();
+            }
+            skipped += 1;
+            continue;
+        }
+
+        migrate_hash(from, to, hash.as_ref()).await?;
+
+        to.set(REPO_MIGRATION_PROGRESS, hash.as_ref().to_vec().into())
+            .await?;
+
+        migrated += 1;
+        if migrated >= next_progress {
+            next_progress += PROGRESS_INTERVAL;
+            tracing::warn!("Migrated {migrated} hashes ({skipped} previously migrated)");
+        }
+    }
+
+    tracing::warn!(
+        "Repo migration complete: migrated {migrated} hashes ({skipped} previously migrated)"
+    );
+
+    // clean up the migration key to avoid interfering with future migrations
+    to.remove(REPO_MIGRATION_PROGRESS).await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(skip(from, to, hash), fields(hash = hex::encode(hash)))]
+async fn migrate_hash<R1, R2>(from: &R1, to: &R2, hash: &[u8]) -> Result<(), Error>
+where
+    R1: FullRepo,
+    R2: FullRepo,
+{
+    let from_hash: R1::Bytes = hash.to_vec().into();
+    let to_hash: R2::Bytes = hash.to_vec().into();
+
+    // An interrupted migration may have already created this hash, in which case everything is
+    // related again below
+    if HashRepo::create(to, to_hash.clone()).await?.is_err() {
+        tracing::debug!("Hash already exists in destination repo");
+    }
+
+    let identifier = from.identifier::<Vec<u8>>(from_hash.clone()).await?;
+    migrate_details(from, to, &identifier).await?;
+    to.relate_identifier(to_hash.clone(), &identifier).await?;
+
+    if let Some(motion_identifier) = from.motion_identifier::<Vec<u8>>(from_hash.clone()).await? {
+        migrate_details(from, to, &motion_identifier).await?;
+        to.relate_motion_identifier(to_hash.clone(), &motion_identifier)
+            .await?;
+    }
+
+    if let Some(perceptual_hash) = from.perceptual_hash(from_hash.clone()).await? {
+        to.relate_perceptual_hash(to_hash.clone(), perceptual_hash)
+            .await?;
+    }
+
+    for (variant, variant_identifier) in from.variants::<Vec<u8>>(from_hash.clone()).await? {
+        migrate_details(from, to, &variant_identifier).await?;
+        to.relate_variant_identifier(to_hash.clone(), variant.clone(), &variant_identifier)
+            .await?;
+
+        if let Some(accessed_at) = from.accessed_at(from_hash.clone(), variant.clone()).await? {
+            to.mark_accessed(to_hash.clone(), variant, accessed_at)
+                .await?;
+        }
+    }
+
+    for alias in from.aliases(from_hash).await? {
+        let _ = AliasRepo::create(to, &alias).await?;
+
+        if let Ok(delete_token) = from.delete_token(&alias).await {
+            let _ = to.relate_delete_token(&alias, &delete_token).await?;
+        }
+
+        to.relate_hash(&alias, to_hash.clone()).await?;
+        to.relate_alias(to_hash.clone(), &alias).await?;
+
+        if let Some(expires_at) = from.cached_expiry(&alias).await? {
+            to.mark_cached(&alias, expires_at).await?;
+        }
+
+        if let Some(focal_point) = from.focal_point(&alias).await? {
+            to.relate_focal_point(&alias, focal_point).await?;
+        }
+
+        for (hash, variant) in from.overlay_variants(&alias).await? {
+            to.relate_overlay_variant(&alias, hash.as_ref().to_vec().into(), variant)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn migrate_details<R1, R2>(from: &R1, to: &R2, identifier: &Vec<u8>) -> Result<(), Error>
+where
+    R1: IdentifierRepo,
+    R2: IdentifierRepo,
+{
+    if let Some(details) = from.details(identifier).await? {
+        to.relate_details(identifier, &details).await?;
+    }
+
+    Ok(())
+}
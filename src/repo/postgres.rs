@@ -0,0 +1,1450 @@
+use crate::{
+    error::{Error, UploadError},
+    processor::FocalPoint,
+    repo::{
+        Alias, AliasRepo, AlreadyExists, BaseRepo, BlocklistRepo, CachedRepo, DeleteToken, Details,
+        FullRepo, HashRepo, Identifier, IdentifierRepo, InnerUploadResult, QueueRepo, SettingsRepo,
+        UploadId, UploadRepo, UploadResult, VariantAccessRepo,
+    },
+};
+use deadpool_postgres::{
+    tokio_postgres::{types::ToSql, NoTls, Row},
+    Manager, ManagerConfig, Object, Pool, RecyclingMethod,
+};
+use futures_util::Stream;
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+use time::OffsetDateTime;
+use tokio::sync::Notify;
+use url::Url;
+
+const MIGRATIONS: &[(i32, &str)] = &[
+    (1, include_str!("postgres/migrations/V001__initial.sql")),
+    (
+        2,
+        include_str!("postgres/migrations/V002__alias_expiry.sql"),
+    ),
+    (
+        3,
+        include_str!("postgres/migrations/V003__perceptual_hash.sql"),
+    ),
+    (4, include_str!("postgres/migrations/V004__blocklist.sql")),
+    (5, include_str!("postgres/migrations/V005__focal_point.sql")),
+    (
+        6,
+        include_str!("postgres/migrations/V006__overlay_variants.sql"),
+    ),
+    (
+        7,
+        include_str!("postgres/migrations/V007__variant_access.sql"),
+    ),
+];
+
+// Arbitrary key for the advisory lock held while migrations run, so multiple pict-rs instances
+// starting against the same database don't race each other
+const MIGRATION_LOCK: i64 = 0x0070_6963_742d_7273;
+
+// Other pict-rs instances can push jobs or complete uploads without notifying this process, so
+// waiters wake up periodically to check the database
+const QUEUE_POLL_INTERVAL: Duration = Duration::from_secs(5);
+const UPLOAD_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+const HASHES_PAGE_SIZE: i64 = 64;
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum PostgresError {
+    #[error("Error in database")]
+    Postgres(#[from] deadpool_postgres::tokio_postgres::Error),
+
+    #[error("Error getting connection from pool")]
+    Pool(#[from] deadpool_postgres::PoolError),
+
+    #[error("Invalid details json")]
+    Details(#[from] serde_json::Error),
+
+    #[error("Required field was not present")]
+    Missing,
+}
+
+#[derive(Clone)]
+pub(crate) struct PostgresRepo {
+    pool: Pool,
+    queue_notifier: Arc<RwLock<HashMap<&'static str, Arc<Notify>>>>,
+    upload_notifier: Arc<Notify>,
+}
+
+impl PostgresRepo {
+    pub(crate) async fn connect(url: Url) -> color_eyre::Result<Self> {
+        let config = url
+            .as_str()
+            .parse::<deadpool_postgres::tokio_postgres::Config>()?;
+
+        let manager = Manager::from_config(
+            config,
+            NoTls,
+            ManagerConfig {
+                recycling_method: RecyclingMethod::Fast,
+            },
+        );
+
+        let pool = Pool::builder(manager).build()?;
+
+        let repo = PostgresRepo {
+            pool,
+            queue_notifier: Arc::new(RwLock::new(HashMap::new())),
+            upload_notifier: Arc::new(Notify::new()),
+        };
+
+        repo.run_migrations().await?;
+
+        Ok(repo)
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn run_migrations(&self) -> Result<(), PostgresError> {
+        let mut client = self.pool.get().await?;
+        let transaction = client.transaction().await?;
+
+        transaction
+            .execute("SELECT pg_advisory_xact_lock($1)", &[&MIGRATION_LOCK])
+            .await?;
+
+        transaction
+            .batch_execute(
+                "CREATE TABLE IF NOT EXISTS pict_rs_migrations (
+                    version INTEGER PRIMARY KEY,
+                    applied_at TIMESTAMPTZ NOT NULL DEFAULT now()
+                )",
+            )
+            .await?;
+
+        for (version, sql) in MIGRATIONS {
+            let applied = transaction
+                .query_opt(
+                    "SELECT version FROM pict_rs_migrations WHERE version = $1",
+                    &[version],
+                )
+                .await?
+                .is_some();
+
+            if applied {
+                continue;
+            }
+
+            tracing::warn!("Applying postgres migration {version}");
+
+            transaction.batch_execute(sql).await?;
+            transaction
+                .execute(
+                    "INSERT INTO pict_rs_migrations (version) VALUES ($1)",
+                    &[version],
+                )
+                .await?;
+        }
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    async fn client(&self) -> Result<Object, PostgresError> {
+        Ok(self.pool.get().await?)
+    }
+
+    async fn execute(&self, sql: &str, params: &[&(dyn ToSql + Sync)]) -> Result<u64, Error> {
+        let client = self.client().await?;
+        let statement = client
+            .prepare_cached(sql)
+            .await
+            .map_err(PostgresError::from)?;
+
+        let count = client
+            .execute(&statement, params)
+            .await
+            .map_err(PostgresError::from)?;
+
+        Ok(count)
+    }
+
+    async fn query(&self, sql: &str, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, Error> {
+        let client = self.client().await?;
+        let statement = client
+            .prepare_cached(sql)
+            .await
+            .map_err(PostgresError::from)?;
+
+        let rows = client
+            .query(&statement, params)
+            .await
+            .map_err(PostgresError::from)?;
+
+        Ok(rows)
+    }
+
+    async fn query_opt(
+        &self,
+        sql: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Option<Row>, Error> {
+        let client = self.client().await?;
+        let statement = client
+            .prepare_cached(sql)
+            .await
+            .map_err(PostgresError::from)?;
+
+        let opt = client
+            .query_opt(&statement, params)
+            .await
+            .map_err(PostgresError::from)?;
+
+        Ok(opt)
+    }
+
+    fn queue_notifier(&self, queue_name: &'static str) -> Arc<Notify> {
+        let opt = self
+            .queue_notifier
+            .read()
+            .unwrap()
+            .get(&queue_name)
+            .map(Arc::clone);
+
+        if let Some(notify) = opt {
+            notify
+        } else {
+            let mut guard = self.queue_notifier.write().unwrap();
+            let entry = guard
+                .entry(queue_name)
+                .or_insert_with(|| Arc::new(Notify::new()));
+            Arc::clone(entry)
+        }
+    }
+}
+
+impl BaseRepo for PostgresRepo {
+    type Bytes = Vec<u8>;
+}
+
+#[async_trait::async_trait(?Send)]
+impl FullRepo for PostgresRepo {
+    async fn health_check(&self) -> Result<(), Error> {
+        self.query("SELECT 1", &[]).await?;
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl UploadRepo for PostgresRepo {
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn create(&self, upload_id: UploadId) -> Result<(), Error> {
+        self.execute(
+            "INSERT INTO uploads (upload_id) VALUES ($1)",
+            &[&upload_id.id],
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn wait(&self, upload_id: UploadId) -> Result<UploadResult, Error> {
+        loop {
+            let opt = self
+                .query_opt(
+                    "SELECT result FROM uploads WHERE upload_id = $1",
+                    &[&upload_id.id],
+                )
+                .await?;
+
+            let Some(row) = opt else {
+                return Err(UploadError::AlreadyClaimed.into());
+            };
+
+            if let Some(result) = row.get::<_, Option<serde_json::Value>>(0) {
+                let result: InnerUploadResult =
+                    serde_json::from_value(result).map_err(PostgresError::from)?;
+                return Ok(result.into());
+            }
+
+            let _ = actix_rt::time::timeout(UPLOAD_POLL_INTERVAL, self.upload_notifier.notified())
+                .await;
+        }
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn claim(&self, upload_id: UploadId) -> Result<(), Error> {
+        self.execute("DELETE FROM uploads WHERE upload_id = $1", &[&upload_id.id])
+            .await?;
+
+        self.upload_notifier.notify_waiters();
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "trace", skip(self, result))]
+    async fn complete(&self, upload_id: UploadId, result: UploadResult) -> Result<(), Error> {
+        let result: InnerUploadResult = result.into();
+        let result = serde_json::to_value(&result).map_err(PostgresError::from)?;
+
+        self.execute(
+            "UPDATE uploads SET result = $2 WHERE upload_id = $1",
+            &[&upload_id.id, &result],
+        )
+        .await?;
+
+        self.upload_notifier.notify_waiters();
+
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl QueueRepo for PostgresRepo {
+    #[tracing::instrument(skip_all, fields(worker_id = %String::from_utf8_lossy(&worker_prefix)))]
+    async fn requeue_in_progress(&self, worker_prefix: Vec<u8>) -> Result<(), Error> {
+        self.execute(
+            "UPDATE job_queue SET worker_id = NULL
+            WHERE substring(worker_id FROM 1 FOR length($1::bytea)) = $1",
+            &[&worker_prefix],
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self, job), fields(job = %String::from_utf8_lossy(&job)))]
+    async fn push(&self, queue_name: &'static str, job: Self::Bytes) -> Result<(), Error> {
+        self.execute(
+            "INSERT INTO job_queue (queue, job) VALUES ($1, $2)",
+            &[&queue_name, &job],
+        )
+        .await?;
+
+        self.queue_notifier(queue_name).notify_one();
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self, worker_id), fields(worker_id = %String::from_utf8_lossy(&worker_id)))]
+    async fn pop(
+        &self,
+        queue_name: &'static str,
+        worker_id: Vec<u8>,
+    ) -> Result<Self::Bytes, Error> {
+        // A worker only pops its next job once it has finished the previous one
+        self.execute("DELETE FROM job_queue WHERE worker_id = $1", &[&worker_id])
+            .await?;
+
+        loop {
+            let opt = self
+                .query_opt(
+                    "UPDATE job_queue SET worker_id = $2
+                    WHERE id = (
+                        SELECT id FROM job_queue
+                        WHERE queue = $1 AND worker_id IS NULL
+                        ORDER BY id
+                        LIMIT 1
+                        FOR UPDATE SKIP LOCKED
+                    )
+                    RETURNING job",
+                    &[&queue_name, &worker_id],
+                )
+                .await?;
+
+            if let Some(row) = opt {
+                return Ok(row.get(0));
+            }
+
+            let notify = self.queue_notifier(queue_name);
+
+            let _ = actix_rt::time::timeout(QUEUE_POLL_INTERVAL, notify.notified()).await;
+        }
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn queue_length(&self, queue_name: &'static str) -> Result<u64, Error> {
+        let row = self
+            .query_opt(
+                "SELECT count(*) FROM job_queue WHERE queue = $1 AND worker_id IS NULL",
+                &[&queue_name],
+            )
+            .await?
+            .ok_or(PostgresError::Missing)?;
+
+        let count: i64 = row.get(0);
+
+        Ok(count as u64)
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl SettingsRepo for PostgresRepo {
+    #[tracing::instrument(level = "trace", skip(value))]
+    async fn set(&self, key: &'static str, value: Self::Bytes) -> Result<(), Error> {
+        self.execute(
+            "INSERT INTO settings (key, value) VALUES ($1, $2)
+            ON CONFLICT (key) DO UPDATE SET value = EXCLUDED.value",
+            &[&key, &value],
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn get(&self, key: &'static str) -> Result<Option<Self::Bytes>, Error> {
+        let opt = self
+            .query_opt("SELECT value FROM settings WHERE key = $1", &[&key])
+            .await?;
+
+        Ok(opt.map(|row| row.get(0)))
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn remove(&self, key: &'static str) -> Result<(), Error> {
+        self.execute("DELETE FROM settings WHERE key = $1", &[&key])
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl IdentifierRepo for PostgresRepo {
+    #[tracing::instrument(level = "trace", skip(self, identifier), fields(identifier = identifier.string_repr()))]
+    async fn relate_details<I: Identifier>(
+        &self,
+        identifier: &I,
+        details: &Details,
+    ) -> Result<(), Error> {
+        let key = identifier.to_bytes()?;
+        let details = serde_json::to_value(details).map_err(PostgresError::from)?;
+
+        self.execute(
+            "INSERT INTO details (identifier, json) VALUES ($1, $2)
+            ON CONFLICT (identifier) DO UPDATE SET json = EXCLUDED.json",
+            &[&key, &details],
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "trace", skip(self, identifier), fields(identifier = identifier.string_repr()))]
+    async fn details<I: Identifier>(&self, identifier: &I) -> Result<Option<Details>, Error> {
+        let key = identifier.to_bytes()?;
+
+        let opt = self
+            .query_opt("SELECT json FROM details WHERE identifier = $1", &[&key])
+            .await?;
+
+        opt.map(|row| serde_json::from_value(row.get(0)))
+            .transpose()
+            .map_err(PostgresError::from)
+            .map_err(Error::from)
+    }
+
+    #[tracing::instrument(level = "trace", skip(self, identifier), fields(identifier = identifier.string_repr()))]
+    async fn cleanup<I: Identifier>(&self, identifier: &I) -> Result<(), Error> {
+        let key = identifier.to_bytes()?;
+
+        self.execute("DELETE FROM details WHERE identifier = $1", &[&key])
+            .await?;
+
+        Ok(())
+    }
+}
+
+type StreamItem = Result<Vec<u8>, Error>;
+type LocalBoxStream<'a, T> = Pin<Box<dyn Stream<Item = T> + 'a>>;
+
+#[async_trait::async_trait(?Send)]
+impl HashRepo for PostgresRepo {
+    type Stream = LocalBoxStream<'static, StreamItem>;
+
+    async fn hashes(&self) -> Self::Stream {
+        use futures_util::StreamExt;
+
+        let repo = self.clone();
+
+        let pages = futures_util::stream::unfold(Some(Vec::new()), move |cursor| {
+            let repo = repo.clone();
+
+            async move {
+                let cursor = cursor?;
+
+                let res = repo
+                    .query(
+                        "SELECT hash FROM hashes WHERE hash > $1 ORDER BY hash LIMIT $2",
+                        &[&cursor, &HASHES_PAGE_SIZE],
+                    )
+                    .await;
+
+                match res {
+                    Ok(rows) if rows.is_empty() => None,
+                    Ok(rows) => {
+                        let page = rows
+                            .into_iter()
+                            .map(|row| row.get::<_, Vec<u8>>(0))
+                            .collect::<Vec<_>>();
+                        let next = page.last().cloned();
+
+                        Some((page.into_iter().map(Ok).collect::<Vec<_>>(), next))
+                    }
+                    Err(e) => Some((vec![Err(e)], None)),
+                }
+            }
+        });
+
+        Box::pin(pages.flat_map(futures_util::stream::iter))
+    }
+
+    #[tracing::instrument(level = "trace", skip(self, after))]
+    async fn hashes_after(
+        &self,
+        after: Option<Self::Bytes>,
+        limit: usize,
+    ) -> Result<Vec<Self::Bytes>, Error> {
+        // Every hash sorts after the empty one
+        let after = after.unwrap_or_default();
+        let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+
+        let rows = self
+            .query(
+                "SELECT hash FROM hashes WHERE hash > $1 ORDER BY hash LIMIT $2",
+                &[&after, &limit],
+            )
+            .await?;
+
+        Ok(rows.into_iter().map(|row| row.get(0)).collect())
+    }
+
+    #[tracing::instrument(level = "trace", skip(self, hash), fields(hash = hex::encode(&hash)))]
+    async fn create(&self, hash: Self::Bytes) -> Result<Result<(), AlreadyExists>, Error> {
+        let count = self
+            .execute(
+                "INSERT INTO hashes (hash) VALUES ($1) ON CONFLICT DO NOTHING",
+                &[&hash],
+            )
+            .await?;
+
+        if count == 0 {
+            Ok(Err(AlreadyExists))
+        } else {
+            Ok(Ok(()))
+        }
+    }
+
+    #[tracing::instrument(level = "trace", skip(self, hash), fields(hash = hex::encode(&hash)))]
+    async fn relate_alias(&self, hash: Self::Bytes, alias: &Alias) -> Result<(), Error> {
+        self.execute(
+            "INSERT INTO hash_aliases (hash, alias) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+            &[&hash, &alias.to_string()],
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "trace", skip(self, hash), fields(hash = hex::encode(&hash)))]
+    async fn remove_alias(&self, hash: Self::Bytes, alias: &Alias) -> Result<(), Error> {
+        self.execute(
+            "DELETE FROM hash_aliases WHERE hash = $1 AND alias = $2",
+            &[&hash, &alias.to_string()],
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn aliases(&self, hash: Self::Bytes) -> Result<Vec<Alias>, Error> {
+        let rows = self
+            .query("SELECT alias FROM hash_aliases WHERE hash = $1", &[&hash])
+            .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| Alias::from_existing(row.get(0)))
+            .collect())
+    }
+
+    #[tracing::instrument(level = "trace", skip(self, hash, identifier), fields(hash = hex::encode(&hash), identifier = identifier.string_repr()))]
+    async fn relate_identifier<I: Identifier>(
+        &self,
+        hash: Self::Bytes,
+        identifier: &I,
+    ) -> Result<(), Error> {
+        let bytes = identifier.to_bytes()?;
+
+        self.execute(
+            "UPDATE hashes SET identifier = $2 WHERE hash = $1",
+            &[&hash, &bytes],
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "trace", skip(self, hash), fields(hash = hex::encode(&hash)))]
+    async fn identifier<I: Identifier + 'static>(&self, hash: Self::Bytes) -> Result<I, Error> {
+        let opt = self
+            .query_opt("SELECT identifier FROM hashes WHERE hash = $1", &[&hash])
+            .await?;
+
+        opt.and_then(|row| row.get::<_, Option<Vec<u8>>>(0))
+            .ok_or(PostgresError::Missing)
+            .map_err(Error::from)
+            .and_then(I::from_bytes)
+    }
+
+    #[tracing::instrument(level = "trace", skip(self, hash, identifier), fields(hash = hex::encode(&hash), identifier = identifier.string_repr()))]
+    async fn relate_variant_identifier<I: Identifier>(
+        &self,
+        hash: Self::Bytes,
+        variant: String,
+        identifier: &I,
+    ) -> Result<(), Error> {
+        let bytes = identifier.to_bytes()?;
+
+        self.execute(
+            "INSERT INTO variants (hash, variant, identifier) VALUES ($1, $2, $3)
+            ON CONFLICT (hash, variant) DO UPDATE SET identifier = EXCLUDED.identifier",
+            &[&hash, &variant, &bytes],
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "trace", skip(self, hash), fields(hash = hex::encode(&hash)))]
+    async fn variant_identifier<I: Identifier + 'static>(
+        &self,
+        hash: Self::Bytes,
+        variant: String,
+    ) -> Result<Option<I>, Error> {
+        let opt = self
+            .query_opt(
+                "SELECT identifier FROM variants WHERE hash = $1 AND variant = $2",
+                &[&hash, &variant],
+            )
+            .await?;
+
+        opt.map(|row| I::from_bytes(row.get(0))).transpose()
+    }
+
+    #[tracing::instrument(skip(self, hash), fields(hash = hex::encode(&hash)))]
+    async fn variants<I: Identifier + 'static>(
+        &self,
+        hash: Self::Bytes,
+    ) -> Result<Vec<(String, I)>, Error> {
+        let rows = self
+            .query(
+                "SELECT variant, identifier FROM variants WHERE hash = $1",
+                &[&hash],
+            )
+            .await?;
+
+        let vec = rows
+            .into_iter()
+            .filter_map(|row| {
+                let variant: String = row.get(0);
+                let bytes: Vec<u8> = row.get(1);
+
+                let identifier = I::from_bytes(bytes.clone()).ok();
+                if identifier.is_none() {
+                    tracing::warn!(
+                        "Skipping an identifier: {}",
+                        String::from_utf8_lossy(&bytes)
+                    );
+                }
+
+                Some((variant, identifier?))
+            })
+            .collect();
+
+        Ok(vec)
+    }
+
+    #[tracing::instrument(level = "trace", skip(self, hash), fields(hash = hex::encode(&hash)))]
+    async fn remove_variant(&self, hash: Self::Bytes, variant: String) -> Result<(), Error> {
+        self.execute(
+            "DELETE FROM variants WHERE hash = $1 AND variant = $2",
+            &[&hash, &variant],
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "trace", skip(self, hash, identifier), fields(hash = hex::encode(&hash), identifier = identifier.string_repr()))]
+    async fn relate_motion_identifier<I: Identifier>(
+        &self,
+        hash: Self::Bytes,
+        identifier: &I,
+    ) -> Result<(), Error> {
+        let bytes = identifier.to_bytes()?;
+
+        self.execute(
+            "UPDATE hashes SET motion_identifier = $2 WHERE hash = $1",
+            &[&hash, &bytes],
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "trace", skip(self, hash), fields(hash = hex::encode(&hash)))]
+    async fn motion_identifier<I: Identifier + 'static>(
+        &self,
+        hash: Self::Bytes,
+    ) -> Result<Option<I>, Error> {
+        let opt = self
+            .query_opt(
+                "SELECT motion_identifier FROM hashes WHERE hash = $1",
+                &[&hash],
+            )
+            .await?;
+
+        opt.and_then(|row| row.get::<_, Option<Vec<u8>>>(0))
+            .map(I::from_bytes)
+            .transpose()
+    }
+
+    #[tracing::instrument(level = "trace", skip(self, hash), fields(hash = hex::encode(&hash)))]
+    async fn relate_perceptual_hash(
+        &self,
+        hash: Self::Bytes,
+        perceptual_hash: u64,
+    ) -> Result<(), Error> {
+        // BIGINT is signed, so the bits are stored as-is and reinterpreted when read back
+        self.execute(
+            "UPDATE hashes SET perceptual_hash = $2 WHERE hash = $1",
+            &[&hash, &(perceptual_hash as i64)],
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "trace", skip(self, hash), fields(hash = hex::encode(&hash)))]
+    async fn perceptual_hash(&self, hash: Self::Bytes) -> Result<Option<u64>, Error> {
+        let opt = self
+            .query_opt(
+                "SELECT perceptual_hash FROM hashes WHERE hash = $1",
+                &[&hash],
+            )
+            .await?;
+
+        Ok(opt
+            .and_then(|row| row.get::<_, Option<i64>>(0))
+            .map(|perceptual_hash| perceptual_hash as u64))
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn similar_hashes(
+        &self,
+        perceptual_hash: u64,
+        max_distance: u32,
+    ) -> Result<Vec<(Self::Bytes, u64)>, Error> {
+        // Counting the set bits of the XOR through its text form works on every supported
+        // postgres version
+        let rows = self
+            .query(
+                "SELECT hash, perceptual_hash FROM hashes
+                WHERE perceptual_hash IS NOT NULL
+                AND length(replace(((perceptual_hash # $1)::bit(64))::text, '0', '')) <= $2",
+                &[&(perceptual_hash as i64), &(max_distance as i32)],
+            )
+            .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.get(0), row.get::<_, i64>(1) as u64))
+            .collect())
+    }
+
+    #[tracing::instrument(skip(self, hash), fields(hash = hex::encode(&hash)))]
+    async fn cleanup(&self, hash: Self::Bytes) -> Result<(), Error> {
+        let mut client = self.client().await?;
+        let transaction = client.transaction().await.map_err(PostgresError::from)?;
+
+        for sql in [
+            "DELETE FROM hashes WHERE hash = $1",
+            "DELETE FROM hash_aliases WHERE hash = $1",
+            "DELETE FROM variants WHERE hash = $1",
+        ] {
+            transaction
+                .execute(sql, &[&hash])
+                .await
+                .map_err(PostgresError::from)?;
+        }
+
+        transaction.commit().await.map_err(PostgresError::from)?;
+
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl AliasRepo for PostgresRepo {
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn create(&self, alias: &Alias) -> Result<Result<(), AlreadyExists>, Error> {
+        let count = self
+            .execute(
+                "INSERT INTO aliases (alias) VALUES ($1) ON CONFLICT DO NOTHING",
+                &[&alias.to_string()],
+            )
+            .await?;
+
+        if count == 0 {
+            Ok(Err(AlreadyExists))
+        } else {
+            Ok(Ok(()))
+        }
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn relate_delete_token(
+        &self,
+        alias: &Alias,
+        delete_token: &DeleteToken,
+    ) -> Result<Result<(), AlreadyExists>, Error> {
+        let count = self
+            .execute(
+                "INSERT INTO aliases (alias, delete_token) VALUES ($1, $2)
+                ON CONFLICT (alias) DO UPDATE SET delete_token = EXCLUDED.delete_token
+                WHERE aliases.delete_token IS NULL",
+                &[&alias.to_string(), &delete_token.to_string()],
+            )
+            .await?;
+
+        if count == 0 {
+            Ok(Err(AlreadyExists))
+        } else {
+            Ok(Ok(()))
+        }
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn delete_token(&self, alias: &Alias) -> Result<DeleteToken, Error> {
+        let opt = self
+            .query_opt(
+                "SELECT delete_token FROM aliases WHERE alias = $1",
+                &[&alias.to_string()],
+            )
+            .await?;
+
+        opt.and_then(|row| {
+            row.get::<_, Option<&str>>(0)
+                .map(DeleteToken::from_existing)
+        })
+        .ok_or(PostgresError::Missing)
+        .map_err(Error::from)
+    }
+
+    #[tracing::instrument(level = "trace", skip(self, hash), fields(hash = hex::encode(&hash)))]
+    async fn relate_hash(&self, alias: &Alias, hash: Self::Bytes) -> Result<(), Error> {
+        self.execute(
+            "INSERT INTO aliases (alias, hash) VALUES ($1, $2)
+            ON CONFLICT (alias) DO UPDATE SET hash = EXCLUDED.hash",
+            &[&alias.to_string(), &hash],
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn hash(&self, alias: &Alias) -> Result<Self::Bytes, Error> {
+        let opt = self
+            .query_opt(
+                "SELECT hash FROM aliases WHERE alias = $1",
+                &[&alias.to_string()],
+            )
+            .await?;
+
+        opt.and_then(|row| row.get::<_, Option<Vec<u8>>>(0))
+            .ok_or(PostgresError::Missing)
+            .map_err(Error::from)
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn relate_focal_point(
+        &self,
+        alias: &Alias,
+        focal_point: FocalPoint,
+    ) -> Result<(), Error> {
+        self.execute(
+            "UPDATE aliases SET focal_x = $2, focal_y = $3 WHERE alias = $1",
+            &[&alias.to_string(), &focal_point.x, &focal_point.y],
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn focal_point(&self, alias: &Alias) -> Result<Option<FocalPoint>, Error> {
+        let opt = self
+            .query_opt(
+                "SELECT focal_x, focal_y FROM aliases WHERE alias = $1",
+                &[&alias.to_string()],
+            )
+            .await?;
+
+        Ok(opt.and_then(|row| {
+            Some(FocalPoint {
+                x: row.get::<_, Option<f64>>(0)?,
+                y: row.get::<_, Option<f64>>(1)?,
+            })
+        }))
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn remove_focal_point(&self, alias: &Alias) -> Result<(), Error> {
+        self.execute(
+            "UPDATE aliases SET focal_x = NULL, focal_y = NULL WHERE alias = $1",
+            &[&alias.to_string()],
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "trace", skip(self, hash), fields(hash = hex::encode(&hash)))]
+    async fn relate_overlay_variant(
+        &self,
+        overlay: &Alias,
+        hash: Self::Bytes,
+        variant: String,
+    ) -> Result<(), Error> {
+        self.execute(
+            "INSERT INTO overlay_variants (overlay, hash, variant) VALUES ($1, $2, $3)
+            ON CONFLICT DO NOTHING",
+            &[&overlay.to_string(), &hash, &variant],
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn overlay_variants(&self, overlay: &Alias) -> Result<Vec<(Self::Bytes, String)>, Error> {
+        let rows = self
+            .query(
+                "SELECT hash, variant FROM overlay_variants WHERE overlay = $1",
+                &[&overlay.to_string()],
+            )
+            .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| (row.get::<_, Vec<u8>>(0), row.get::<_, String>(1)))
+            .collect())
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn remove_overlay_variants(&self, overlay: &Alias) -> Result<(), Error> {
+        self.execute(
+            "DELETE FROM overlay_variants WHERE overlay = $1",
+            &[&overlay.to_string()],
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn cleanup(&self, alias: &Alias) -> Result<(), Error> {
+        self.execute(
+            "DELETE FROM aliases WHERE alias = $1",
+            &[&alias.to_string()],
+        )
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl CachedRepo for PostgresRepo {
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn mark_cached(&self, alias: &Alias, expires_at: OffsetDateTime) -> Result<(), Error> {
+        self.execute(
+            "UPDATE aliases SET expires_at = $2 WHERE alias = $1",
+            &[&alias.to_string(), &expires_at],
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn cached_expiry(&self, alias: &Alias) -> Result<Option<OffsetDateTime>, Error> {
+        let opt = self
+            .query_opt(
+                "SELECT expires_at FROM aliases WHERE alias = $1",
+                &[&alias.to_string()],
+            )
+            .await?;
+
+        Ok(opt.and_then(|row| row.get(0)))
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn expired(&self, now: OffsetDateTime) -> Result<Vec<Alias>, Error> {
+        let rows = self
+            .query(
+                "SELECT alias FROM aliases WHERE expires_at < $1 ORDER BY expires_at",
+                &[&now],
+            )
+            .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| Alias::from_existing(row.get(0)))
+            .collect())
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl VariantAccessRepo for PostgresRepo {
+    #[tracing::instrument(level = "trace", skip(self, hash), fields(hash = hex::encode(&hash)))]
+    async fn mark_accessed(
+        &self,
+        hash: Self::Bytes,
+        variant: String,
+        accessed_at: OffsetDateTime,
+    ) -> Result<(), Error> {
+        self.execute(
+            "UPDATE variants SET accessed_at = $3 WHERE hash = $1 AND variant = $2",
+            &[&hash, &variant, &accessed_at],
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "trace", skip(self, hash), fields(hash = hex::encode(&hash)))]
+    async fn accessed_at(
+        &self,
+        hash: Self::Bytes,
+        variant: String,
+    ) -> Result<Option<OffsetDateTime>, Error> {
+        let opt = self
+            .query_opt(
+                "SELECT accessed_at FROM variants WHERE hash = $1 AND variant = $2",
+                &[&hash, &variant],
+            )
+            .await?;
+
+        Ok(opt.map(|row| row.get(0)))
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn accessed_before(
+        &self,
+        timestamp: OffsetDateTime,
+    ) -> Result<Vec<(Self::Bytes, String)>, Error> {
+        let rows = self
+            .query(
+                "SELECT hash, variant FROM variants WHERE accessed_at < $1 ORDER BY accessed_at",
+                &[&timestamp],
+            )
+            .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.get(0), row.get(1)))
+            .collect())
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl BlocklistRepo for PostgresRepo {
+    #[tracing::instrument(level = "trace", skip(self, hash), fields(hash = hex::encode(&hash)))]
+    async fn block(&self, hash: Self::Bytes) -> Result<(), Error> {
+        self.execute(
+            "INSERT INTO blocklist (hash) VALUES ($1) ON CONFLICT DO NOTHING",
+            &[&hash],
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "trace", skip(self, hash), fields(hash = hex::encode(&hash)))]
+    async fn unblock(&self, hash: Self::Bytes) -> Result<(), Error> {
+        self.execute("DELETE FROM blocklist WHERE hash = $1", &[&hash])
+            .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "trace", skip(self, hash), fields(hash = hex::encode(&hash)))]
+    async fn is_blocked(&self, hash: Self::Bytes) -> Result<bool, Error> {
+        let opt = self
+            .query_opt("SELECT hash FROM blocklist WHERE hash = $1", &[&hash])
+            .await?;
+
+        Ok(opt.is_some())
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn blocked(&self) -> Result<Vec<Self::Bytes>, Error> {
+        let rows = self
+            .query("SELECT hash FROM blocklist ORDER BY hash", &[])
+            .await?;
+
+        Ok(rows.into_iter().map(|row| row.get(0)).collect())
+    }
+}
+
+impl std::fmt::Debug for PostgresRepo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PostgresRepo").finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PostgresRepo;
+    use crate::processor::FocalPoint;
+    use crate::repo::{
+        Alias, AliasRepo, BlocklistRepo, CachedRepo, DeleteToken, HashRepo, QueueRepo,
+        SettingsRepo, VariantAccessRepo,
+    };
+
+    // These tests need a database, so they're ignored by default. Run them with
+    // `cargo test -- --ignored` and a database url, e.g.
+    // PICTRS_TEST_POSTGRES_URL=postgres://postgres@localhost:5432/pictrs_test
+    fn test_url() -> url::Url {
+        std::env::var("PICTRS_TEST_POSTGRES_URL")
+            .expect("PICTRS_TEST_POSTGRES_URL is not set")
+            .parse()
+            .expect("Invalid PICTRS_TEST_POSTGRES_URL")
+    }
+
+    #[test]
+    #[ignore = "requires PICTRS_TEST_POSTGRES_URL"]
+    fn hash_and_alias_roundtrip() {
+        let url = test_url();
+
+        actix_rt::System::new().block_on(async move {
+            let repo = PostgresRepo::connect(url).await.unwrap();
+
+            let hash = uuid::Uuid::new_v4().as_bytes().to_vec();
+            let alias = Alias::generate(String::from(".png"));
+            let token = DeleteToken::generate();
+
+            assert!(HashRepo::create(&repo, hash.clone()).await.unwrap().is_ok());
+            assert!(HashRepo::create(&repo, hash.clone())
+                .await
+                .unwrap()
+                .is_err());
+
+            repo.relate_identifier(hash.clone(), &b"identifier".to_vec())
+                .await
+                .unwrap();
+            repo.relate_variant_identifier(
+                hash.clone(),
+                String::from("thumbnail"),
+                &b"variant".to_vec(),
+            )
+            .await
+            .unwrap();
+
+            assert!(AliasRepo::create(&repo, &alias).await.unwrap().is_ok());
+            assert!(AliasRepo::create(&repo, &alias).await.unwrap().is_err());
+            assert!(repo
+                .relate_delete_token(&alias, &token)
+                .await
+                .unwrap()
+                .is_ok());
+            assert!(repo
+                .relate_delete_token(&alias, &DeleteToken::generate())
+                .await
+                .unwrap()
+                .is_err());
+            repo.relate_hash(&alias, hash.clone()).await.unwrap();
+            repo.relate_alias(hash.clone(), &alias).await.unwrap();
+
+            assert_eq!(repo.hash(&alias).await.unwrap(), hash);
+            assert_eq!(repo.delete_token(&alias).await.unwrap(), token);
+            assert_eq!(
+                repo.aliases(hash.clone()).await.unwrap(),
+                vec![alias.clone()]
+            );
+            assert_eq!(
+                repo.identifier::<Vec<u8>>(hash.clone()).await.unwrap(),
+                b"identifier".to_vec()
+            );
+            assert_eq!(
+                repo.variants::<Vec<u8>>(hash.clone()).await.unwrap(),
+                vec![(String::from("thumbnail"), b"variant".to_vec())]
+            );
+
+            AliasRepo::cleanup(&repo, &alias).await.unwrap();
+            HashRepo::cleanup(&repo, hash.clone()).await.unwrap();
+
+            assert!(repo.hash(&alias).await.is_err());
+            assert!(repo.variants::<Vec<u8>>(hash).await.unwrap().is_empty());
+        });
+    }
+
+    #[test]
+    #[ignore = "requires PICTRS_TEST_POSTGRES_URL"]
+    fn cached_alias_expiry() {
+        let url = test_url();
+
+        actix_rt::System::new().block_on(async move {
+            let repo = PostgresRepo::connect(url).await.unwrap();
+
+            let alias = Alias::generate(String::from(".png"));
+            let now = time::OffsetDateTime::now_utc()
+                .replace_nanosecond(0)
+                .unwrap();
+
+            assert!(AliasRepo::create(&repo, &alias).await.unwrap().is_ok());
+            assert_eq!(repo.cached_expiry(&alias).await.unwrap(), None);
+
+            repo.mark_cached(&alias, now).await.unwrap();
+            assert_eq!(repo.cached_expiry(&alias).await.unwrap(), Some(now));
+            assert!(!repo
+                .expired(now - time::Duration::hours(1))
+                .await
+                .unwrap()
+                .contains(&alias));
+            assert!(repo
+                .expired(now + time::Duration::hours(1))
+                .await
+                .unwrap()
+                .contains(&alias));
+
+            AliasRepo::cleanup(&repo, &alias).await.unwrap();
+            assert_eq!(repo.cached_expiry(&alias).await.unwrap(), None);
+        });
+    }
+
+    #[test]
+    #[ignore = "requires PICTRS_TEST_POSTGRES_URL"]
+    fn variant_access_order() {
+        let url = test_url();
+
+        actix_rt::System::new().block_on(async move {
+            let repo = PostgresRepo::connect(url).await.unwrap();
+
+            let hash = uuid::Uuid::new_v4().as_bytes().to_vec();
+            let now = time::OffsetDateTime::now_utc()
+                .replace_nanosecond(0)
+                .unwrap();
+            let earlier = now - time::Duration::days(30);
+
+            assert!(HashRepo::create(&repo, hash.clone()).await.unwrap().is_ok());
+            for variant in ["old", "new"] {
+                repo.relate_variant_identifier(
+                    hash.clone(),
+                    String::from(variant),
+                    &variant.as_bytes().to_vec(),
+                )
+                .await
+                .unwrap();
+            }
+
+            repo.mark_accessed(hash.clone(), String::from("old"), earlier)
+                .await
+                .unwrap();
+            repo.mark_accessed(hash.clone(), String::from("new"), now)
+                .await
+                .unwrap();
+            assert_eq!(
+                repo.accessed_at(hash.clone(), String::from("old"))
+                    .await
+                    .unwrap(),
+                Some(earlier)
+            );
+
+            let accessed = repo
+                .accessed_before(now + time::Duration::hours(1))
+                .await
+                .unwrap()
+                .into_iter()
+                .filter(|(h, _)| *h == hash)
+                .map(|(_, variant)| variant)
+                .collect::<Vec<_>>();
+            assert_eq!(accessed, vec![String::from("old"), String::from("new")]);
+            assert!(!repo
+                .accessed_before(now)
+                .await
+                .unwrap()
+                .contains(&(hash.clone(), String::from("new"))));
+
+            repo.remove_variant(hash.clone(), String::from("old"))
+                .await
+                .unwrap();
+            assert_eq!(
+                repo.accessed_at(hash.clone(), String::from("old"))
+                    .await
+                    .unwrap(),
+                None
+            );
+
+            HashRepo::cleanup(&repo, hash).await.unwrap();
+        });
+    }
+
+    #[test]
+    #[ignore = "requires PICTRS_TEST_POSTGRES_URL"]
+    fn alias_focal_point() {
+        let url = test_url();
+
+        actix_rt::System::new().block_on(async move {
+            let repo = PostgresRepo::connect(url).await.unwrap();
+
+            let alias = Alias::generate(String::from(".png"));
+            let focal_point = FocalPoint::new(0.25, 0.75).unwrap();
+
+            assert!(AliasRepo::create(&repo, &alias).await.unwrap().is_ok());
+            assert_eq!(repo.focal_point(&alias).await.unwrap(), None);
+
+            repo.relate_focal_point(&alias, focal_point).await.unwrap();
+            assert_eq!(repo.focal_point(&alias).await.unwrap(), Some(focal_point));
+
+            repo.remove_focal_point(&alias).await.unwrap();
+            assert_eq!(repo.focal_point(&alias).await.unwrap(), None);
+
+            AliasRepo::cleanup(&repo, &alias).await.unwrap();
+        });
+    }
+
+    #[test]
+    #[ignore = "requires PICTRS_TEST_POSTGRES_URL"]
+    fn overlay_variants_roundtrip() {
+        let url = test_url();
+
+        actix_rt::System::new().block_on(async move {
+            let repo = PostgresRepo::connect(url).await.unwrap();
+
+            let overlay = Alias::generate(String::from(".png"));
+            let hash = uuid::Uuid::new_v4().as_bytes().to_vec();
+            let variant = format!("overlay/{overlay}/southeast,0,0,100/jpg");
+
+            assert!(repo.overlay_variants(&overlay).await.unwrap().is_empty());
+
+            // Regenerating a variant records it again
+            for _ in 0..2 {
+                repo.relate_overlay_variant(&overlay, hash.clone(), variant.clone())
+                    .await
+                    .unwrap();
+            }
+            assert_eq!(
+                repo.overlay_variants(&overlay).await.unwrap(),
+                vec![(hash, variant)]
+            );
+
+            repo.remove_overlay_variants(&overlay).await.unwrap();
+            assert!(repo.overlay_variants(&overlay).await.unwrap().is_empty());
+        });
+    }
+
+    #[test]
+    #[ignore = "requires PICTRS_TEST_POSTGRES_URL"]
+    fn perceptual_hash_distance() {
+        let url = test_url();
+
+        actix_rt::System::new().block_on(async move {
+            let repo = PostgresRepo::connect(url).await.unwrap();
+
+            let hash = uuid::Uuid::new_v4().as_bytes().to_vec();
+            // Random, with the sign bit set to check it survives the trip through BIGINT
+            let perceptual_hash = uuid::Uuid::new_v4().as_u64_pair().0 | (1 << 63);
+            let nearby = perceptual_hash ^ 0b111;
+
+            assert!(HashRepo::create(&repo, hash.clone()).await.unwrap().is_ok());
+            repo.relate_perceptual_hash(hash.clone(), perceptual_hash)
+                .await
+                .unwrap();
+
+            assert_eq!(
+                repo.perceptual_hash(hash.clone()).await.unwrap(),
+                Some(perceptual_hash)
+            );
+            assert!(repo
+                .similar_hashes(nearby, 3)
+                .await
+                .unwrap()
+                .contains(&(hash.clone(), perceptual_hash)));
+            assert!(!repo
+                .similar_hashes(nearby, 2)
+                .await
+                .unwrap()
+                .contains(&(hash.clone(), perceptual_hash)));
+
+            HashRepo::cleanup(&repo, hash.clone()).await.unwrap();
+            assert_eq!(repo.perceptual_hash(hash).await.unwrap(), None);
+        });
+    }
+
+    #[test]
+    #[ignore = "requires PICTRS_TEST_POSTGRES_URL"]
+    fn blocklist_roundtrip() {
+        let url = test_url();
+
+        actix_rt::System::new().block_on(async move {
+            let repo = PostgresRepo::connect(url).await.unwrap();
+
+            let hash = uuid::Uuid::new_v4().as_bytes().to_vec();
+
+            assert!(!repo.is_blocked(hash.clone()).await.unwrap());
+
+            // Blocking twice is fine
+            repo.block(hash.clone()).await.unwrap();
+            repo.block(hash.clone()).await.unwrap();
+            assert!(repo.is_blocked(hash.clone()).await.unwrap());
+            assert!(repo.blocked().await.unwrap().contains(&hash));
+
+            repo.unblock(hash.clone()).await.unwrap();
+            assert!(!repo.is_blocked(hash.clone()).await.unwrap());
+            assert!(!repo.blocked().await.unwrap().contains(&hash));
+        });
+    }
+
+    #[test]
+    #[ignore = "requires PICTRS_TEST_POSTGRES_URL"]
+    fn queue_and_settings_roundtrip() {
+        let url = test_url();
+
+        actix_rt::System::new().block_on(async move {
+            let repo = PostgresRepo::connect(url).await.unwrap();
+
+            let queue = "postgres-test";
+            let worker_id = uuid::Uuid::new_v4().to_string().into_bytes();
+
+            repo.push(queue, b"job".to_vec()).await.unwrap();
+            assert_eq!(
+                repo.pop(queue, worker_id.clone()).await.unwrap(),
+                b"job".to_vec()
+            );
+
+            repo.push(queue, b"job2".to_vec()).await.unwrap();
+            repo.requeue_in_progress(worker_id.clone()).await.unwrap();
+            assert_eq!(
+                repo.pop(queue, worker_id.clone()).await.unwrap(),
+                b"job".to_vec()
+            );
+            assert_eq!(repo.pop(queue, worker_id).await.unwrap(), b"job2".to_vec());
+
+            repo.set("postgres-test", b"value".to_vec()).await.unwrap();
+            assert_eq!(
+                repo.get("postgres-test").await.unwrap(),
+                Some(b"value".to_vec())
+            );
+            repo.remove("postgres-test").await.unwrap();
+            assert_eq!(repo.get("postgres-test").await.unwrap(), None);
+        });
+    }
+}
@@ -0,0 +1,54 @@
+CREATE TABLE settings (
+    key TEXT PRIMARY KEY,
+    value BYTEA NOT NULL
+);
+
+CREATE TABLE details (
+    identifier BYTEA PRIMARY KEY,
+    json JSONB NOT NULL
+);
+
+CREATE TABLE hashes (
+    hash BYTEA PRIMARY KEY,
+    identifier BYTEA,
+    motion_identifier BYTEA,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE TABLE hash_aliases (
+    hash BYTEA NOT NULL,
+    alias TEXT NOT NULL,
+    PRIMARY KEY (hash, alias)
+);
+
+CREATE TABLE variants (
+    hash BYTEA NOT NULL,
+    variant TEXT NOT NULL,
+    identifier BYTEA NOT NULL,
+    PRIMARY KEY (hash, variant)
+);
+
+CREATE TABLE aliases (
+    alias TEXT PRIMARY KEY,
+    hash BYTEA,
+    delete_token TEXT
+);
+
+CREATE INDEX aliases_hash_index ON aliases (hash);
+
+CREATE TABLE job_queue (
+    id BIGSERIAL PRIMARY KEY,
+    queue TEXT NOT NULL,
+    job BYTEA NOT NULL,
+    worker_id BYTEA,
+    queued_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX job_queue_pending_index ON job_queue (queue, id) WHERE worker_id IS NULL;
+CREATE INDEX job_queue_worker_index ON job_queue (worker_id);
+
+CREATE TABLE uploads (
+    upload_id UUID PRIMARY KEY,
+    result JSONB,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
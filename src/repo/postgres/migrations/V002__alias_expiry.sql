@@ -0,0 +1,3 @@
+ALTER TABLE aliases ADD COLUMN expires_at TIMESTAMPTZ;
+
+CREATE INDEX aliases_expires_at_index ON aliases (expires_at) WHERE expires_at IS NOT NULL;
@@ -0,0 +1 @@
+ALTER TABLE hashes ADD COLUMN perceptual_hash BIGINT;
@@ -0,0 +1,4 @@
+CREATE TABLE blocklist (
+    hash BYTEA PRIMARY KEY,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
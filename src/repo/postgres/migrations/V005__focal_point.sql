@@ -0,0 +1,2 @@
+ALTER TABLE aliases ADD COLUMN focal_x DOUBLE PRECISION;
+ALTER TABLE aliases ADD COLUMN focal_y DOUBLE PRECISION;
@@ -0,0 +1,6 @@
+CREATE TABLE overlay_variants (
+    overlay TEXT NOT NULL,
+    hash BYTEA NOT NULL,
+    variant TEXT NOT NULL,
+    PRIMARY KEY (overlay, hash, variant)
+);
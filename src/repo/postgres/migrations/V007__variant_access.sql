@@ -0,0 +1,3 @@
+ALTER TABLE variants ADD COLUMN accessed_at TIMESTAMPTZ NOT NULL DEFAULT now();
+
+CREATE INDEX variants_accessed_at_index ON variants (accessed_at);
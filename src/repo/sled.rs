@@ -1,22 +1,28 @@
 use crate::{
     error::{Error, UploadError},
+    processor::FocalPoint,
     repo::{
-        Alias, AliasRepo, AlreadyExists, BaseRepo, DeleteToken, Details, FullRepo, HashRepo,
-        Identifier, IdentifierRepo, QueueRepo, SettingsRepo, UploadId, UploadRepo, UploadResult,
+        Alias, AliasRepo, AlreadyExists, BaseRepo, BlocklistRepo, CachedRepo, DeleteToken, Details,
+        FullRepo, HashRepo, Identifier, IdentifierRepo, InnerUploadResult, QueueRepo, SettingsRepo,
+        UploadId, UploadRepo, UploadResult, VariantAccessRepo,
     },
-    serde_str::Serde,
     stream::from_iterator,
 };
 use futures_util::Stream;
-use sled::{Db, IVec, Tree};
+use sled::{
+    transaction::{TransactionError, Transactional},
+    Db, IVec, Tree,
+};
 use std::{
     collections::HashMap,
+    ops::Bound,
     pin::Pin,
     sync::{
         atomic::{AtomicU64, Ordering},
         Arc, RwLock,
     },
 };
+use time::OffsetDateTime;
 use tokio::sync::Notify;
 
 macro_rules! b {
@@ -57,9 +63,17 @@ pub(crate) struct SledRepo {
     hash_identifiers: Tree,
     hash_variant_identifiers: Tree,
     hash_motion_identifiers: Tree,
+    hash_perceptual_hashes: Tree,
     aliases: Tree,
     alias_hashes: Tree,
     alias_delete_tokens: Tree,
+    alias_expiries: Tree,
+    alias_focal_points: Tree,
+    overlay_variants: Tree,
+    expiry_aliases: Tree,
+    variant_access: Tree,
+    access_variants: Tree,
+    blocklist: Tree,
     queue: Tree,
     in_progress_queue: Tree,
     queue_notifier: Arc<RwLock<HashMap<&'static str, Arc<Notify>>>>,
@@ -79,9 +93,17 @@ impl SledRepo {
             hash_identifiers: db.open_tree("pict-rs-hash-identifiers-tree")?,
             hash_variant_identifiers: db.open_tree("pict-rs-hash-variant-identifiers-tree")?,
             hash_motion_identifiers: db.open_tree("pict-rs-hash-motion-identifiers-tree")?,
+            hash_perceptual_hashes: db.open_tree("pict-rs-hash-perceptual-hashes-tree")?,
             aliases: db.open_tree("pict-rs-aliases-tree")?,
             alias_hashes: db.open_tree("pict-rs-alias-hashes-tree")?,
             alias_delete_tokens: db.open_tree("pict-rs-alias-delete-tokens-tree")?,
+            alias_expiries: db.open_tree("pict-rs-alias-expiries-tree")?,
+            alias_focal_points: db.open_tree("pict-rs-alias-focal-points-tree")?,
+            overlay_variants: db.open_tree("pict-rs-overlay-variants-tree")?,
+            expiry_aliases: db.open_tree("pict-rs-expiry-aliases-tree")?,
+            variant_access: db.open_tree("pict-rs-variant-access-tree")?,
+            access_variants: db.open_tree("pict-rs-access-variants-tree")?,
+            blocklist: db.open_tree("pict-rs-blocklist-tree")?,
             queue: db.open_tree("pict-rs-queue-tree")?,
             in_progress_queue: db.open_tree("pict-rs-in-progress-queue-tree")?,
             queue_notifier: Arc::new(RwLock::new(HashMap::new())),
@@ -89,6 +111,12 @@ impl SledRepo {
             db,
         })
     }
+
+    pub(crate) async fn flush(&self) -> Result<(), SledError> {
+        self.db.flush_async().await?;
+
+        Ok(())
+    }
 }
 
 impl BaseRepo for SledRepo {
@@ -108,41 +136,6 @@ impl FullRepo for SledRepo {
     }
 }
 
-#[derive(serde::Deserialize, serde::Serialize)]
-enum InnerUploadResult {
-    Success {
-        alias: Serde<Alias>,
-        token: Serde<DeleteToken>,
-    },
-    Failure {
-        message: String,
-    },
-}
-
-impl From<UploadResult> for InnerUploadResult {
-    fn from(u: UploadResult) -> Self {
-        match u {
-            UploadResult::Success { alias, token } => InnerUploadResult::Success {
-                alias: Serde::new(alias),
-                token: Serde::new(token),
-            },
-            UploadResult::Failure { message } => InnerUploadResult::Failure { message },
-        }
-    }
-}
-
-impl From<InnerUploadResult> for UploadResult {
-    fn from(i: InnerUploadResult) -> Self {
-        match i {
-            InnerUploadResult::Success { alias, token } => UploadResult::Success {
-                alias: Serde::into_inner(alias),
-                token: Serde::into_inner(token),
-            },
-            InnerUploadResult::Failure { message } => UploadResult::Failure { message },
-        }
-    }
-}
-
 #[async_trait::async_trait(?Send)]
 impl UploadRepo for SledRepo {
     #[tracing::instrument(level = "trace", skip(self))]
@@ -328,6 +321,17 @@ impl QueueRepo for SledRepo {
             notify.notified().await
         }
     }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn queue_length(&self, queue_name: &'static str) -> Result<u64, Error> {
+        let count = b!(self.queue, {
+            let count = queue.scan_prefix(queue_name.as_bytes()).keys().count();
+
+            Ok(count) as Result<usize, SledError>
+        });
+
+        Ok(count as u64)
+    }
 }
 
 #[async_trait::async_trait(?Send)]
@@ -361,6 +365,18 @@ fn variant_key(hash: &[u8], variant: &str) -> Vec<u8> {
     bytes
 }
 
+fn overlay_variant_prefix(overlay: &Alias) -> Vec<u8> {
+    let mut bytes = overlay.to_bytes();
+    bytes.push(0);
+    bytes
+}
+
+fn overlay_variant_key(overlay: &Alias, hash: &[u8], variant: &str) -> Vec<u8> {
+    let mut bytes = overlay_variant_prefix(overlay);
+    bytes.extend(variant_key(hash, variant));
+    bytes
+}
+
 fn variant_from_key(hash: &[u8], key: &[u8]) -> Option<String> {
     let prefix_len = hash.len() + 1;
     let variant_bytes = key.get(prefix_len..)?.to_vec();
@@ -430,6 +446,24 @@ impl HashRepo for SledRepo {
         Box::pin(from_iterator(iter, 8))
     }
 
+    #[tracing::instrument(level = "trace", skip(self, after))]
+    async fn hashes_after(
+        &self,
+        after: Option<Self::Bytes>,
+        limit: usize,
+    ) -> Result<Vec<Self::Bytes>, Error> {
+        let page = b!(self.hashes, {
+            let iter = match after {
+                Some(after) => hashes.range((Bound::Excluded(after), Bound::Unbounded)),
+                None => hashes.iter(),
+            };
+
+            iter.keys().take(limit).collect::<Result<Vec<_>, _>>()
+        });
+
+        Ok(page)
+    }
+
     #[tracing::instrument(level = "trace", skip(self, hash), fields(hash = hex::encode(&hash)))]
     async fn create(&self, hash: Self::Bytes) -> Result<Result<(), AlreadyExists>, Error> {
         let res = b!(self.hashes, {
@@ -505,9 +539,26 @@ impl HashRepo for SledRepo {
         let key = variant_key(&hash, &variant);
         let value = identifier.to_bytes()?;
 
+        let key2 = key.clone();
         b!(
             self.hash_variant_identifiers,
-            hash_variant_identifiers.insert(key, value)
+            hash_variant_identifiers.insert(key2, value)
+        );
+
+        // Count the variant as accessed when it's created, so it can be evicted even if it's
+        // never served, like postgres does with its column default
+        let accessed_at = OffsetDateTime::now_utc().unix_timestamp().to_be_bytes();
+        let access_variants = self.access_variants.clone();
+        b!(
+            self.variant_access,
+            insert_access(
+                &variant_access,
+                &access_variants,
+                &key,
+                &accessed_at,
+                &hash,
+                true
+            )
         );
 
         Ok(())
@@ -567,9 +618,16 @@ impl HashRepo for SledRepo {
     async fn remove_variant(&self, hash: Self::Bytes, variant: String) -> Result<(), Error> {
         let key = variant_key(&hash, &variant);
 
+        let key2 = key.clone();
         b!(
             self.hash_variant_identifiers,
-            hash_variant_identifiers.remove(key)
+            hash_variant_identifiers.remove(key2)
+        );
+
+        let access_variants = self.access_variants.clone();
+        b!(
+            self.variant_access,
+            remove_access(&variant_access, &access_variants, &key)
         );
 
         Ok(())
@@ -606,6 +664,53 @@ impl HashRepo for SledRepo {
             .map_err(Error::from)
     }
 
+    #[tracing::instrument(level = "trace", skip(self, hash), fields(hash = hex::encode(&hash)))]
+    async fn relate_perceptual_hash(
+        &self,
+        hash: Self::Bytes,
+        perceptual_hash: u64,
+    ) -> Result<(), Error> {
+        b!(
+            self.hash_perceptual_hashes,
+            hash_perceptual_hashes.insert(hash, &perceptual_hash.to_be_bytes()[..])
+        );
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "trace", skip(self, hash), fields(hash = hex::encode(&hash)))]
+    async fn perceptual_hash(&self, hash: Self::Bytes) -> Result<Option<u64>, Error> {
+        let opt = b!(
+            self.hash_perceptual_hashes,
+            hash_perceptual_hashes.get(hash)
+        );
+
+        Ok(opt.and_then(|ivec| Some(u64::from_be_bytes(ivec.as_ref().try_into().ok()?))))
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn similar_hashes(
+        &self,
+        perceptual_hash: u64,
+        max_distance: u32,
+    ) -> Result<Vec<(Self::Bytes, u64)>, Error> {
+        // Hamming distance can't be indexed in sled, so every perceptual hash gets checked
+        let v = b!(self.hash_perceptual_hashes, {
+            Ok(hash_perceptual_hashes
+                .iter()
+                .filter_map(Result::ok)
+                .filter_map(|(hash, ivec)| {
+                    let other = u64::from_be_bytes(ivec.as_ref().try_into().ok()?);
+
+                    ((other ^ perceptual_hash).count_ones() <= max_distance)
+                        .then_some((hash, other))
+                })
+                .collect::<Vec<_>>()) as Result<_, sled::Error>
+        });
+
+        Ok(v)
+    }
+
     #[tracing::instrument(skip(self, hash), fields(hash = hex::encode(&hash)))]
     async fn cleanup(&self, hash: Self::Bytes) -> Result<(), Error> {
         let hash2 = hash.clone();
@@ -620,6 +725,12 @@ impl HashRepo for SledRepo {
             hash_motion_identifiers.remove(hash2)
         );
 
+        let hash2 = hash.clone();
+        b!(
+            self.hash_perceptual_hashes,
+            hash_perceptual_hashes.remove(hash2)
+        );
+
         let aliases = self.aliases(hash.clone()).await?;
         let hash2 = hash.clone();
         b!(self.hash_aliases, {
@@ -636,17 +747,26 @@ impl HashRepo for SledRepo {
                 .scan_prefix(hash)
                 .keys()
                 .filter_map(Result::ok)
-                .collect::<Vec<_>>();
+                .collect::<Vec<IVec>>();
 
-            Ok(v) as Result<Vec<_>, sled::Error>
+            Ok(v) as Result<Vec<IVec>, sled::Error>
         });
+        let variant_keys2 = variant_keys.clone();
         b!(self.hash_variant_identifiers, {
-            for key in variant_keys {
+            for key in variant_keys2 {
                 let _ = hash_variant_identifiers.remove(key);
             }
             Ok(()) as Result<(), sled::Error>
         });
 
+        let access_variants = self.access_variants.clone();
+        b!(self.variant_access, {
+            for key in variant_keys {
+                let _ = remove_access(&variant_access, &access_variants, &key);
+            }
+            Ok(()) as Result<(), sled::Error>
+        });
+
         Ok(())
     }
 }
@@ -712,6 +832,92 @@ impl AliasRepo for SledRepo {
         opt.ok_or(SledError::Missing).map_err(Error::from)
     }
 
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn relate_focal_point(
+        &self,
+        alias: &Alias,
+        focal_point: FocalPoint,
+    ) -> Result<(), Error> {
+        let key = alias.to_bytes();
+        let focal_point = serde_json::to_vec(&focal_point)?;
+
+        b!(
+            self.alias_focal_points,
+            alias_focal_points.insert(key, focal_point)
+        );
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn focal_point(&self, alias: &Alias) -> Result<Option<FocalPoint>, Error> {
+        let key = alias.to_bytes();
+
+        let opt = b!(self.alias_focal_points, alias_focal_points.get(key));
+
+        opt.map(|ivec| serde_json::from_slice(&ivec))
+            .transpose()
+            .map_err(Error::from)
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn remove_focal_point(&self, alias: &Alias) -> Result<(), Error> {
+        let key = alias.to_bytes();
+
+        b!(self.alias_focal_points, alias_focal_points.remove(key));
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "trace", skip(self, hash), fields(hash = hex::encode(&hash)))]
+    async fn relate_overlay_variant(
+        &self,
+        overlay: &Alias,
+        hash: Self::Bytes,
+        variant: String,
+    ) -> Result<(), Error> {
+        let key = overlay_variant_key(overlay, &hash, &variant);
+
+        b!(self.overlay_variants, overlay_variants.insert(key, hash));
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn overlay_variants(&self, overlay: &Alias) -> Result<Vec<(Self::Bytes, String)>, Error> {
+        let prefix = overlay_variant_prefix(overlay);
+
+        let vec = b!(
+            self.overlay_variants,
+            Ok(overlay_variants
+                .scan_prefix(&prefix)
+                .filter_map(|res| res.ok())
+                .filter_map(|(key, hash)| {
+                    let variant = variant_from_key(&hash, key.get(prefix.len()..)?)?;
+
+                    Some((hash, variant))
+                })
+                .collect::<Vec<_>>()) as Result<Vec<_>, SledError>
+        );
+
+        Ok(vec)
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn remove_overlay_variants(&self, overlay: &Alias) -> Result<(), Error> {
+        let prefix = overlay_variant_prefix(overlay);
+
+        b!(self.overlay_variants, {
+            for res in overlay_variants.scan_prefix(prefix).keys() {
+                overlay_variants.remove(res?)?;
+            }
+
+            Ok(()) as Result<(), SledError>
+        });
+
+        Ok(())
+    }
+
     #[tracing::instrument(skip(self))]
     async fn cleanup(&self, alias: &Alias) -> Result<(), Error> {
         let key = alias.to_bytes();
@@ -722,12 +928,237 @@ impl AliasRepo for SledRepo {
         let key2 = key.clone();
         b!(self.alias_delete_tokens, alias_delete_tokens.remove(key2));
 
-        b!(self.alias_hashes, alias_hashes.remove(key));
+        let key2 = key.clone();
+        b!(self.alias_hashes, alias_hashes.remove(key2));
+
+        let key2 = key.clone();
+        b!(self.alias_focal_points, alias_focal_points.remove(key2));
+
+        let expiry_aliases = self.expiry_aliases.clone();
+        b!(self.alias_expiries, {
+            if let Some(expiry) = alias_expiries.remove(&key)? {
+                expiry_aliases.remove(expiry_alias_key(&expiry, &key))?;
+            }
+
+            Ok(()) as Result<(), SledError>
+        });
 
         Ok(())
     }
 }
 
+fn expiry_alias_key(expiry: &[u8], alias: &[u8]) -> Vec<u8> {
+    let mut v = expiry.to_vec();
+    v.extend_from_slice(alias);
+    v
+}
+
+#[async_trait::async_trait(?Send)]
+impl CachedRepo for SledRepo {
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn mark_cached(&self, alias: &Alias, expires_at: OffsetDateTime) -> Result<(), Error> {
+        let key = alias.to_bytes();
+        let expiry = expires_at.unix_timestamp().to_be_bytes();
+
+        let expiry_aliases = self.expiry_aliases.clone();
+        b!(self.alias_expiries, {
+            if let Some(previous) = alias_expiries.insert(&key, &expiry[..])? {
+                expiry_aliases.remove(expiry_alias_key(&previous, &key))?;
+            }
+
+            expiry_aliases.insert(expiry_alias_key(&expiry, &key), key)?;
+
+            Ok(()) as Result<(), SledError>
+        });
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn cached_expiry(&self, alias: &Alias) -> Result<Option<OffsetDateTime>, Error> {
+        let key = alias.to_bytes();
+
+        let opt = b!(self.alias_expiries, alias_expiries.get(key));
+
+        Ok(opt.and_then(|ivec| {
+            let bytes: [u8; 8] = ivec.as_ref().try_into().ok()?;
+            OffsetDateTime::from_unix_timestamp(i64::from_be_bytes(bytes)).ok()
+        }))
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn expired(&self, now: OffsetDateTime) -> Result<Vec<Alias>, Error> {
+        let now = now.unix_timestamp().to_be_bytes();
+
+        let v = b!(self.expiry_aliases, {
+            Ok(expiry_aliases
+                .range(..now)
+                .values()
+                .filter_map(Result::ok)
+                .filter_map(|ivec| Alias::from_slice(&ivec))
+                .collect::<Vec<_>>()) as Result<_, sled::Error>
+        });
+
+        Ok(v)
+    }
+}
+
+fn access_variant_key(accessed_at: &[u8], variant_key: &[u8]) -> Vec<u8> {
+    let mut v = accessed_at.to_vec();
+    v.extend_from_slice(variant_key);
+    v
+}
+
+// Both access trees are updated in one transaction, so neither can point at an entry the other
+// has already replaced
+fn insert_access(
+    variant_access: &Tree,
+    access_variants: &Tree,
+    variant_key: &[u8],
+    accessed_at: &[u8],
+    hash: &[u8],
+    keep_existing: bool,
+) -> Result<(), sled::Error> {
+    (variant_access, access_variants)
+        .transaction(|(variant_access, access_variants)| {
+            if let Some(previous) = variant_access.get(variant_key)? {
+                if keep_existing || previous == accessed_at {
+                    return Ok(());
+                }
+
+                access_variants.remove(access_variant_key(&previous, variant_key))?;
+            }
+
+            variant_access.insert(variant_key, accessed_at)?;
+            access_variants.insert(access_variant_key(accessed_at, variant_key), hash)?;
+
+            Ok(())
+        })
+        .map_err(transaction_error)
+}
+
+fn remove_access(
+    variant_access: &Tree,
+    access_variants: &Tree,
+    variant_key: &[u8],
+) -> Result<(), sled::Error> {
+    (variant_access, access_variants)
+        .transaction(|(variant_access, access_variants)| {
+            if let Some(accessed_at) = variant_access.remove(variant_key)? {
+                access_variants.remove(access_variant_key(&accessed_at, variant_key))?;
+            }
+
+            Ok(())
+        })
+        .map_err(transaction_error)
+}
+
+fn transaction_error(e: TransactionError<sled::Error>) -> sled::Error {
+    match e {
+        TransactionError::Abort(e) | TransactionError::Storage(e) => e,
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl VariantAccessRepo for SledRepo {
+    #[tracing::instrument(level = "trace", skip(self, hash), fields(hash = hex::encode(&hash)))]
+    async fn mark_accessed(
+        &self,
+        hash: Self::Bytes,
+        variant: String,
+        accessed_at: OffsetDateTime,
+    ) -> Result<(), Error> {
+        let key = variant_key(&hash, &variant);
+        let accessed_at = accessed_at.unix_timestamp().to_be_bytes();
+
+        let access_variants = self.access_variants.clone();
+        b!(
+            self.variant_access,
+            insert_access(
+                &variant_access,
+                &access_variants,
+                &key,
+                &accessed_at,
+                &hash,
+                false
+            )
+        );
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "trace", skip(self, hash), fields(hash = hex::encode(&hash)))]
+    async fn accessed_at(
+        &self,
+        hash: Self::Bytes,
+        variant: String,
+    ) -> Result<Option<OffsetDateTime>, Error> {
+        let key = variant_key(&hash, &variant);
+
+        let opt = b!(self.variant_access, variant_access.get(key));
+
+        Ok(opt.and_then(|ivec| {
+            let bytes: [u8; 8] = ivec.as_ref().try_into().ok()?;
+            OffsetDateTime::from_unix_timestamp(i64::from_be_bytes(bytes)).ok()
+        }))
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn accessed_before(
+        &self,
+        timestamp: OffsetDateTime,
+    ) -> Result<Vec<(Self::Bytes, String)>, Error> {
+        let timestamp = timestamp.unix_timestamp().to_be_bytes();
+
+        let v = b!(self.access_variants, {
+            Ok(access_variants
+                .range(..timestamp)
+                .filter_map(Result::ok)
+                .filter_map(|(key, hash)| {
+                    let variant = variant_from_key(&hash, key.get(8..)?)?;
+                    Some((hash, variant))
+                })
+                .collect::<Vec<_>>()) as Result<_, sled::Error>
+        });
+
+        Ok(v)
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl BlocklistRepo for SledRepo {
+    #[tracing::instrument(level = "trace", skip(self, hash), fields(hash = hex::encode(&hash)))]
+    async fn block(&self, hash: Self::Bytes) -> Result<(), Error> {
+        b!(self.blocklist, blocklist.insert(hash, b"1"));
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "trace", skip(self, hash), fields(hash = hex::encode(&hash)))]
+    async fn unblock(&self, hash: Self::Bytes) -> Result<(), Error> {
+        b!(self.blocklist, blocklist.remove(hash));
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "trace", skip(self, hash), fields(hash = hex::encode(&hash)))]
+    async fn is_blocked(&self, hash: Self::Bytes) -> Result<bool, Error> {
+        let blocked = b!(self.blocklist, blocklist.contains_key(hash));
+
+        Ok(blocked)
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn blocked(&self) -> Result<Vec<Self::Bytes>, Error> {
+        let v = b!(
+            self.blocklist,
+            blocklist.iter().keys().collect::<Result<Vec<_>, _>>()
+        );
+
+        Ok(v)
+    }
+}
+
 impl std::fmt::Debug for SledRepo {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("SledRepo").finish()
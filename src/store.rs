@@ -1,7 +1,7 @@
 use crate::error::Error;
 use actix_web::web::Bytes;
-use futures_util::stream::Stream;
-use std::fmt::Debug;
+use futures_util::stream::{LocalBoxStream, Stream};
+use std::{fmt::Debug, time::SystemTime};
 use tokio::io::{AsyncRead, AsyncWrite};
 
 pub(crate) mod file_store;
@@ -17,6 +17,15 @@ pub(crate) trait Identifier: Send + Sync + Clone + Debug {
     fn string_repr(&self) -> String;
 }
 
+/// An object found while listing a store
+#[derive(Debug)]
+pub(crate) struct StoredObject<I> {
+    pub(crate) identifier: I,
+    pub(crate) modified: SystemTime,
+}
+
+pub(crate) type ListStream<I> = LocalBoxStream<'static, Result<StoredObject<I>, Error>>;
+
 pub(crate) trait StoreConfig: Send + Sync + Clone {
     type Store: Store;
 
@@ -56,6 +65,9 @@ pub(crate) trait Store: Clone + Debug {
     async fn len(&self, identifier: &Self::Identifier) -> Result<u64, Error>;
 
     async fn remove(&self, identifier: &Self::Identifier) -> Result<(), Error>;
+
+    /// List every object in the store, including those the repo doesn't know about
+    async fn list(&self) -> Result<ListStream<Self::Identifier>, Error>;
 }
 
 #[async_trait::async_trait(?Send)]
@@ -111,6 +123,10 @@ where
     async fn remove(&self, identifier: &Self::Identifier) -> Result<(), Error> {
         T::remove(self, identifier).await
     }
+
+    async fn list(&self) -> Result<ListStream<Self::Identifier>, Error> {
+        T::list(self).await
+    }
 }
 
 #[async_trait::async_trait(?Send)]
@@ -166,4 +182,8 @@ where
     async fn remove(&self, identifier: &Self::Identifier) -> Result<(), Error> {
         T::remove(self, identifier).await
     }
+
+    async fn list(&self) -> Result<ListStream<Self::Identifier>, Error> {
+        T::list(self).await
+    }
 }
@@ -2,13 +2,15 @@ use crate::{
     error::Error,
     file::File,
     repo::{Repo, SettingsRepo},
-    store::{Store, StoreConfig},
+    store::{ListStream, Store, StoreConfig, StoredObject},
+    stream::from_iterator,
 };
 use actix_web::web::Bytes;
-use futures_util::stream::Stream;
+use futures_util::stream::{Stream, StreamExt};
 use std::{
     path::{Path, PathBuf},
     pin::Pin,
+    time::SystemTime,
 };
 use storage_path_generator::Generator;
 use tokio::io::{AsyncRead, AsyncWrite};
@@ -151,6 +153,73 @@ impl Store for FileStore {
 
         Ok(())
     }
+
+    #[tracing::instrument]
+    async fn list(&self) -> Result<ListStream<Self::Identifier>, Error> {
+        let walk = WalkFiles {
+            root: Some(self.root_dir.clone()),
+            stack: Vec::new(),
+        };
+
+        let store = self.clone();
+
+        let stream = from_iterator(walk, 8).map(move |res| {
+            let (path, modified) = res.map_err(FileError::from)?;
+
+            Ok(StoredObject {
+                identifier: store.file_id_from_path(path)?,
+                modified,
+            })
+        });
+
+        Ok(Box::pin(stream))
+    }
+}
+
+// Depth-first walk over every file beneath the root directory, run on a blocking thread by
+// from_iterator
+struct WalkFiles {
+    root: Option<PathBuf>,
+    stack: Vec<std::fs::ReadDir>,
+}
+
+impl Iterator for WalkFiles {
+    type Item = std::io::Result<(PathBuf, SystemTime)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(root) = self.root.take() {
+            match std::fs::read_dir(root) {
+                Ok(read_dir) => self.stack.push(read_dir),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+
+        loop {
+            let entry = match self.stack.last_mut()?.next() {
+                Some(Ok(entry)) => entry,
+                Some(Err(e)) => return Some(Err(e)),
+                None => {
+                    self.stack.pop();
+                    continue;
+                }
+            };
+
+            let metadata = match entry.metadata() {
+                Ok(metadata) => metadata,
+                Err(e) => return Some(Err(e)),
+            };
+
+            if metadata.is_dir() {
+                match std::fs::read_dir(entry.path()) {
+                    Ok(read_dir) => self.stack.push(read_dir),
+                    Err(e) => return Some(Err(e)),
+                }
+            } else if metadata.is_file() {
+                return Some(metadata.modified().map(|modified| (entry.path(), modified)));
+            }
+        }
+    }
 }
 
 impl FileStore {
@@ -173,6 +242,9 @@ impl FileStore {
                     .set(GENERATOR_KEY, path.to_be_bytes().into())
                     .await?;
             }
+            Repo::Postgres(ref postgres_repo) => {
+                postgres_repo.set(GENERATOR_KEY, path.to_be_bytes()).await?;
+            }
         }
 
         let mut target_path = self.root_dir.clone();
@@ -282,6 +354,15 @@ async fn init_generator(repo: &Repo) -> Result<Generator, Error> {
                 Ok(Generator::new())
             }
         }
+        Repo::Postgres(postgres_repo) => {
+            if let Some(bytes) = postgres_repo.get(GENERATOR_KEY).await? {
+                Ok(Generator::from_existing(
+                    storage_path_generator::Path::from_be_bytes(bytes)?,
+                ))
+            } else {
+                Ok(Generator::new())
+            }
+        }
     }
 }
 
@@ -2,7 +2,7 @@ use crate::{
     bytes_stream::BytesStream,
     error::Error,
     repo::{Repo, SettingsRepo},
-    store::{Store, StoreConfig},
+    store::{ListStream, Store, StoreConfig, StoredObject},
 };
 use actix_rt::task::JoinError;
 use actix_web::{
@@ -16,8 +16,15 @@ use actix_web::{
 use awc::{error::SendRequestError, Client, ClientRequest, ClientResponse, SendClientRequest};
 use base64::{prelude::BASE64_STANDARD, Engine};
 use futures_util::{Stream, StreamExt, TryStreamExt};
-use rusty_s3::{actions::S3Action, Bucket, BucketError, Credentials, UrlStyle};
-use std::{pin::Pin, string::FromUtf8Error, time::Duration};
+use rusty_s3::{
+    actions::{ListObjectsV2, ListObjectsV2Response, S3Action},
+    Bucket, BucketError, Credentials, UrlStyle,
+};
+use std::{
+    pin::Pin,
+    string::FromUtf8Error,
+    time::{Duration, SystemTime},
+};
 use storage_path_generator::{Generator, Path};
 use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
 use tokio_util::io::ReaderStream;
@@ -28,6 +35,7 @@ mod object_id;
 pub(crate) use object_id::ObjectId;
 
 const CHUNK_SIZE: usize = 8_388_608; // 8 Mebibytes, min is 5 (5_242_880);
+const LIST_BODY_LIMIT: usize = 8_388_608; // a page of 1000 keys is well under this
 
 // - Settings Tree
 //   - last-path -> last generated path
@@ -57,6 +65,12 @@ pub(crate) enum ObjectError {
     #[error("Invalid etag response")]
     Etag,
 
+    #[error("Failed to parse list response: {0}")]
+    ListResponse(String),
+
+    #[error("Invalid last modified time")]
+    LastModified,
+
     #[error("Task cancelled")]
     Cancelled,
 
@@ -394,6 +408,49 @@ impl Store for ObjectStore {
 
         Ok(())
     }
+
+    #[tracing::instrument(skip(self))]
+    async fn list(&self) -> Result<ListStream<Self::Identifier>, Error> {
+        let store = self.clone();
+
+        // The state is the continuation token for the next page, or None once the last page has
+        // been fetched
+        let stream = futures_util::stream::try_unfold(Some(None), move |state| {
+            let store = store.clone();
+
+            async move {
+                let Some(continuation_token) = state else {
+                    return Ok(None);
+                };
+
+                let page = store.list_objects_page(continuation_token).await?;
+
+                let next_state = page.next_continuation_token.map(Some);
+
+                let objects = page
+                    .contents
+                    .into_iter()
+                    .map(|content| {
+                        let modified = time::OffsetDateTime::parse(
+                            &content.last_modified,
+                            &time::format_description::well_known::Rfc3339,
+                        )
+                        .map_err(|_| ObjectError::LastModified)?;
+
+                        Ok(StoredObject {
+                            identifier: ObjectId::from_string(content.key),
+                            modified: SystemTime::from(modified),
+                        })
+                    })
+                    .collect::<Vec<Result<_, Error>>>();
+
+                Ok(Some((futures_util::stream::iter(objects), next_state))) as Result<_, Error>
+            }
+        })
+        .try_flatten();
+
+        Ok(Box::pin(stream))
+    }
 }
 
 impl ObjectStore {
@@ -529,6 +586,36 @@ impl ObjectStore {
         self.build_request(action)
     }
 
+    async fn list_objects_page(
+        &self,
+        continuation_token: Option<String>,
+    ) -> Result<ListObjectsV2Response, Error> {
+        let mut action = self.bucket.list_objects_v2(Some(&self.credentials));
+
+        if let Some(token) = continuation_token {
+            action.query_mut().insert("continuation-token", token);
+        }
+
+        let mut response = self
+            .build_request(action)
+            .send()
+            .await
+            .map_err(ObjectError::from)?;
+
+        if !response.status().is_success() {
+            return Err(status_error(response).await);
+        }
+
+        let body = response.body().limit(LIST_BODY_LIMIT).await?;
+        let body = String::from_utf8(body.to_vec()).map_err(ObjectError::from)?;
+
+        // rusty-s3 depends on a different version of quick-xml, so its errors can't be converted
+        let page = ListObjectsV2::parse_response(&body)
+            .map_err(|e| ObjectError::ListResponse(e.to_string()))?;
+
+        Ok(page)
+    }
+
     fn build_request<'a, A: S3Action<'a>>(&'a self, action: A) -> ClientRequest {
         let (req, _) = self.build_request_inner(action);
         req
@@ -602,6 +689,9 @@ impl ObjectStore {
                     .set(GENERATOR_KEY, path.to_be_bytes().into())
                     .await?;
             }
+            Repo::Postgres(ref postgres_repo) => {
+                postgres_repo.set(GENERATOR_KEY, path.to_be_bytes()).await?;
+            }
         }
 
         Ok(path)
@@ -626,6 +716,15 @@ async fn init_generator(repo: &Repo) -> Result<Generator, Error> {
                 Ok(Generator::new())
             }
         }
+        Repo::Postgres(postgres_repo) => {
+            if let Some(bytes) = postgres_repo.get(GENERATOR_KEY).await? {
+                Ok(Generator::from_existing(
+                    storage_path_generator::Path::from_be_bytes(bytes)?,
+                ))
+            } else {
+                Ok(Generator::new())
+            }
+        }
     }
 }
 
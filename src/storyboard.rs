@@ -0,0 +1,237 @@
+use crate::{
+    concurrent_processor::CancelSafeProcessor,
+    config::ImageFormat,
+    details::Details,
+    error::{Error, UploadError},
+    ffmpeg::VideoFormat,
+    repo::FullRepo,
+    store::{Identifier, Store},
+};
+use actix_web::web::Bytes;
+use std::{path::PathBuf, time::Instant};
+use tokio::io::AsyncReadExt;
+
+const DEFAULT_FRAMES: u32 = 10;
+const DEFAULT_WIDTH: u32 = 160;
+
+/// A sprite of frames sampled evenly from a gif or video, for previewing while scrubbing through
+/// it
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Storyboard {
+    frames: u32,
+    width: u32,
+    format: ImageFormat,
+}
+
+/// Where each frame of a storyboard sprite is, and when in the video it was taken
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub(crate) struct Manifest {
+    columns: usize,
+    rows: usize,
+    tile_width: usize,
+    tile_height: usize,
+    frames: Vec<ManifestFrame>,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+struct ManifestFrame {
+    /// In seconds from the start of the video
+    timestamp: f64,
+    x: usize,
+    y: usize,
+}
+
+/// Lay frames out in rows as close to square as possible
+pub(crate) fn grid(count: usize) -> (usize, usize) {
+    let count = count.max(1);
+
+    let mut columns = 1;
+    while columns * columns < count {
+        columns += 1;
+    }
+
+    (columns, count.div_ceil(columns))
+}
+
+impl Storyboard {
+    pub(crate) fn new(
+        frames: Option<u32>,
+        width: Option<u32>,
+        format: ImageFormat,
+    ) -> Result<Self, Error> {
+        let limits = &crate::CONFIG.media.storyboard;
+
+        let frames = frames.unwrap_or(DEFAULT_FRAMES.min(limits.max_frames));
+        let width = width.unwrap_or(DEFAULT_WIDTH.min(limits.max_width));
+
+        if !(1..=limits.max_frames).contains(&frames) || !(1..=limits.max_width).contains(&width) {
+            return Err(UploadError::ParsePath.into());
+        }
+
+        Ok(Storyboard {
+            frames,
+            width,
+            format,
+        })
+    }
+
+    pub(crate) fn format(&self) -> ImageFormat {
+        self.format
+    }
+
+    /// The variant the sprite is stored as
+    pub(crate) fn sprite_path(&self) -> PathBuf {
+        let mut path = PathBuf::from("storyboard");
+        path.push(self.frames.to_string());
+        path.push(self.width.to_string());
+        path.push(self.format.as_ext());
+        path
+    }
+
+    /// The variant the manifest is stored as, next to its sprite
+    pub(crate) fn manifest_path(&self) -> PathBuf {
+        self.sprite_path().join("manifest.json")
+    }
+}
+
+impl Manifest {
+    fn new(sprite: &Details, timestamps: Vec<f64>) -> Self {
+        let (columns, rows) = grid(timestamps.len());
+        let (width, height) = sprite.dimensions();
+
+        let tile_width = width / columns;
+        let tile_height = height / rows;
+
+        let frames = timestamps
+            .into_iter()
+            .enumerate()
+            .map(|(i, timestamp)| ManifestFrame {
+                timestamp,
+                x: (i % columns) * tile_width,
+                y: (i / columns) * tile_height,
+            })
+            .collect();
+
+        Manifest {
+            columns,
+            rows,
+            tile_width,
+            tile_height,
+            frames,
+        }
+    }
+}
+
+/// Make the sprite and manifest for a storyboard, returning the sprite
+#[tracing::instrument(skip(repo, store, hash))]
+pub(crate) async fn generate<R: FullRepo, S: Store + 'static>(
+    repo: &R,
+    store: &S,
+    storyboard: Storyboard,
+    input_format: Option<VideoFormat>,
+    hash: R::Bytes,
+) -> Result<(Details, Bytes), Error> {
+    let input_format = input_format.ok_or(UploadError::NotAnimated)?;
+
+    let process_fut = process(repo, store, storyboard, input_format, hash.clone());
+
+    CancelSafeProcessor::new(hash.as_ref(), storyboard.sprite_path(), process_fut).await
+}
+
+#[tracing::instrument(skip(repo, store, hash))]
+async fn process<R: FullRepo, S: Store + 'static>(
+    repo: &R,
+    store: &S,
+    storyboard: Storyboard,
+    input_format: VideoFormat,
+    hash: R::Bytes,
+) -> Result<(Details, Bytes), Error> {
+    let start = Instant::now();
+    let permit = crate::PROCESS_SEMAPHORE.acquire().await;
+    metrics::histogram!(
+        "pict_rs_process_semaphore_wait_seconds",
+        start.elapsed().as_secs_f64(),
+        "operation" => "storyboard"
+    );
+
+    let identifier = repo.identifier(hash.clone()).await?;
+
+    let (reader, timestamps) = crate::ffmpeg::storyboard(
+        store.clone(),
+        identifier,
+        input_format,
+        storyboard.frames,
+        storyboard.width,
+    )
+    .await?;
+
+    // The sprite is encoded the same way as any other processed image
+    let operations = crate::CONFIG.media.encoding.apply(Vec::new());
+    let (_, args) = crate::processor::build_chain(&operations, storyboard.format.as_ext(), None)?;
+
+    let mut vec = Vec::new();
+    crate::magick::process_image_async_read(reader, args, storyboard.format)?
+        .read_to_end(&mut vec)
+        .await?;
+    let bytes = Bytes::from(vec);
+
+    drop(permit);
+
+    let details = Details::from_bytes(bytes.clone(), storyboard.format.as_hint()).await?;
+    let manifest = Manifest::new(&details, timestamps);
+
+    let identifier = store.save_bytes(bytes.clone()).await?;
+    repo.relate_details(&identifier, &details).await?;
+    save_variant(repo, hash.clone(), storyboard.sprite_path(), &identifier).await?;
+
+    let manifest_identifier = store
+        .save_bytes(Bytes::from(serde_json::to_vec(&manifest)?))
+        .await?;
+    save_variant(
+        repo,
+        hash.clone(),
+        storyboard.manifest_path(),
+        &manifest_identifier,
+    )
+    .await?;
+
+    Ok((details, bytes))
+}
+
+async fn save_variant<R: FullRepo, I: Identifier + 'static>(
+    repo: &R,
+    hash: R::Bytes,
+    path: PathBuf,
+    identifier: &I,
+) -> Result<(), Error> {
+    let variant = path.to_string_lossy().to_string();
+
+    // Either half of a storyboard can be evicted on its own, leaving the other to be replaced
+    if let Some(existing) = repo
+        .variant_identifier::<I>(hash.clone(), variant.clone())
+        .await?
+    {
+        crate::queue::cleanup_identifier(repo, existing).await?;
+    }
+
+    repo.relate_variant_identifier(hash.clone(), variant.clone(), identifier)
+        .await?;
+    repo.mark_accessed(hash, variant, time::OffsetDateTime::now_utc())
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::grid;
+
+    #[test]
+    fn grids_are_close_to_square() {
+        assert_eq!(grid(1), (1, 1));
+        assert_eq!(grid(4), (2, 2));
+        assert_eq!(grid(5), (3, 2));
+        assert_eq!(grid(10), (4, 3));
+        assert_eq!(grid(100), (10, 10));
+    }
+}
@@ -1,4 +1,4 @@
-use actix_rt::{task::JoinHandle, time::Sleep};
+use actix_rt::time::Sleep;
 use actix_web::web::Bytes;
 use futures_util::Stream;
 use std::{
@@ -80,7 +80,6 @@ enum IterStreamState<I, T> {
         buffer: usize,
     },
     Running {
-        handle: JoinHandle<()>,
         receiver: tokio::sync::mpsc::Receiver<T>,
     },
     Pending,
@@ -190,7 +189,9 @@ where
                 let (sender, receiver) = tracing::trace_span!(parent: None, "Create channel")
                     .in_scope(|| tokio::sync::mpsc::channel(buffer));
 
-                let mut handle = actix_rt::task::spawn_blocking(move || {
+                // Items may still be buffered in the channel after this task completes, so the
+                // stream only ends once the receiver sees the sender dropped
+                actix_rt::task::spawn_blocking(move || {
                     let iterator = iterator.into_iter();
 
                     for item in iterator {
@@ -200,34 +201,25 @@ where
                     }
                 });
 
-                if Pin::new(&mut handle).poll(cx).is_ready() {
-                    return Poll::Ready(None);
-                }
-
-                this.state = IterStreamState::Running { handle, receiver };
+                this.state = IterStreamState::Running { receiver };
 
                 self.poll_next(cx)
             }
-            IterStreamState::Running {
-                mut handle,
-                mut receiver,
-            } => match Pin::new(&mut receiver).poll_recv(cx) {
-                Poll::Ready(Some(item)) => {
-                    this.state = IterStreamState::Running { handle, receiver };
-
-                    Poll::Ready(Some(item))
-                }
-                Poll::Ready(None) => Poll::Ready(None),
-                Poll::Pending => {
-                    if Pin::new(&mut handle).poll(cx).is_ready() {
-                        return Poll::Ready(None);
-                    }
+            IterStreamState::Running { mut receiver } => {
+                match Pin::new(&mut receiver).poll_recv(cx) {
+                    Poll::Ready(Some(item)) => {
+                        this.state = IterStreamState::Running { receiver };
 
-                    this.state = IterStreamState::Running { handle, receiver };
+                        Poll::Ready(Some(item))
+                    }
+                    Poll::Ready(None) => Poll::Ready(None),
+                    Poll::Pending => {
+                        this.state = IterStreamState::Running { receiver };
 
-                    Poll::Pending
+                        Poll::Pending
+                    }
                 }
-            },
+            }
             IterStreamState::Pending => panic!("Polled after completion"),
         }
     }
@@ -72,10 +72,17 @@ pub(crate) async fn validate_bytes(
                 crate::magick::convert_bytes_read(bytes, format)?,
             ))),
         )),
-        (FileFormat::Image(ImageFormat::Webp), _) => Ok((
-            ValidInputType::Webp,
+        // exiftool can't be relied on to strip metadata from these formats, so they're always
+        // re-encoded
+        (
+            FileFormat::Image(
+                image_format @ (ImageFormat::Webp | ImageFormat::Avif | ImageFormat::Jxl),
+            ),
+            _,
+        ) => Ok((
+            ValidInputType::from_format(image_format),
             Either::right(Either::left(Either::right(
-                crate::magick::convert_bytes_read(bytes, ImageFormat::Webp)?,
+                crate::magick::convert_bytes_read(bytes, image_format)?,
             ))),
         )),
         (FileFormat::Image(image_format), _) => {